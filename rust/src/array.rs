@@ -0,0 +1,188 @@
+//! Argument types that borrow NumPy buffers instead of copying them.
+//!
+//! C-contiguous `float32` and `float16` arrays are read in place through the
+//! NumPy C API. Plain Python sequences are still accepted, at the cost of one
+//! conversion, so existing callers that pass `List[float]` keep working.
+
+use std::borrow::Cow;
+
+use half::f16;
+use numpy::{PyReadonlyArray1, PyReadonlyArray2, PyUntypedArrayMethods};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::provenance::DimensionMismatchError;
+use crate::search::Scalar;
+
+/// A 1-D embedding passed from Python.
+#[derive(FromPyObject)]
+pub enum VectorArg<'py> {
+    F32(PyReadonlyArray1<'py, f32>),
+    F16(PyReadonlyArray1<'py, f16>),
+    List(Vec<f32>),
+}
+
+impl VectorArg<'_> {
+    /// Returns the vector as `f32`, borrowing when the array already is one.
+    pub fn to_f32(&self) -> Cow<'_, [f32]> {
+        match self {
+            Self::F32(array) => contiguous(array.as_slice(), || array.as_array().to_vec()),
+            Self::F16(array) => Cow::Owned(array.as_array().iter().map(|v| v.to_f32()).collect()),
+            Self::List(values) => Cow::Borrowed(values),
+        }
+    }
+}
+
+/// A 2-D embedding matrix passed from Python, one embedding per row.
+#[derive(FromPyObject)]
+pub enum MatrixArg<'py> {
+    F32(PyReadonlyArray2<'py, f32>),
+    F16(PyReadonlyArray2<'py, f16>),
+    List(Vec<Vec<f32>>),
+}
+
+/// Row-major matrix data borrowed from a [`MatrixArg`].
+pub struct MatrixView<'a, T: Clone> {
+    pub data: Cow<'a, [T]>,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl<T: Scalar> MatrixView<'_, T> {
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        // Only an empty matrix can have zero columns, and `chunks_exact`
+        // rejects a zero chunk size.
+        self.data.chunks_exact(self.cols.max(1))
+    }
+}
+
+/// A borrowed matrix in whichever precision the caller supplied.
+pub enum Matrix<'a> {
+    F32(MatrixView<'a, f32>),
+    F16(MatrixView<'a, f16>),
+}
+
+impl Matrix<'_> {
+    pub fn rows(&self) -> usize {
+        match self {
+            Self::F32(view) => view.rows,
+            Self::F16(view) => view.rows,
+        }
+    }
+
+    pub fn cols(&self) -> usize {
+        match self {
+            Self::F32(view) => view.cols,
+            Self::F16(view) => view.cols,
+        }
+    }
+
+    /// Fails unless the matrix has exactly one row per id.
+    pub fn check_rows(&self, ids: usize) -> PyResult<()> {
+        if self.rows() != ids {
+            return Err(PyValueError::new_err(format!(
+                "got {} chunk ids for {} embeddings",
+                ids,
+                self.rows()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Calls `f` with each row as `f32`, reusing one buffer for `float16` input.
+    pub fn for_each_row(&self, mut f: impl FnMut(&[f32])) {
+        match self {
+            Self::F32(view) => view.rows().for_each(f),
+            Self::F16(view) => {
+                let mut buffer = Vec::with_capacity(view.cols);
+                for row in view.rows() {
+                    buffer.clear();
+                    buffer.extend(row.iter().map(|v| v.to_f32()));
+                    f(&buffer);
+                }
+            }
+        }
+    }
+
+    /// Copies the matrix into an owned row-major `f32` buffer.
+    pub fn to_f32_vec(&self) -> Vec<f32> {
+        match self {
+            Self::F32(view) => view.data.to_vec(),
+            Self::F16(view) => view.data.iter().map(|v| v.to_f32()).collect(),
+        }
+    }
+
+    /// Fails unless every row has `dim` columns.
+    pub fn check_dim(&self, dim: usize) -> PyResult<()> {
+        if self.rows() > 0 && self.cols() != dim {
+            return Err(DimensionMismatchError::new_err(format!(
+                "embeddings have dimension {}, expected {}",
+                self.cols(),
+                dim
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl MatrixArg<'_> {
+    /// Borrows the matrix data, copying only non-contiguous arrays and lists.
+    pub fn view(&self) -> PyResult<Matrix<'_>> {
+        let matrix = match self {
+            Self::F32(array) => {
+                let [rows, cols] = shape(array.shape());
+                Matrix::F32(MatrixView {
+                    data: contiguous(array.as_slice(), || {
+                        array.as_array().iter().copied().collect()
+                    }),
+                    rows,
+                    cols,
+                })
+            }
+            Self::F16(array) => {
+                let [rows, cols] = shape(array.shape());
+                Matrix::F16(MatrixView {
+                    data: contiguous(array.as_slice(), || {
+                        array.as_array().iter().copied().collect()
+                    }),
+                    rows,
+                    cols,
+                })
+            }
+            Self::List(rows) => {
+                let cols = rows.first().map_or(0, Vec::len);
+                if let Some(bad) = rows.iter().position(|row| row.len() != cols) {
+                    return Err(DimensionMismatchError::new_err(format!(
+                        "row {} has dimension {}, expected {}",
+                        bad,
+                        rows[bad].len(),
+                        cols
+                    )));
+                }
+                Matrix::F32(MatrixView {
+                    data: Cow::Owned(rows.concat()),
+                    rows: rows.len(),
+                    cols,
+                })
+            }
+        };
+        if matrix.rows() > 0 && matrix.cols() == 0 {
+            return Err(PyValueError::new_err("embeddings must not be empty"));
+        }
+        Ok(matrix)
+    }
+}
+
+fn shape(dims: &[usize]) -> [usize; 2] {
+    [dims[0], dims[1]]
+}
+
+fn contiguous<'a, T: Clone, E>(
+    slice: Result<&'a [T], E>,
+    copy: impl FnOnce() -> Vec<T>,
+) -> Cow<'a, [T]> {
+    match slice {
+        Ok(values) => Cow::Borrowed(values),
+        Err(_) => Cow::Owned(copy()),
+    }
+}
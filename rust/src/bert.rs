@@ -0,0 +1,145 @@
+//! BERT checkpoints on local disk, shared by the embedder and the reranker.
+//!
+//! A checkpoint is a directory holding `config.json`, `tokenizer.json` and
+//! `model.safetensors`, as published on the Hugging Face hub. Weights are
+//! memory-mapped and run with candle on the CPU.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::Config;
+use pyo3::exceptions::{PyFileNotFoundError, PyValueError};
+use pyo3::prelude::*;
+use serde::Deserialize;
+use tokenizers::{Encoding, PaddingParams, PaddingStrategy, Tokenizer, TruncationParams};
+
+const CONFIG_FILE: &str = "config.json";
+const TOKENIZER_FILE: &str = "tokenizer.json";
+const WEIGHTS_FILE: &str = "model.safetensors";
+
+/// Fails with `FileNotFoundError` unless `dir` holds `name`.
+fn model_file(dir: &Path, name: &str) -> PyResult<PathBuf> {
+    let path = dir.join(name);
+    if !path.is_file() {
+        return Err(PyFileNotFoundError::new_err(format!(
+            "{}: no such file",
+            path.display()
+        )));
+    }
+    Ok(path)
+}
+
+/// A `ValueError` naming the file that could not be used.
+pub fn invalid(path: &Path, e: impl ToString) -> PyErr {
+    PyValueError::new_err(format!("{}: {}", path.display(), e.to_string()))
+}
+
+pub fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> PyResult<T> {
+    let text = fs::read_to_string(path).map_err(|e| invalid(path, e))?;
+    serde_json::from_str(&text).map_err(|e| invalid(path, e))
+}
+
+/// An opened checkpoint whose model has not been built yet.
+pub struct Checkpoint {
+    pub path: PathBuf,
+    pub config: Config,
+    /// Truncates to the requested length and pads batches to their longest
+    /// sequence.
+    pub tokenizer: Tokenizer,
+    pub weights: VarBuilder<'static>,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint in directory `path`, truncating inputs to
+    /// `max_length` tokens or the model's own limit if lower.
+    pub fn open(path: PathBuf, max_length: usize) -> PyResult<Self> {
+        if !path.is_dir() {
+            return Err(PyFileNotFoundError::new_err(format!(
+                "{}: no such directory",
+                path.display()
+            )));
+        }
+        if max_length == 0 {
+            return Err(PyValueError::new_err("max_length must be positive"));
+        }
+        let config: Config = read_json(&model_file(&path, CONFIG_FILE)?)?;
+
+        let tokenizer_path = model_file(&path, TOKENIZER_FILE)?;
+        let mut tokenizer =
+            Tokenizer::from_file(&tokenizer_path).map_err(|e| invalid(&tokenizer_path, e))?;
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: max_length.min(config.max_position_embeddings),
+                ..Default::default()
+            }))
+            .map_err(|e| invalid(&tokenizer_path, e))?;
+        let pad_id = config.pad_token_id as u32;
+        let pad_token = tokenizer
+            .id_to_token(pad_id)
+            .unwrap_or_else(|| "[PAD]".to_string());
+        tokenizer.with_padding(Some(PaddingParams {
+            strategy: PaddingStrategy::BatchLongest,
+            pad_id,
+            pad_token,
+            ..Default::default()
+        }));
+
+        let weights_path = model_file(&path, WEIGHTS_FILE)?;
+        // Half-precision checkpoints are widened to f32 for the CPU kernels
+        let weights = unsafe {
+            VarBuilder::from_mmaped_safetensors(&[&weights_path], DType::F32, &Device::Cpu)
+        }
+        .map_err(|e| invalid(&weights_path, e))?;
+        Ok(Self {
+            path,
+            config,
+            tokenizer,
+            weights,
+        })
+    }
+
+    /// `name` if given, otherwise the checkpoint's directory name.
+    pub fn name(&self, name: Option<String>) -> String {
+        name.unwrap_or_else(|| {
+            self.path
+                .canonicalize()
+                .ok()
+                .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| self.path.display().to_string())
+        })
+    }
+
+    /// A `ValueError` for weights that do not fit the model.
+    pub fn invalid_weights(&self, e: impl ToString) -> PyErr {
+        invalid(&self.path.join(WEIGHTS_FILE), e)
+    }
+}
+
+/// Token ids, token type ids and attention mask of a padded batch, each
+/// `(rows, length)`.
+pub fn batch_tensors(
+    encodings: &[Encoding],
+    device: &Device,
+) -> candle_core::Result<(Tensor, Tensor, Tensor)> {
+    let rows = encodings.len();
+    let len = encodings.first().map_or(0, Encoding::len);
+    let column = |field: fn(&Encoding) -> &[u32]| {
+        let data: Vec<u32> = encodings.iter().flat_map(|e| field(e).to_vec()).collect();
+        Tensor::from_vec(data, (rows, len), device)
+    };
+    Ok((
+        column(Encoding::get_ids)?,
+        column(Encoding::get_type_ids)?,
+        column(Encoding::get_attention_mask)?,
+    ))
+}
+
+/// Input positions ordered by length, so that batches taken in this order
+/// are mostly real tokens rather than padding.
+pub fn by_length(lengths: impl Iterator<Item = usize>) -> Vec<usize> {
+    let mut order: Vec<(usize, usize)> = lengths.enumerate().collect();
+    order.sort_by_key(|&(_, len)| len);
+    order.into_iter().map(|(i, _)| i).collect()
+}
@@ -0,0 +1,274 @@
+//! Lexical retrieval with an inverted index scored by Okapi BM25.
+//!
+//! Vector search blurs exact identifiers such as error codes and function
+//! names; BM25 matches them literally. Results from both can be combined
+//! with [`fuse`](crate::hybrid::fuse).
+
+use std::collections::HashMap;
+
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::filter::{Attributes, Filter};
+use crate::search::TopK;
+
+/// Splits `text` into lowercase terms.
+///
+/// A term is a run of alphanumeric characters or underscores, so
+/// `load_documents` and `ERR_CONN_RESET` stay whole. Terms joined by
+/// underscores are also indexed part by part, so `load_documents` is found
+/// by a query for `documents` too.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    for word in text
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+    {
+        let word = word.to_lowercase();
+        let parts: Vec<&str> = word.split('_').filter(|part| !part.is_empty()).collect();
+        if parts.len() > 1 {
+            terms.extend(parts.iter().map(|part| part.to_string()));
+        }
+        if !parts.is_empty() {
+            terms.push(word);
+        }
+    }
+    terms
+}
+
+struct Document {
+    id: String,
+    /// Distinct terms with their frequency in this document.
+    terms: Vec<(String, u32)>,
+    length: u32,
+    attributes: Attributes,
+}
+
+/// An incrementally maintained BM25 index over chunk texts.
+#[pyclass]
+pub struct Bm25Index {
+    k1: f32,
+    b: f32,
+    /// Document slots; removed documents leave a hole reused by the next add.
+    docs: Vec<Option<Document>>,
+    free: Vec<u32>,
+    /// Term frequency per document slot, for every term. Keyed by slot so a
+    /// document is dropped from a term's postings without scanning them.
+    postings: HashMap<String, HashMap<u32, u32>>,
+    positions: HashMap<String, u32>,
+    total_length: u64,
+}
+
+impl Bm25Index {
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Inserts a document, replacing the existing one if `id` is already indexed.
+    pub fn insert(&mut self, id: String, text: &str, attributes: Attributes) {
+        self.delete(&id);
+        let tokens = tokenize(text);
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        for token in tokens.iter() {
+            *counts.entry(token.clone()).or_default() += 1;
+        }
+        let slot = match self.free.pop() {
+            Some(slot) => slot,
+            None => {
+                self.docs.push(None);
+                (self.docs.len() - 1) as u32
+            }
+        };
+        for (term, tf) in counts.iter() {
+            self.postings
+                .entry(term.clone())
+                .or_default()
+                .insert(slot, *tf);
+        }
+        self.total_length += tokens.len() as u64;
+        self.positions.insert(id.clone(), slot);
+        self.docs[slot as usize] = Some(Document {
+            id,
+            terms: counts.into_iter().collect(),
+            length: tokens.len() as u32,
+            attributes,
+        });
+    }
+
+    /// Removes a document and its postings.
+    pub fn delete(&mut self, id: &str) -> bool {
+        let Some(slot) = self.positions.remove(id) else {
+            return false;
+        };
+        let Some(doc) = self.docs[slot as usize].take() else {
+            return false;
+        };
+        for (term, _) in doc.terms.iter() {
+            if let Some(list) = self.postings.get_mut(term) {
+                list.remove(&slot);
+                if list.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        self.total_length -= doc.length as u64;
+        self.free.push(slot);
+        true
+    }
+
+    /// Returns the best `(slot, score)` pairs for `query`, best first.
+    pub fn top_k(&self, query: &str, k: usize, filter: Option<&Filter>) -> Vec<(usize, f32)> {
+        let n = self.len() as f32;
+        if n == 0.0 {
+            return Vec::new();
+        }
+        let avg_length = self.total_length as f32 / n;
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+
+        let mut scores = vec![0f32; self.docs.len()];
+        let mut touched = Vec::new();
+        for term in terms.iter() {
+            let Some(list) = self.postings.get(term) else {
+                continue;
+            };
+            // The "+1" form of IDF stays positive for very common terms
+            let df = list.len() as f32;
+            let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+            for (&slot, &tf) in list.iter() {
+                let Some(doc) = &self.docs[slot as usize] else {
+                    continue;
+                };
+                let tf = tf as f32;
+                let norm = self.k1 * (1.0 - self.b + self.b * doc.length as f32 / avg_length);
+                if scores[slot as usize] == 0.0 {
+                    touched.push(slot as usize);
+                }
+                scores[slot as usize] += idf * tf * (self.k1 + 1.0) / (tf + norm);
+            }
+        }
+
+        let mut top = TopK::new(k);
+        for slot in touched {
+            let doc = self.docs[slot]
+                .as_ref()
+                .expect("postings point at live documents");
+            if filter.is_some_and(|filter| !filter.matches(&doc.attributes)) {
+                continue;
+            }
+            top.push(slot, scores[slot]);
+        }
+        top.into_sorted_vec()
+    }
+
+    fn with_ids(&self, hits: Vec<(usize, f32)>) -> Vec<(String, f32)> {
+        hits.into_iter()
+            .filter_map(|(slot, score)| Some((self.docs[slot].as_ref()?.id.clone(), score)))
+            .collect()
+    }
+}
+
+#[pymethods]
+impl Bm25Index {
+    /// Creates an empty index. `k1` controls term-frequency saturation and
+    /// `b` how strongly scores are normalized by document length.
+    #[new]
+    #[pyo3(signature = (k1 = 1.2, b = 0.75))]
+    fn new(k1: f32, b: f32) -> PyResult<Self> {
+        if k1 < 0.0 || !(0.0..=1.0).contains(&b) {
+            return Err(PyValueError::new_err(
+                "k1 must be non-negative and b must lie in [0, 1]",
+            ));
+        }
+        Ok(Self {
+            k1,
+            b,
+            docs: Vec::new(),
+            free: Vec::new(),
+            postings: HashMap::new(),
+            positions: HashMap::new(),
+            total_length: 0,
+        })
+    }
+
+    /// Adds or replaces the text indexed for `chunk_id`, along with
+    /// attributes to filter on.
+    #[pyo3(signature = (chunk_id, text, attributes = None))]
+    fn add(&mut self, chunk_id: String, text: &str, attributes: Option<Attributes>) {
+        self.insert(chunk_id, text, attributes.unwrap_or_default());
+    }
+
+    /// Adds or replaces one text per chunk id, with an optional attribute
+    /// dict per text.
+    #[pyo3(signature = (chunk_ids, texts, attributes = None))]
+    fn add_many(
+        &mut self,
+        py: Python<'_>,
+        chunk_ids: Vec<String>,
+        texts: Vec<String>,
+        attributes: Option<Vec<Attributes>>,
+    ) -> PyResult<()> {
+        if texts.len() != chunk_ids.len() {
+            return Err(PyValueError::new_err(format!(
+                "got {} chunk ids for {} texts",
+                chunk_ids.len(),
+                texts.len()
+            )));
+        }
+        let attributes = match attributes {
+            Some(attributes) if attributes.len() != chunk_ids.len() => {
+                return Err(PyValueError::new_err(format!(
+                    "got {} attribute dicts for {} chunk ids",
+                    attributes.len(),
+                    chunk_ids.len()
+                )))
+            }
+            Some(attributes) => attributes,
+            None => vec![Attributes::new(); chunk_ids.len()],
+        };
+        py.allow_threads(|| {
+            for ((id, text), attributes) in chunk_ids.into_iter().zip(texts).zip(attributes) {
+                self.insert(id, &text, attributes);
+            }
+        });
+        Ok(())
+    }
+
+    /// Removes the text indexed for `chunk_id`.
+    fn remove(&mut self, chunk_id: &str) -> PyResult<()> {
+        if !self.delete(chunk_id) {
+            return Err(PyKeyError::new_err(chunk_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Returns up to `k` `(chunk_id, score)` pairs by BM25 score, best
+    /// first. Only chunks sharing at least one term with the query are
+    /// returned, and `filter` skips chunks whose attributes do not match.
+    #[pyo3(signature = (query, k, filter = None))]
+    fn search(
+        &self,
+        py: Python<'_>,
+        query: &str,
+        k: usize,
+        filter: Option<Filter>,
+    ) -> Vec<(String, f32)> {
+        let hits = py.allow_threads(|| self.top_k(query, k, filter.as_ref()));
+        self.with_ids(hits)
+    }
+
+    fn __len__(&self) -> usize {
+        self.len()
+    }
+
+    fn __contains__(&self, chunk_id: &str) -> bool {
+        self.positions.contains_key(chunk_id)
+    }
+}
+
+/// Splits `text` into the terms [`Bm25Index`] indexes.
+#[pyfunction(name = "tokenize")]
+pub fn py_tokenize(text: &str) -> Vec<String> {
+    tokenize(text)
+}
@@ -0,0 +1,297 @@
+//! Byte-pair encoding tokenizers for counting and cutting prompt text.
+//!
+//! Loads the tokenizer of the model that will read the prompt, either a
+//! tiktoken rank file (`cl100k_base.tiktoken`, `o200k_base.tiktoken`, ...)
+//! or a Hugging Face `tokenizer.json`, so context can be fitted to the
+//! model's token budget instead of guessed from its length in characters.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use fancy_regex::Regex;
+use pyo3::exceptions::{PyFileNotFoundError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use tokenizers::Tokenizer;
+
+use crate::bert::invalid;
+
+/// Splits text into words before merging, as in `r50k_base` and `p50k_base`.
+const R50K_PATTERN: &str =
+    r"'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+(?!\S)|\s+";
+
+/// Splits text into words before merging, as in `cl100k_base`.
+const CL100K_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+/// Splits text into words before merging, as in `o200k_base`.
+const O200K_PATTERN: &str = concat!(
+    r"[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]*[\p{Ll}\p{Lm}\p{Lo}\p{M}]+(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]+[\p{Ll}\p{Lm}\p{Lo}\p{M}]*(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n/]*|\s*[\r\n]+|\s+(?!\S)|\s+",
+);
+
+/// The split pattern of the tiktoken encoding named `name`, `cl100k_base`'s
+/// for names it does not know.
+fn tiktoken_pattern(name: &str) -> &'static str {
+    if name.starts_with("o200k") {
+        O200K_PATTERN
+    } else if name.starts_with("r50k") || name.starts_with("p50k") {
+        R50K_PATTERN
+    } else {
+        CL100K_PATTERN
+    }
+}
+
+/// A tiktoken encoding: byte sequences ranked by merge priority.
+struct Tiktoken {
+    ranks: HashMap<Vec<u8>, u32>,
+    tokens: HashMap<u32, Vec<u8>>,
+    pattern: Regex,
+}
+
+impl Tiktoken {
+    /// Reads a rank file, one base64 token and its rank per line.
+    fn load(path: &Path, pattern: &str) -> PyResult<Self> {
+        let text = fs::read_to_string(path).map_err(|e| invalid(path, e))?;
+        let mut ranks = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let parsed = line.split_once(' ').and_then(|(token, rank)| {
+                Some((
+                    STANDARD.decode(token).ok()?,
+                    rank.trim().parse::<u32>().ok()?,
+                ))
+            });
+            let (token, rank) = parsed.ok_or_else(|| {
+                invalid(
+                    path,
+                    format!("line {}: expected a base64 token and a rank", number + 1),
+                )
+            })?;
+            ranks.insert(token, rank);
+        }
+        let pattern = Regex::new(pattern).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let tokens = ranks.iter().map(|(t, &r)| (r, t.clone())).collect();
+        Ok(Self {
+            ranks,
+            tokens,
+            pattern,
+        })
+    }
+
+    /// Start offsets of the parts `piece` merges into, followed by its length.
+    fn merge(&self, piece: &[u8]) -> Vec<usize> {
+        // parts[i].1 is the rank of the token that merging parts i and i + 1 makes
+        let rank = |parts: &[(usize, u32)], i: usize| {
+            parts
+                .get(i + 3)
+                .and_then(|&(end, _)| self.ranks.get(&piece[parts[i].0..end]))
+                .copied()
+                .unwrap_or(u32::MAX)
+        };
+        let mut parts: Vec<(usize, u32)> = (0..=piece.len()).map(|i| (i, u32::MAX)).collect();
+        for i in 0..piece.len().saturating_sub(1) {
+            parts[i].1 = self
+                .ranks
+                .get(&piece[i..i + 2])
+                .copied()
+                .unwrap_or(u32::MAX);
+        }
+        // Lowest rank first, leftmost on ties, as tiktoken merges
+        while let Some((i, _)) = parts[..parts.len() - 1]
+            .iter()
+            .enumerate()
+            .filter(|(_, &(_, r))| r != u32::MAX)
+            .min_by_key(|(_, &(_, r))| r)
+        {
+            if i > 0 {
+                parts[i - 1].1 = rank(&parts, i - 1);
+            }
+            parts[i].1 = rank(&parts, i);
+            parts.remove(i + 1);
+        }
+        parts.into_iter().map(|(start, _)| start).collect()
+    }
+
+    fn encode(&self, text: &str) -> Result<(Vec<u32>, Vec<usize>), String> {
+        let mut ids = Vec::new();
+        let mut ends = Vec::new();
+        for found in self.pattern.find_iter(text) {
+            let found = found.map_err(|e| e.to_string())?;
+            let piece = found.as_str().as_bytes();
+            if let Some(&id) = self.ranks.get(piece) {
+                ids.push(id);
+                ends.push(found.end());
+                continue;
+            }
+            for bounds in self.merge(piece).windows(2) {
+                let bytes = &piece[bounds[0]..bounds[1]];
+                let id = self
+                    .ranks
+                    .get(bytes)
+                    .ok_or_else(|| format!("no token for bytes {:?}", bytes))?;
+                ids.push(*id);
+                ends.push(found.start() + bounds[1]);
+            }
+        }
+        Ok((ids, ends))
+    }
+
+    fn decode(&self, ids: &[u32]) -> Result<String, String> {
+        let mut bytes = Vec::new();
+        for id in ids {
+            let token = self
+                .tokens
+                .get(id)
+                .ok_or_else(|| format!("no token {}", id))?;
+            bytes.extend_from_slice(token);
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+enum Encoding {
+    Tiktoken(Box<Tiktoken>),
+    HuggingFace(Box<Tokenizer>),
+}
+
+/// A tokenizer loaded from a tiktoken or Hugging Face tokenizer file.
+#[pyclass]
+pub struct BpeTokenizer {
+    name: String,
+    encoding: Encoding,
+}
+
+impl BpeTokenizer {
+    /// Token ids of `text`, and the byte offset each token ends at.
+    fn tokens(&self, text: &str) -> Result<(Vec<u32>, Vec<usize>), String> {
+        match &self.encoding {
+            Encoding::Tiktoken(tiktoken) => tiktoken.encode(text),
+            Encoding::HuggingFace(tokenizer) => {
+                let encoding = tokenizer.encode(text, false).map_err(|e| e.to_string())?;
+                let ends = encoding.get_offsets().iter().map(|&(_, end)| end).collect();
+                Ok((encoding.get_ids().to_vec(), ends))
+            }
+        }
+    }
+
+    /// `text` cut after its first `max_tokens` tokens.
+    fn truncated<'t>(&self, text: &'t str, max_tokens: usize) -> Result<&'t str, String> {
+        let (_, ends) = self.tokens(text)?;
+        if ends.len() <= max_tokens {
+            return Ok(text);
+        }
+        // Tokens of a split character can end inside it
+        let mut end = ends[..max_tokens].iter().copied().max().unwrap_or(0);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        Ok(&text[..end])
+    }
+}
+
+#[pymethods]
+impl BpeTokenizer {
+    /// Loads the tokenizer at `path`: a Hugging Face `tokenizer.json`, or a
+    /// directory holding one, or otherwise a tiktoken rank file.
+    ///
+    /// tiktoken files do not record how text is split into words before
+    /// merging, so `pattern` gives the regular expression, by default that
+    /// of the encoding the file is named after (`cl100k_base` if unknown).
+    /// Special tokens are encoded as ordinary text.
+    #[new]
+    #[pyo3(signature = (path, pattern = None, name = None))]
+    fn new(path: PathBuf, pattern: Option<&str>, name: Option<String>) -> PyResult<Self> {
+        let path = if path.is_dir() {
+            path.join("tokenizer.json")
+        } else {
+            path
+        };
+        if !path.is_file() {
+            return Err(PyFileNotFoundError::new_err(format!(
+                "{}: no such file",
+                path.display()
+            )));
+        }
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let encoding = if path.extension().is_some_and(|e| e == "json") {
+            if pattern.is_some() {
+                return Err(PyValueError::new_err(
+                    "pattern only applies to tiktoken files",
+                ));
+            }
+            let mut tokenizer = Tokenizer::from_file(&path).map_err(|e| invalid(&path, e))?;
+            tokenizer.with_padding(None);
+            tokenizer
+                .with_truncation(None)
+                .map_err(|e| invalid(&path, e))?;
+            Encoding::HuggingFace(Box::new(tokenizer))
+        } else {
+            let pattern = pattern.unwrap_or_else(|| tiktoken_pattern(&stem));
+            Encoding::Tiktoken(Box::new(Tiktoken::load(&path, pattern)?))
+        };
+        // tokenizer.json is named after the model directory it sits in
+        let name = name.unwrap_or_else(|| match (&encoding, path.parent()) {
+            (Encoding::HuggingFace(_), Some(dir)) => dir
+                .canonicalize()
+                .ok()
+                .and_then(|d| d.file_name().map(|n| n.to_string_lossy().into_owned()))
+                .unwrap_or(stem.clone()),
+            _ => stem.clone(),
+        });
+        Ok(Self { name, encoding })
+    }
+
+    #[getter]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Token ids of `text`.
+    fn encode(&self, py: Python<'_>, text: &str) -> PyResult<Vec<u32>> {
+        py.allow_threads(|| self.tokens(text))
+            .map(|(ids, _)| ids)
+            .map_err(PyRuntimeError::new_err)
+    }
+
+    /// The text of token ids `ids`.
+    fn decode(&self, ids: Vec<u32>) -> PyResult<String> {
+        match &self.encoding {
+            Encoding::Tiktoken(tiktoken) => tiktoken.decode(&ids),
+            Encoding::HuggingFace(tokenizer) => {
+                tokenizer.decode(&ids, false).map_err(|e| e.to_string())
+            }
+        }
+        .map_err(PyValueError::new_err)
+    }
+
+    /// Number of tokens in `text`.
+    fn count(&self, py: Python<'_>, text: &str) -> PyResult<usize> {
+        py.allow_threads(|| self.tokens(text))
+            .map(|(ids, _)| ids.len())
+            .map_err(PyRuntimeError::new_err)
+    }
+
+    /// `text` cut after its first `max_tokens` tokens.
+    /// `text` itself if it fits.
+    fn truncate(&self, py: Python<'_>, text: &str, max_tokens: usize) -> PyResult<String> {
+        py.allow_threads(|| self.truncated(text, max_tokens))
+            .map(str::to_string)
+            .map_err(PyRuntimeError::new_err)
+    }
+
+    fn __repr__(&self) -> String {
+        let kind = match self.encoding {
+            Encoding::Tiktoken(_) => "tiktoken",
+            Encoding::HuggingFace(_) => "huggingface",
+        };
+        format!("BpeTokenizer(name={:?}, format={:?})", self.name, kind)
+    }
+}
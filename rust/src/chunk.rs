@@ -0,0 +1,293 @@
+//! Deterministic text chunking.
+//!
+//! Every strategy first cuts the text into small contiguous units (chars,
+//! sentences, separator-delimited pieces or tokens) and then packs
+//! consecutive units into chunks of at most `size`, measured in characters,
+//! or in approximate tokens (see [`token_units`]) for the token-budget
+//! strategy. Consecutive chunks share up to
+//! `overlap` of trailing units. Chunks are reported as UTF-8 byte ranges into
+//! the input, trimmed of surrounding whitespace, so the same text always
+//! yields the same chunks.
+
+use std::ops::Range;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// Separators tried in order by the recursive strategy.
+pub const DEFAULT_SEPARATORS: [&str; 5] = ["\n\n", "\n", ". ", " ", ""];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Windows of `size` characters.
+    Fixed,
+    /// Whole sentences packed up to `size` characters.
+    Sentence,
+    /// Split on the coarsest separator that yields pieces of at most `size`
+    /// characters, then pack the pieces.
+    Recursive,
+    /// Approximate tokens, as cut by [`token_units`], packed up to `size`.
+    Tokens,
+}
+
+impl Strategy {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "fixed" => Ok(Self::Fixed),
+            "sentence" => Ok(Self::Sentence),
+            "recursive" => Ok(Self::Recursive),
+            "tokens" => Ok(Self::Tokens),
+            other => Err(PyValueError::new_err(format!(
+                "unknown chunking strategy {:?}, expected one of fixed, sentence, recursive, tokens",
+                other
+            ))),
+        }
+    }
+}
+
+fn char_len(text: &str, span: &Range<usize>) -> usize {
+    text[span.clone()].chars().count()
+}
+
+/// One unit per character.
+fn char_units(text: &str) -> Vec<Range<usize>> {
+    text.char_indices()
+        .map(|(i, c)| i..i + c.len_utf8())
+        .collect()
+}
+
+/// Sentences end after `.`, `!` or `?` followed by whitespace, and at blank
+/// lines. Trailing whitespace stays with the sentence it follows.
+fn sentence_units(text: &str) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut units = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let end_mark = matches!(bytes[i], b'.' | b'!' | b'?')
+            && bytes.get(i + 1).is_some_and(u8::is_ascii_whitespace);
+        let blank_line = bytes[i] == b'\n' && bytes.get(i + 1) == Some(&b'\n');
+        if end_mark || blank_line {
+            let mut end = i + 1;
+            while end < bytes.len() && bytes[end].is_ascii_whitespace() {
+                end += 1;
+            }
+            units.push(start..end);
+            start = end;
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+    if start < bytes.len() {
+        units.push(start..bytes.len());
+    }
+    units
+}
+
+/// Word-piece approximation of a subword tokenizer: each run of
+/// alphanumeric characters and each other non-space character is one token.
+/// Whitespace is attached to the token before it. Real subword tokenizers
+/// split long or rare words further, so they count more tokens than this.
+pub fn token_units(text: &str) -> Vec<Range<usize>> {
+    let mut units: Vec<Range<usize>> = Vec::new();
+    let mut in_word = false;
+    for (i, c) in text.char_indices() {
+        let end = i + c.len_utf8();
+        match units.last_mut() {
+            // Leading whitespace opens the first token instead of counting alone
+            Some(last) if c.is_whitespace() || (in_word && c.is_alphanumeric()) => last.end = end,
+            Some(last) if last.end == i && text[last.clone()].trim().is_empty() => last.end = end,
+            _ => units.push(i..end),
+        }
+        in_word = c.is_alphanumeric();
+    }
+    units
+}
+
+/// Splits `span` on the first separator that occurs in it and recurses into
+/// pieces that are still longer than `size` characters.
+fn recursive_units(
+    text: &str,
+    span: Range<usize>,
+    separators: &[String],
+    size: usize,
+    out: &mut Vec<Range<usize>>,
+) {
+    if char_len(text, &span) <= size {
+        out.push(span);
+        return;
+    }
+    let Some((separator, rest)) = separators.split_first() else {
+        out.extend(
+            char_units(&text[span.clone()])
+                .into_iter()
+                .map(|r| r.start + span.start..r.end + span.start),
+        );
+        return;
+    };
+    if separator.is_empty() {
+        recursive_units(text, span, &[], size, out);
+        return;
+    }
+    let slice = &text[span.clone()];
+    if !slice.contains(separator.as_str()) {
+        recursive_units(text, span, rest, size, out);
+        return;
+    }
+    // Each piece keeps the separator that ends it
+    let mut start = 0;
+    for (i, _) in slice.match_indices(separator.as_str()) {
+        let end = i + separator.len();
+        recursive_units(text, span.start + start..span.start + end, rest, size, out);
+        start = end;
+    }
+    if start < slice.len() {
+        recursive_units(text, span.start + start..span.end, rest, size, out);
+    }
+}
+
+/// Packs consecutive units into chunks whose `measure` stays within `size`,
+/// starting each chunk with up to `overlap` of the previous chunk's units.
+/// A single unit larger than `size` becomes a chunk of its own.
+pub(crate) fn pack(
+    units: &[Range<usize>],
+    measure: impl Fn(&Range<usize>) -> usize,
+    size: usize,
+    overlap: usize,
+) -> Vec<Range<usize>> {
+    let weights: Vec<usize> = units.iter().map(measure).collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < units.len() {
+        let mut end = start;
+        let mut total = 0;
+        while end < units.len() && (end == start || total + weights[end] <= size) {
+            total += weights[end];
+            end += 1;
+        }
+        chunks.push(units[start].start..units[end - 1].end);
+        if end == units.len() {
+            break;
+        }
+        // Walk back from the end while the carried-over units fit in `overlap`
+        let mut next = end;
+        let mut carried = 0;
+        while next > start + 1 && carried + weights[next - 1] <= overlap {
+            carried += weights[next - 1];
+            next -= 1;
+        }
+        start = next;
+    }
+    chunks
+}
+
+/// Shrinks `span` to exclude leading and trailing whitespace.
+pub(crate) fn trim(text: &str, span: Range<usize>) -> Range<usize> {
+    let slice = &text[span.clone()];
+    let start = span.start + (slice.len() - slice.trim_start().len());
+    let end = span.end - (slice.len() - slice.trim_end().len());
+    start..end.max(start)
+}
+
+/// Maps byte offsets in a text to 1-based line numbers.
+pub(crate) struct Lines(Vec<usize>);
+
+impl Lines {
+    pub fn new(text: &str) -> Self {
+        Self(
+            std::iter::once(0)
+                .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+                .collect(),
+        )
+    }
+
+    /// The 1-based, inclusive line range covered by a non-empty `span`.
+    pub fn range(&self, span: &Range<usize>) -> (usize, usize) {
+        let line_of = |byte: usize| self.0.partition_point(|&start| start <= byte);
+        (line_of(span.start), line_of(span.end - 1))
+    }
+}
+
+/// Chunks `text` and returns the byte range of each non-empty chunk.
+pub fn chunk_spans(
+    text: &str,
+    strategy: Strategy,
+    size: usize,
+    overlap: usize,
+    separators: &[String],
+) -> Vec<Range<usize>> {
+    let chars = |span: &Range<usize>| char_len(text, span);
+    let spans = match strategy {
+        Strategy::Fixed => pack(&char_units(text), chars, size, overlap),
+        Strategy::Sentence => {
+            // Sentences longer than a chunk are cut into character windows
+            let mut units = Vec::new();
+            for sentence in sentence_units(text) {
+                if chars(&sentence) <= size {
+                    units.push(sentence);
+                } else {
+                    units.extend(
+                        char_units(&text[sentence.clone()])
+                            .into_iter()
+                            .map(|r| r.start + sentence.start..r.end + sentence.start),
+                    );
+                }
+            }
+            pack(&units, chars, size, overlap)
+        }
+        Strategy::Recursive => {
+            let mut units = Vec::new();
+            recursive_units(text, 0..text.len(), separators, size, &mut units);
+            pack(&units, chars, size, overlap)
+        }
+        Strategy::Tokens => pack(&token_units(text), |_| 1, size, overlap),
+    };
+    spans
+        .into_iter()
+        .map(|span| trim(text, span))
+        .filter(|span| !span.is_empty())
+        .collect()
+}
+
+/// Splits `text` into chunks and returns `(start, end, chunk)` triples,
+/// where `start..end` is the chunk's UTF-8 byte range in `text`.
+///
+/// `strategy` is one of:
+///
+/// - `"fixed"`: windows of `size` characters;
+/// - `"sentence"`: whole sentences packed up to `size` characters;
+/// - `"recursive"`: pieces split on the first of `separators` that makes them
+///   fit in `size` characters (paragraphs, then lines, sentences, words),
+///   packed back together up to `size`;
+/// - `"tokens"`: up to `size` approximate tokens, counting each word and
+///   each punctuation mark as one. Not the embedding model's tokenizer,
+///   which usually counts more, so leave headroom below its input limit.
+///
+/// Consecutive chunks overlap by up to `overlap` characters (tokens for
+/// `"tokens"`). The output depends only on the arguments.
+#[pyfunction]
+#[pyo3(signature = (text, strategy = "recursive", size = 1000, overlap = 200, separators = None))]
+pub fn chunk_text(
+    py: Python<'_>,
+    text: &str,
+    strategy: &str,
+    size: usize,
+    overlap: usize,
+    separators: Option<Vec<String>>,
+) -> PyResult<Vec<(usize, usize, String)>> {
+    let strategy = Strategy::parse(strategy)?;
+    if size == 0 {
+        return Err(PyValueError::new_err("size must be positive"));
+    }
+    if overlap >= size {
+        return Err(PyValueError::new_err("overlap must be smaller than size"));
+    }
+    let separators =
+        separators.unwrap_or_else(|| DEFAULT_SEPARATORS.iter().map(|s| s.to_string()).collect());
+    let spans = py.allow_threads(|| chunk_spans(text, strategy, size, overlap, &separators));
+    Ok(spans
+        .into_iter()
+        .map(|span| (span.start, span.end, text[span].to_string()))
+        .collect())
+}
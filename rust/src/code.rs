@@ -0,0 +1,341 @@
+//! Source-code chunking at function and class boundaries.
+//!
+//! Sources are parsed with tree-sitter. Every definition (function, method,
+//! class, struct, impl block, ...) becomes a chunk of its own, together with
+//! the comments, decorators and attributes directly above it, and is labelled
+//! with its qualified symbol name such as `Retriever.search`. Code between
+//! definitions (imports, constants, module-level statements) is packed into
+//! chunks labelled with the enclosing symbol, if any. A definition longer
+//! than `size` characters is split into its nested definitions; whatever is
+//! still too long is cut at line boundaries.
+
+use std::ops::Range;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use tree_sitter::{Node, Parser};
+
+use crate::chunk::{pack, trim, Lines};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    Python,
+    Rust,
+    JavaScript,
+    Go,
+}
+
+impl Language {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "python" => Ok(Self::Python),
+            "rust" => Ok(Self::Rust),
+            "javascript" => Ok(Self::JavaScript),
+            "go" => Ok(Self::Go),
+            other => Err(PyValueError::new_err(format!(
+                "unsupported language {:?}, expected one of python, rust, javascript, go",
+                other
+            ))),
+        }
+    }
+
+    /// The language of a source file with extension `extension`, if supported.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "py" => Some(Self::Python),
+            "rs" => Some(Self::Rust),
+            "js" | "mjs" | "jsx" => Some(Self::JavaScript),
+            "go" => Some(Self::Go),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Python => "python",
+            Self::Rust => "rust",
+            Self::JavaScript => "javascript",
+            Self::Go => "go",
+        }
+    }
+
+    fn grammar(self) -> tree_sitter::Language {
+        match self {
+            Self::Python => tree_sitter_python::LANGUAGE.into(),
+            Self::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Self::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            Self::Go => tree_sitter_go::LANGUAGE.into(),
+        }
+    }
+
+    /// Node kinds that start a chunk of their own.
+    fn is_definition(self, node: &Node) -> bool {
+        match self {
+            Self::Python => matches!(
+                node.kind(),
+                "function_definition" | "class_definition" | "decorated_definition"
+            ),
+            Self::Rust => {
+                matches!(
+                    node.kind(),
+                    "function_item"
+                        | "impl_item"
+                        | "trait_item"
+                        | "struct_item"
+                        | "enum_item"
+                        | "union_item"
+                        | "macro_definition"
+                ) || (node.kind() == "mod_item" && node.child_by_field_name("body").is_some())
+            }
+            Self::JavaScript => match node.kind() {
+                "function_declaration"
+                | "generator_function_declaration"
+                | "class_declaration"
+                | "method_definition" => true,
+                "export_statement" => node
+                    .child_by_field_name("declaration")
+                    .is_some_and(|declaration| self.is_definition(&declaration)),
+                // `const f = () => ...` and `const C = class { ... }`
+                "lexical_declaration" | "variable_declaration" => assigned_function(node).is_some(),
+                _ => false,
+            },
+            Self::Go => matches!(
+                node.kind(),
+                "function_declaration" | "method_declaration" | "type_declaration"
+            ),
+        }
+    }
+
+    /// The definition wrapped by a decorator or export, or `node` itself.
+    fn unwrap<'t>(self, node: Node<'t>) -> Node<'t> {
+        let inner = match (self, node.kind()) {
+            (Self::Python, "decorated_definition") => node.child_by_field_name("definition"),
+            (Self::JavaScript, "export_statement") => node.child_by_field_name("declaration"),
+            _ => None,
+        };
+        inner.unwrap_or(node)
+    }
+
+    /// Comments and attributes that belong to the definition after them.
+    fn is_leading(self, node: &Node) -> bool {
+        match self {
+            Self::Rust => matches!(
+                node.kind(),
+                "line_comment" | "block_comment" | "attribute_item"
+            ),
+            _ => node.kind() == "comment",
+        }
+    }
+
+    /// The unqualified name of a definition.
+    fn symbol_name(self, node: &Node, source: &str) -> Option<String> {
+        let text = |node: Node| node.utf8_text(source.as_bytes()).ok().map(str::to_string);
+        let node = self.unwrap(*node);
+        match (self, node.kind()) {
+            (Self::Rust, "impl_item") => {
+                let ty = text(node.child_by_field_name("type")?)?;
+                match node.child_by_field_name("trait") {
+                    Some(trait_) => Some(format!("<{} as {}>", ty, text(trait_)?)),
+                    None => Some(ty),
+                }
+            }
+            (Self::JavaScript, "lexical_declaration" | "variable_declaration") => {
+                text(assigned_function(&node)?.child_by_field_name("name")?)
+            }
+            (Self::Go, "method_declaration") => {
+                let name = text(node.child_by_field_name("name")?)?;
+                let receiver = node
+                    .child_by_field_name("receiver")?
+                    .named_child(0)?
+                    .child_by_field_name("type")?;
+                let receiver = text(receiver)?;
+                let receiver = receiver.trim_start_matches('*');
+                let receiver = receiver.split('[').next().unwrap_or(receiver);
+                Some(format!("{}.{}", receiver, name))
+            }
+            (Self::Go, "type_declaration") => {
+                text(node.named_child(0)?.child_by_field_name("name")?)
+            }
+            _ => text(node.child_by_field_name("name")?),
+        }
+    }
+}
+
+/// The declarator of a JavaScript declaration that binds a single function
+/// or class expression.
+fn assigned_function<'a>(node: &Node<'a>) -> Option<Node<'a>> {
+    if node.named_child_count() != 1 {
+        return None;
+    }
+    let declarator = node.named_child(0)?;
+    let value = declarator.child_by_field_name("value")?;
+    matches!(
+        value.kind(),
+        "arrow_function" | "function_expression" | "generator_function" | "class"
+    )
+    .then_some(declarator)
+}
+
+/// A byte range of the source attributed to a symbol, if any.
+struct Segment {
+    span: Range<usize>,
+    symbol: Option<String>,
+}
+
+struct Chunker<'a> {
+    language: Language,
+    source: &'a str,
+    size: usize,
+    segments: Vec<Segment>,
+}
+
+impl Chunker<'_> {
+    fn chars(&self, span: &Range<usize>) -> usize {
+        self.source[span.clone()].chars().count()
+    }
+
+    /// The definitions below `node` that are not nested in another
+    /// definition, each with its leading comments, in source order.
+    fn definitions<'t>(&self, node: Node<'t>, out: &mut Vec<(Range<usize>, Node<'t>)>) {
+        let mut cursor = node.walk();
+        let mut lead: Option<usize> = None;
+        for child in node.children(&mut cursor) {
+            if self.language.is_leading(&child) {
+                lead.get_or_insert(child.start_byte());
+            } else if self.language.is_definition(&child) {
+                let start = lead.take().unwrap_or(child.start_byte());
+                out.push((start..child.end_byte(), child));
+            } else {
+                lead = None;
+                self.definitions(child, out);
+            }
+        }
+    }
+
+    /// Splits `span`, the extent of `node`, into segments.
+    fn split(&mut self, node: Node<'_>, span: Range<usize>, scope: Option<&str>) {
+        let mut definitions = Vec::new();
+        self.definitions(node, &mut definitions);
+        let mut cursor = span.start;
+        for (def_span, def) in definitions {
+            self.push(cursor..def_span.start, scope);
+            let name = self.language.symbol_name(&def, self.source);
+            let symbol = match (scope, name) {
+                (Some(scope), Some(name)) => Some(format!("{}.{}", scope, name)),
+                (scope, name) => name.or(scope.map(str::to_string)),
+            };
+            // Decorators and `export` stay with the definition's first chunk
+            let def = self.language.unwrap(def);
+            let mut nested = Vec::new();
+            self.definitions(def, &mut nested);
+            if self.chars(&def_span) > self.size && !nested.is_empty() {
+                self.split(def, def_span.clone(), symbol.as_deref());
+            } else {
+                self.push(def_span.clone(), symbol.as_deref());
+            }
+            cursor = def_span.end;
+        }
+        self.push(cursor..span.end, scope);
+    }
+
+    fn push(&mut self, span: Range<usize>, symbol: Option<&str>) {
+        let span = trim(self.source, span);
+        if span.is_empty() {
+            return;
+        }
+        self.segments.push(Segment {
+            span,
+            symbol: symbol.map(str::to_string),
+        });
+    }
+}
+
+/// Cuts `span` into runs of whole lines of at most `size` characters.
+/// A single line longer than `size` is kept whole.
+fn split_lines(source: &str, span: Range<usize>, size: usize) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = span.start;
+    for (i, _) in source[span.clone()].match_indices('\n') {
+        lines.push(start..span.start + i + 1);
+        start = span.start + i + 1;
+    }
+    if start < span.end {
+        lines.push(start..span.end);
+    }
+    pack(&lines, |line| source[line.clone()].chars().count(), size, 0)
+}
+
+/// A chunk's byte range, symbol and 1-based inclusive line range.
+pub type CodeChunk = (Range<usize>, Option<String>, usize, usize);
+
+/// Chunks `source` written in `language`.
+pub fn code_chunks(
+    source: &str,
+    language: Language,
+    size: usize,
+) -> Result<Vec<CodeChunk>, String> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(&language.grammar())
+        .map_err(|e| e.to_string())?;
+    let tree = parser
+        .parse(source, None)
+        .ok_or_else(|| "failed to parse source".to_string())?;
+    let mut chunker = Chunker {
+        language,
+        source,
+        size,
+        segments: Vec::new(),
+    };
+    chunker.split(tree.root_node(), 0..source.len(), None);
+
+    let lines = Lines::new(source);
+    let mut chunks = Vec::new();
+    for segment in chunker.segments {
+        for span in split_lines(source, segment.span, size) {
+            let span = trim(source, span);
+            if span.is_empty() {
+                continue;
+            }
+            let (start_line, end_line) = lines.range(&span);
+            chunks.push((span, segment.symbol.clone(), start_line, end_line));
+        }
+    }
+    Ok(chunks)
+}
+
+/// `(start, end, chunk, symbol, start_line, end_line)` as returned to Python.
+type CodeTuple = (usize, usize, String, Option<String>, usize, usize);
+
+/// Splits source code into chunks at function and class boundaries.
+///
+/// `language` is one of `"python"`, `"rust"`, `"javascript"` or `"go"`.
+/// Returns `(start, end, chunk, symbol, start_line, end_line)` tuples, where
+/// `start..end` is the chunk's UTF-8 byte range in `source`, `symbol` the
+/// qualified name of the enclosing definition (`None` for module-level code)
+/// and the line range is 1-based and inclusive. Chunks hold at most `size`
+/// characters unless a single line is longer. The output depends only on
+/// the arguments.
+#[pyfunction]
+#[pyo3(signature = (source, language, size = 1000))]
+pub fn chunk_code(
+    py: Python<'_>,
+    source: &str,
+    language: &str,
+    size: usize,
+) -> PyResult<Vec<CodeTuple>> {
+    let language = Language::parse(language)?;
+    if size == 0 {
+        return Err(PyValueError::new_err("size must be positive"));
+    }
+    let chunks = py
+        .allow_threads(|| code_chunks(source, language, size))
+        .map_err(PyValueError::new_err)?;
+    Ok(chunks
+        .into_iter()
+        .map(|(span, symbol, start_line, end_line)| {
+            let text = source[span.clone()].to_string();
+            (span.start, span.end, text, symbol, start_line, end_line)
+        })
+        .collect())
+}
@@ -0,0 +1,510 @@
+//! Conversion of HTML, DOCX and EPUB documents to markdown text.
+//!
+//! Headings become `#` headings, list items `- ` lines, preformatted text
+//! fenced code blocks and tables pipe tables, so the converted text can be
+//! chunked along its heading structure by
+//! [`markdown_chunks`](crate::markdown::markdown_chunks). Everything else
+//! is reduced to paragraphs of plain text.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+
+use ego_tree::NodeRef;
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use scraper::{ElementRef, Html, Node, Selector};
+use zip::ZipArchive;
+
+use crate::ingest::decode;
+
+/// Elements whose content is never part of the document text.
+const SKIPPED_ELEMENTS: [&str; 10] = [
+    "head", "script", "style", "noscript", "template", "svg", "nav", "header", "footer", "button",
+];
+
+/// Elements that start a block of their own.
+const BLOCK_ELEMENTS: [&str; 16] = [
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "aside",
+    "blockquote",
+    "ul",
+    "ol",
+    "dl",
+    "dt",
+    "dd",
+    "figure",
+    "figcaption",
+    "hr",
+    "address",
+];
+
+/// Builds markdown text block by block.
+#[derive(Default)]
+struct Markdown {
+    out: String,
+}
+
+impl Markdown {
+    /// Ends the current block with a blank line.
+    fn block_break(&mut self) {
+        let trimmed = self.out.trim_end_matches([' ', '\t', '\n']).len();
+        self.out.truncate(trimmed);
+        if !self.out.is_empty() {
+            self.out.push_str("\n\n");
+        }
+    }
+
+    /// Ends the current line.
+    fn line_break(&mut self) {
+        let trimmed = self.out.trim_end_matches([' ', '\t']).len();
+        self.out.truncate(trimmed);
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+
+    /// Appends inline text, collapsing runs of whitespace.
+    fn text(&mut self, text: &str) {
+        for (i, word) in text.split_whitespace().enumerate() {
+            let at_line_start = self.out.is_empty() || self.out.ends_with('\n');
+            let spaced = i > 0 || text.starts_with(char::is_whitespace);
+            if spaced && !at_line_start && !self.out.ends_with(' ') {
+                self.out.push(' ');
+            }
+            self.out.push_str(word);
+        }
+        if text.ends_with(char::is_whitespace) && !self.out.ends_with(['\n', ' ']) {
+            self.out.push(' ');
+        }
+    }
+
+    fn heading(&mut self, level: usize, title: &str) {
+        let title = collapse(title);
+        if title.is_empty() {
+            return;
+        }
+        self.block_break();
+        self.out.push_str(&"#".repeat(level.clamp(1, 6)));
+        self.out.push(' ');
+        self.out.push_str(&title);
+        self.block_break();
+    }
+
+    fn code_block(&mut self, code: &str) {
+        let code = code.trim_matches('\n');
+        if code.trim().is_empty() {
+            return;
+        }
+        self.block_break();
+        self.out.push_str("```\n");
+        self.out.push_str(code);
+        self.out.push_str("\n```");
+        self.block_break();
+    }
+
+    fn table(&mut self, rows: &[Vec<String>]) {
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+        self.block_break();
+        for (i, row) in rows.iter().enumerate() {
+            let cells: Vec<String> = (0..columns)
+                .map(|c| {
+                    row.get(c)
+                        .map(|cell| cell.replace('|', "\\|"))
+                        .unwrap_or_default()
+                })
+                .collect();
+            self.out.push_str(&format!("| {} |\n", cells.join(" | ")));
+            if i == 0 {
+                self.out.push_str(&format!("|{}\n", "---|".repeat(columns)));
+            }
+        }
+        self.block_break();
+    }
+
+    fn finish(mut self) -> String {
+        self.block_break();
+        self.out.trim_end().to_string()
+    }
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn heading_level(name: &str) -> Option<usize> {
+    match name.as_bytes() {
+        [b'h', level @ b'1'..=b'6'] => Some((level - b'0') as usize),
+        _ => None,
+    }
+}
+
+fn element_text(element: ElementRef<'_>) -> String {
+    element.text().collect()
+}
+
+fn html_rows(table: ElementRef<'_>) -> Vec<Vec<String>> {
+    let rows = Selector::parse("tr").expect("valid selector");
+    let cells = Selector::parse("th, td").expect("valid selector");
+    table
+        .select(&rows)
+        .map(|row| {
+            row.select(&cells)
+                .map(|cell| collapse(&element_text(cell)))
+                .collect()
+        })
+        .collect()
+}
+
+fn convert_html(node: NodeRef<'_, Node>, list_depth: usize, md: &mut Markdown) {
+    match node.value() {
+        Node::Text(text) => md.text(text),
+        Node::Element(element) => {
+            let name = element.name();
+            let Some(element_ref) = ElementRef::wrap(node) else {
+                return;
+            };
+            if SKIPPED_ELEMENTS.contains(&name) {
+                return;
+            }
+            if let Some(level) = heading_level(name) {
+                md.heading(level, &element_text(element_ref));
+                return;
+            }
+            match name {
+                "pre" => md.code_block(&element_text(element_ref)),
+                "table" => md.table(&html_rows(element_ref)),
+                "br" => md.line_break(),
+                "li" => {
+                    md.line_break();
+                    md.out.push_str(&"  ".repeat(list_depth.saturating_sub(1)));
+                    md.out.push_str("- ");
+                    for child in node.children() {
+                        convert_html(child, list_depth, md);
+                    }
+                    md.line_break();
+                }
+                "ul" | "ol" => {
+                    if list_depth == 0 {
+                        md.block_break();
+                    }
+                    for child in node.children() {
+                        convert_html(child, list_depth + 1, md);
+                    }
+                    if list_depth == 0 {
+                        md.block_break();
+                    }
+                }
+                _ if BLOCK_ELEMENTS.contains(&name) && list_depth == 0 => {
+                    md.block_break();
+                    for child in node.children() {
+                        convert_html(child, list_depth, md);
+                    }
+                    md.block_break();
+                }
+                _ => {
+                    for child in node.children() {
+                        convert_html(child, list_depth, md);
+                    }
+                }
+            }
+        }
+        _ => {
+            for child in node.children() {
+                convert_html(child, list_depth, md);
+            }
+        }
+    }
+}
+
+/// Converts an HTML page to markdown.
+///
+/// Confluence exports keep the page body in `#main-content`; when present,
+/// only that element is converted, which drops the surrounding navigation.
+pub fn html_to_markdown(html: &str) -> String {
+    let document = Html::parse_document(html);
+    let main = Selector::parse("#main-content").expect("valid selector");
+    let mut md = Markdown::default();
+    if let Some(title) = document
+        .select(&Selector::parse("#title-text").expect("valid selector"))
+        .next()
+    {
+        md.heading(1, &element_text(title));
+    }
+    match document.select(&main).next() {
+        Some(content) => convert_html(*content, 0, &mut md),
+        None => convert_html(*document.root_element(), 0, &mut md),
+    }
+    md.finish()
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<String, String> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|e| format!("{}: {}", name, e))?;
+    let mut text = String::new();
+    entry
+        .read_to_string(&mut text)
+        .map_err(|e| format!("{}: {}", name, e))?;
+    Ok(text)
+}
+
+fn attribute(element: &BytesStart<'_>, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.into_owned())
+}
+
+/// Heading level of a Word paragraph style such as `Heading2` or `Title`.
+fn style_heading_level(style: &str) -> Option<usize> {
+    if style == "Title" {
+        return Some(1);
+    }
+    style
+        .strip_prefix("Heading")
+        .and_then(|level| level.parse().ok())
+}
+
+/// Converts the main part of a Word document (`.docx`) to markdown.
+pub fn docx_to_markdown(bytes: &[u8]) -> Result<String, String> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let xml = read_entry(&mut archive, "word/document.xml")?;
+    let mut reader = Reader::from_str(&xml);
+    let mut md = Markdown::default();
+
+    let mut paragraph = String::new();
+    let mut heading: Option<usize> = None;
+    let mut list_item = false;
+    let mut in_text = false;
+    // Rows of the tables being read, innermost last
+    let mut tables: Vec<Vec<Vec<String>>> = Vec::new();
+    loop {
+        let event = reader.read_event().map_err(|e| e.to_string())?;
+        let start = matches!(event, Event::Start(_));
+        match event {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"p" => {
+                    paragraph.clear();
+                    heading = None;
+                    list_item = false;
+                }
+                b"pStyle" => {
+                    heading = heading.or(attribute(&e, b"val")
+                        .as_deref()
+                        .and_then(style_heading_level));
+                }
+                // Outline levels 0-8 are headings, 9 is body text
+                b"outlineLvl" => {
+                    heading = heading.or(attribute(&e, b"val")
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .filter(|&level| level < 9)
+                        .map(|level| level + 1));
+                }
+                b"numPr" => list_item = true,
+                // An empty <w:t/> has no text and no end tag to close it
+                b"t" if start => in_text = true,
+                b"tab" => paragraph.push('\t'),
+                b"br" | b"cr" => paragraph.push('\n'),
+                b"tbl" => tables.push(Vec::new()),
+                b"tr" => {
+                    if let Some(rows) = tables.last_mut() {
+                        rows.push(Vec::new());
+                    }
+                }
+                b"tc" => {
+                    if let Some(row) = tables.last_mut().and_then(|rows| rows.last_mut()) {
+                        row.push(String::new());
+                    }
+                }
+                _ => {}
+            },
+            Event::Text(text) if in_text => {
+                paragraph.push_str(&text.unescape().map_err(|e| e.to_string())?);
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" => {
+                    let cell = tables
+                        .last_mut()
+                        .and_then(|rows| rows.last_mut())
+                        .and_then(|row| row.last_mut());
+                    if let Some(cell) = cell {
+                        if !cell.is_empty() {
+                            cell.push(' ');
+                        }
+                        cell.push_str(&collapse(&paragraph));
+                    } else if let Some(level) = heading {
+                        md.heading(level, &paragraph);
+                    } else if list_item {
+                        md.line_break();
+                        md.out.push_str("- ");
+                        md.text(&paragraph);
+                        md.line_break();
+                    } else if !paragraph.trim().is_empty() {
+                        md.block_break();
+                        for (i, line) in paragraph.lines().enumerate() {
+                            if i > 0 {
+                                md.line_break();
+                            }
+                            md.text(line);
+                        }
+                        md.block_break();
+                    }
+                    paragraph.clear();
+                }
+                b"tbl" => {
+                    if let Some(rows) = tables.pop() {
+                        md.table(&rows);
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(md.finish())
+}
+
+/// Decodes `%XX` escapes in a relative URL.
+fn percent_decode(href: &str) -> String {
+    let bytes = href.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Joins `href` onto the directory of the archive entry `base`, resolving
+/// `..` segments.
+fn resolve(base: &str, href: &str) -> String {
+    let mut parts: Vec<&str> = base.split('/').collect();
+    parts.pop();
+    for segment in href.split('/') {
+        match segment {
+            "." | "" => {}
+            ".." => {
+                parts.pop();
+            }
+            segment => parts.push(segment),
+        }
+    }
+    parts.join("/")
+}
+
+/// Converts the chapters of an EPUB book, in reading order, to markdown.
+pub fn epub_to_markdown(bytes: &[u8]) -> Result<String, String> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+
+    let container = read_entry(&mut archive, "META-INF/container.xml")?;
+    let mut reader = Reader::from_str(&container);
+    let mut package = None;
+    loop {
+        match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"rootfile" => {
+                package = attribute(&e, b"full-path");
+                break;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    let package = package.ok_or("META-INF/container.xml names no package document")?;
+
+    let opf = read_entry(&mut archive, &package)?;
+    let mut reader = Reader::from_str(&opf);
+    let mut manifest: HashMap<String, String> = HashMap::new();
+    let mut spine: Vec<String> = Vec::new();
+    loop {
+        match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"item" => {
+                    if let (Some(id), Some(href)) = (attribute(&e, b"id"), attribute(&e, b"href")) {
+                        manifest.insert(id, href);
+                    }
+                }
+                b"itemref" => spine.extend(attribute(&e, b"idref")),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let mut chapters = Vec::new();
+    for id in spine {
+        let Some(href) = manifest.get(&id) else {
+            continue;
+        };
+        let path = resolve(
+            &package,
+            &percent_decode(href.split('#').next().unwrap_or(href)),
+        );
+        let chapter = html_to_markdown(&read_entry(&mut archive, &path)?);
+        if !chapter.is_empty() {
+            chapters.push(chapter);
+        }
+    }
+    Ok(chapters.join("\n\n"))
+}
+
+/// Converts a document with extension `extension` to markdown, or returns
+/// `None` when the extension names no supported document type.
+pub fn document_to_markdown(extension: &str, bytes: &[u8]) -> Option<Result<String, String>> {
+    match extension {
+        "html" | "htm" | "xhtml" => {
+            let html = decode(bytes).map(|(text, _)| text).unwrap_or_default();
+            Some(Ok(html_to_markdown(&html)))
+        }
+        "docx" => Some(docx_to_markdown(bytes)),
+        "epub" => Some(epub_to_markdown(bytes)),
+        _ => None,
+    }
+}
+
+/// Converts an HTML page (`.html`, `.htm`, `.xhtml`), Word document
+/// (`.docx`) or EPUB book (`.epub`) to markdown text, keeping headings,
+/// lists, preformatted blocks and tables.
+#[pyfunction]
+pub fn convert_document(py: Python<'_>, path: PathBuf) -> PyResult<String> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    let bytes =
+        fs::read(&path).map_err(|e| PyOSError::new_err(format!("{}: {}", path.display(), e)))?;
+    py.allow_threads(|| document_to_markdown(&extension, &bytes))
+        .ok_or_else(|| {
+            PyValueError::new_err(format!("unsupported document type: {}", path.display()))
+        })?
+        .map_err(PyValueError::new_err)
+}
@@ -0,0 +1,182 @@
+//! Local sentence embedding inference on the CPU.
+//!
+//! Runs a BERT-style sentence-transformer model (all-MiniLM, BGE, E5, ...)
+//! from a local [`Checkpoint`]. Token states are pooled the way the model's
+//! `1_Pooling/config.json` asks, mean pooling when it is absent, so the
+//! vectors match what sentence-transformers computes for the same model.
+
+use std::path::PathBuf;
+
+use candle_core::DType;
+use candle_transformers::models::bert::BertModel;
+use numpy::ndarray::Array2;
+use numpy::PyArray2;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use serde::Deserialize;
+use tokenizers::{Encoding, Tokenizer};
+
+use crate::bert::{batch_tensors, by_length, read_json, Checkpoint};
+use crate::search::normalized;
+
+const POOLING_FILE: &str = "1_Pooling/config.json";
+
+/// How token states become one vector per text.
+#[derive(Clone, Copy, PartialEq)]
+enum Pooling {
+    Mean,
+    Cls,
+}
+
+/// The fields of a sentence-transformers pooling config that are used.
+#[derive(Deserialize)]
+struct PoolingConfig {
+    #[serde(default)]
+    pooling_mode_cls_token: bool,
+}
+
+/// A sentence-transformer model that embeds texts locally.
+#[pyclass]
+pub struct TextEmbedder {
+    name: String,
+    model: BertModel,
+    tokenizer: Tokenizer,
+    pooling: Pooling,
+    normalize: bool,
+    batch_size: usize,
+    dim: usize,
+}
+
+impl TextEmbedder {
+    /// Runs one padded batch through the model and pools it.
+    fn embed_batch(&self, encodings: &[Encoding]) -> candle_core::Result<Vec<Vec<f32>>> {
+        let (ids, type_ids, mask) = batch_tensors(encodings, &self.model.device)?;
+        let states = self.model.forward(&ids, &type_ids, Some(&mask))?;
+        let pooled = match self.pooling {
+            Pooling::Cls => states.narrow(1, 0, 1)?.squeeze(1)?,
+            Pooling::Mean => {
+                // Padding positions must not count towards the mean
+                let mask = mask.to_dtype(DType::F32)?.unsqueeze(2)?;
+                let sums = states.broadcast_mul(&mask)?.sum(1)?;
+                sums.broadcast_div(&mask.sum(1)?)?
+            }
+        };
+        let vectors = pooled.to_vec2::<f32>()?;
+        Ok(if self.normalize {
+            vectors.iter().map(|v| normalized(v)).collect()
+        } else {
+            vectors
+        })
+    }
+
+    /// Embeds `texts` in batches of similar length, so little of each batch
+    /// is padding, and returns the rows in input order.
+    pub fn embed_texts(&self, texts: &[String]) -> Result<Vec<f32>, String> {
+        let order = by_length(texts.iter().map(String::len));
+        let mut data = vec![0.0; texts.len() * self.dim];
+        for batch in order.chunks(self.batch_size) {
+            let inputs: Vec<&str> = batch.iter().map(|&i| texts[i].as_str()).collect();
+            let encodings = self
+                .tokenizer
+                .encode_batch(inputs, true)
+                .map_err(|e| e.to_string())?;
+            let vectors = self.embed_batch(&encodings).map_err(|e| e.to_string())?;
+            for (&i, vector) in batch.iter().zip(vectors) {
+                data[i * self.dim..(i + 1) * self.dim].copy_from_slice(&vector);
+            }
+        }
+        Ok(data)
+    }
+}
+
+#[pymethods]
+impl TextEmbedder {
+    /// Loads the model in directory `path`.
+    ///
+    /// Texts are truncated to `max_length` tokens, or the model's own limit
+    /// if lower, and run `batch_size` at a time. With `normalize` the
+    /// embeddings have unit length. `name` identifies the model to indexes
+    /// and stores, the directory name by default.
+    #[new]
+    #[pyo3(signature = (path, max_length = 256, batch_size = 32, normalize = true, name = None))]
+    fn new(
+        path: PathBuf,
+        max_length: usize,
+        batch_size: usize,
+        normalize: bool,
+        name: Option<String>,
+    ) -> PyResult<Self> {
+        if batch_size == 0 {
+            return Err(PyValueError::new_err("batch_size must be positive"));
+        }
+        let checkpoint = Checkpoint::open(path, max_length)?;
+        let pooling = match checkpoint.path.join(POOLING_FILE) {
+            pooling if pooling.is_file() => {
+                let pooling: PoolingConfig = read_json(&pooling)?;
+                if pooling.pooling_mode_cls_token {
+                    Pooling::Cls
+                } else {
+                    Pooling::Mean
+                }
+            }
+            _ => Pooling::Mean,
+        };
+        let model = BertModel::load(checkpoint.weights.clone(), &checkpoint.config)
+            .map_err(|e| checkpoint.invalid_weights(e))?;
+        let name = checkpoint.name(name);
+        Ok(Self {
+            name,
+            model,
+            tokenizer: checkpoint.tokenizer,
+            pooling,
+            normalize,
+            batch_size,
+            dim: checkpoint.config.hidden_size,
+        })
+    }
+
+    /// Name the embeddings are recorded under.
+    #[getter]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Dimension of the embeddings.
+    #[getter]
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// `"mean"` or `"cls"`.
+    #[getter]
+    fn pooling(&self) -> &'static str {
+        match self.pooling {
+            Pooling::Mean => "mean",
+            Pooling::Cls => "cls",
+        }
+    }
+
+    /// Embeds each text, returning a `float32` array with one row per text.
+    /// The GIL is released while the model runs.
+    fn embed<'py>(
+        &self,
+        py: Python<'py>,
+        texts: Vec<String>,
+    ) -> PyResult<Bound<'py, PyArray2<f32>>> {
+        let data = py
+            .allow_threads(|| self.embed_texts(&texts))
+            .map_err(PyRuntimeError::new_err)?;
+        let array = Array2::from_shape_vec((texts.len(), self.dim), data)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyArray2::from_owned_array_bound(py, array))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "TextEmbedder(name={:?}, dim={}, pooling={:?})",
+            self.name,
+            self.dim,
+            self.pooling()
+        )
+    }
+}
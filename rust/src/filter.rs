@@ -0,0 +1,174 @@
+//! Per-vector attributes and the filter expressions evaluated against them.
+//!
+//! Filters are passed from Python as dicts. Each key names an attribute and
+//! all keys must match, as must every operator given for a key:
+//!
+//! ```python
+//! {"source_type": "markdown"}                             # equality
+//! {"source": {"$in": ["a.md", "b.md"]}}                   # in-set
+//! {"created_at": {"$gte": 1700000000, "$lt": 1800000000}} # range
+//! {"filename": {"$prefix": "api_"}}                       # string prefix
+//! {"page": {"$in": [1, 2, 3], "$gte": 2}}                 # both
+//! ```
+//!
+//! `datetime` values are compared as POSIX timestamps. A row without the
+//! named attribute never matches, and an empty dict of operators matches
+//! any row that has it.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyString};
+
+/// An attribute value: numbers (including bools and timestamps) or strings.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Num(f64),
+    Str(String),
+}
+
+impl Value {
+    fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Num(a), Self::Num(b)) => a.partial_cmp(b),
+            (Self::Str(a), Self::Str(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+impl<'py> FromPyObject<'py> for Value {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if let Ok(s) = ob.downcast::<PyString>() {
+            return Ok(Self::Str(s.to_str()?.to_owned()));
+        }
+        if let Ok(n) = ob.extract::<f64>() {
+            return Ok(Self::Num(n));
+        }
+        if ob.hasattr("timestamp")? {
+            return Ok(Self::Num(ob.call_method0("timestamp")?.extract()?));
+        }
+        Err(PyValueError::new_err(format!(
+            "unsupported attribute value: {}",
+            ob.repr()?
+        )))
+    }
+}
+
+/// Named attribute values stored alongside one vector.
+pub type Attributes = HashMap<String, Value>;
+
+/// One bound of a range condition.
+#[derive(Clone, Debug)]
+struct Limit {
+    value: Value,
+    inclusive: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Condition {
+    Eq(Value),
+    In(Vec<Value>),
+    Range {
+        lower: Option<Limit>,
+        upper: Option<Limit>,
+    },
+    Prefix(String),
+}
+
+impl Condition {
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            Self::Eq(expected) => value == expected,
+            Self::In(set) => set.contains(value),
+            Self::Range { lower, upper } => {
+                let above = lower.as_ref().is_none_or(|b| {
+                    matches!(
+                        (value.compare(&b.value), b.inclusive),
+                        (Some(Ordering::Greater), _) | (Some(Ordering::Equal), true)
+                    )
+                });
+                let below = upper.as_ref().is_none_or(|b| {
+                    matches!(
+                        (value.compare(&b.value), b.inclusive),
+                        (Some(Ordering::Less), _) | (Some(Ordering::Equal), true)
+                    )
+                });
+                above && below
+            }
+            Self::Prefix(prefix) => {
+                matches!(value, Value::Str(s) if s.starts_with(prefix.as_str()))
+            }
+        }
+    }
+}
+
+/// A conjunction of per-attribute conditions.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    conditions: Vec<(String, Vec<Condition>)>,
+}
+
+impl Filter {
+    /// Whether `attributes` satisfy every condition.
+    pub fn matches(&self, attributes: &Attributes) -> bool {
+        self.conditions.iter().all(|(key, conditions)| {
+            attributes
+                .get(key)
+                .is_some_and(|value| conditions.iter().all(|c| c.matches(value)))
+        })
+    }
+}
+
+/// One condition per operator in `ops`, all of which must hold.
+fn parse_operators(key: &str, ops: &Bound<'_, PyDict>) -> PyResult<Vec<Condition>> {
+    let mut conditions = Vec::with_capacity(ops.len());
+    for (op, operand) in ops.iter() {
+        let op: String = op.extract()?;
+        let condition = match op.as_str() {
+            "$eq" => Condition::Eq(operand.extract()?),
+            "$in" => Condition::In(operand.extract()?),
+            "$prefix" => Condition::Prefix(operand.extract()?),
+            "$gt" | "$gte" => Condition::Range {
+                lower: Some(Limit {
+                    value: operand.extract()?,
+                    inclusive: op == "$gte",
+                }),
+                upper: None,
+            },
+            "$lt" | "$lte" => Condition::Range {
+                lower: None,
+                upper: Some(Limit {
+                    value: operand.extract()?,
+                    inclusive: op == "$lte",
+                }),
+            },
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown filter operator {:?} for {:?}",
+                    other, key
+                )))
+            }
+        };
+        conditions.push(condition);
+    }
+    Ok(conditions)
+}
+
+impl<'py> FromPyObject<'py> for Filter {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let dict = ob.downcast::<PyDict>()?;
+        let mut conditions = Vec::with_capacity(dict.len());
+        for (key, condition) in dict.iter() {
+            let key: String = key.extract()?;
+            let condition = match condition.downcast::<PyDict>() {
+                Ok(ops) => parse_operators(&key, ops)?,
+                Err(_) => vec![Condition::Eq(condition.extract()?)],
+            };
+            conditions.push((key, condition));
+        }
+        Ok(Self { conditions })
+    }
+}
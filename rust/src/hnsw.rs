@@ -0,0 +1,601 @@
+//! Hierarchical navigable small world (HNSW) graph for approximate search.
+//!
+//! Follows Malkov & Yashunin (2016): every node draws a random top layer,
+//! sparse upper layers act as express lanes towards the query, and layer 0
+//! links every node to its `2 * M` nearest diverse neighbours. Build cost is
+//! `O(log n)` per insert, so the index can be extended one chunk at a time.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use rayon::prelude::*;
+
+use crate::array::{MatrixArg, VectorArg};
+use crate::kbfile::{Reader, Writer};
+use crate::metric::{require_cosine, Metric};
+use crate::provenance::{check_model, dimension_mismatch};
+use crate::rng::SplitMix64;
+use crate::search::{cosine_with_norms, norm, Scored};
+
+/// An approximate cosine index over chunk embeddings.
+///
+/// Removing or re-adding a chunk marks its old node deleted: the node keeps
+/// routing searches through the graph but never appears in results. Once
+/// deleted nodes outnumber live ones the graph is rebuilt without them.
+#[pyclass]
+pub struct HnswIndex {
+    dim: usize,
+    /// Embedding model the index was created for, if named.
+    pub(crate) model: Option<String>,
+    m: usize,
+    ef_construction: usize,
+    ef_search: usize,
+    level_mult: f64,
+    rng: SplitMix64,
+    data: Vec<f32>,
+    norms: Vec<f32>,
+    ids: Vec<String>,
+    deleted: Vec<bool>,
+    /// Number of nodes marked deleted.
+    tombstones: usize,
+    positions: HashMap<String, usize>,
+    /// `links[node][level]` lists the neighbours of `node` on `level`.
+    links: Vec<Vec<Vec<u32>>>,
+    entry_point: Option<usize>,
+    max_level: usize,
+}
+
+impl HnswIndex {
+    fn row(&self, node: usize) -> &[f32] {
+        &self.data[node * self.dim..(node + 1) * self.dim]
+    }
+
+    fn similarity(&self, a: usize, b: usize) -> f32 {
+        cosine_with_norms(self.row(a), self.norms[a], self.row(b), self.norms[b])
+    }
+
+    fn similarity_to(&self, query: &[f32], query_norm: f32, node: usize) -> f32 {
+        cosine_with_norms(query, query_norm, self.row(node), self.norms[node])
+    }
+
+    fn max_links(&self, level: usize) -> usize {
+        if level == 0 {
+            self.m * 2
+        } else {
+            self.m
+        }
+    }
+
+    fn random_level(&mut self) -> usize {
+        (-self.rng.next_unit().ln() * self.level_mult).floor() as usize
+    }
+
+    /// Best-first search of one layer, returning up to `ef` nodes best first.
+    fn search_layer(
+        &self,
+        query: &[f32],
+        query_norm: f32,
+        entry: &[Scored],
+        ef: usize,
+        level: usize,
+    ) -> Vec<Scored> {
+        let mut visited: HashSet<usize> = entry.iter().map(|s| s.index).collect();
+        let mut candidates: BinaryHeap<Scored> = entry.iter().copied().collect();
+        let mut results: BinaryHeap<Reverse<Scored>> = entry.iter().copied().map(Reverse).collect();
+
+        while let Some(candidate) = candidates.pop() {
+            if let Some(Reverse(worst)) = results.peek() {
+                if results.len() >= ef && candidate < *worst {
+                    break;
+                }
+            }
+            for &neighbour in &self.links[candidate.index][level] {
+                let neighbour = neighbour as usize;
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let found = Scored {
+                    index: neighbour,
+                    score: self.similarity_to(query, query_norm, neighbour),
+                };
+                let admit = results.len() < ef
+                    || results.peek().is_some_and(|Reverse(worst)| found > *worst);
+                if admit {
+                    candidates.push(found);
+                    results.push(Reverse(found));
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(s)| s)
+            .collect()
+    }
+
+    /// Picks up to `m` neighbours from candidates sorted best first, skipping
+    /// any candidate that is closer to an already chosen neighbour than to
+    /// the base node. This keeps links spread across clusters.
+    fn select_neighbours(&self, candidates: &[Scored], m: usize) -> Vec<u32> {
+        let mut selected: Vec<usize> = Vec::with_capacity(m);
+        for candidate in candidates {
+            if selected.len() >= m {
+                break;
+            }
+            let diverse = selected
+                .iter()
+                .all(|&chosen| self.similarity(candidate.index, chosen) <= candidate.score);
+            if diverse {
+                selected.push(candidate.index);
+            }
+        }
+        selected.into_iter().map(|node| node as u32).collect()
+    }
+
+    /// Re-selects the links of `node` on `level` once it has too many.
+    fn shrink_links(&mut self, node: usize, level: usize) {
+        let mut candidates: Vec<Scored> = self.links[node][level]
+            .iter()
+            .map(|&n| Scored {
+                index: n as usize,
+                score: self.similarity(node, n as usize),
+            })
+            .collect();
+        candidates.sort_unstable_by(|a, b| b.cmp(a));
+        self.links[node][level] = self.select_neighbours(&candidates, self.max_links(level));
+    }
+
+    /// Walks greedily from the entry point down to `level`, one node per layer.
+    fn descend(&self, query: &[f32], query_norm: f32, level: usize) -> Vec<Scored> {
+        let Some(entry_point) = self.entry_point else {
+            return Vec::new();
+        };
+        let mut entry = vec![Scored {
+            index: entry_point,
+            score: self.similarity_to(query, query_norm, entry_point),
+        }];
+        for layer in (level + 1..=self.max_level).rev() {
+            entry = self.search_layer(query, query_norm, &entry, 1, layer);
+        }
+        entry
+    }
+
+    /// Inserts a node, marking any previous node for `id` deleted.
+    pub fn insert(&mut self, id: String, vector: &[f32]) {
+        if let Some(&old) = self.positions.get(&id) {
+            self.deleted[old] = true;
+            self.tombstones += 1;
+        }
+        self.link(id, vector);
+        self.compact_if_sparse();
+    }
+
+    /// Adds a node for `id` and links it into every layer up to its own.
+    fn link(&mut self, id: String, vector: &[f32]) {
+        let node = self.ids.len();
+        let level = self.random_level();
+        self.data.extend_from_slice(vector);
+        self.norms.push(norm(vector));
+        self.positions.insert(id.clone(), node);
+        self.ids.push(id);
+        self.deleted.push(false);
+        self.links.push(vec![Vec::new(); level + 1]);
+
+        if self.entry_point.is_none() {
+            self.entry_point = Some(node);
+            self.max_level = level;
+            return;
+        }
+
+        let query_norm = self.norms[node];
+        let mut entry = self.descend(vector, query_norm, level);
+        for layer in (0..=level.min(self.max_level)).rev() {
+            let found = self.search_layer(vector, query_norm, &entry, self.ef_construction, layer);
+            let neighbours = self.select_neighbours(&found, self.m);
+            for &neighbour in &neighbours {
+                let neighbour = neighbour as usize;
+                self.links[neighbour][layer].push(node as u32);
+                if self.links[neighbour][layer].len() > self.max_links(layer) {
+                    self.shrink_links(neighbour, layer);
+                }
+            }
+            self.links[node][layer] = neighbours;
+            entry = found;
+        }
+
+        if level > self.max_level {
+            self.max_level = level;
+            self.entry_point = Some(node);
+        }
+    }
+
+    /// Marks the node for `id` deleted. Returns false if `id` is unknown.
+    pub fn delete(&mut self, id: &str) -> bool {
+        match self.positions.remove(id) {
+            Some(node) => {
+                self.deleted[node] = true;
+                self.tombstones += 1;
+                self.compact_if_sparse();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Rebuilds the graph once deleted nodes outnumber live ones, so that
+    /// repeated re-syncs do not grow it without bound.
+    fn compact_if_sparse(&mut self) {
+        if self.tombstones > self.positions.len() {
+            self.compact();
+        }
+    }
+
+    /// Rebuilds the graph from the live nodes, dropping every deleted one.
+    pub fn compact(&mut self) {
+        let data = std::mem::take(&mut self.data);
+        let ids = std::mem::take(&mut self.ids);
+        let deleted = std::mem::take(&mut self.deleted);
+        self.norms.clear();
+        self.positions.clear();
+        self.links.clear();
+        self.tombstones = 0;
+        self.entry_point = None;
+        self.max_level = 0;
+        for (node, id) in ids.into_iter().enumerate() {
+            if !deleted[node] {
+                self.link(id, &data[node * self.dim..(node + 1) * self.dim]);
+            }
+        }
+    }
+
+    /// Returns up to `k` live `(node, score)` pairs at or above `threshold`.
+    ///
+    /// Deleted nodes take up candidate slots, so the candidate list starts
+    /// larger in proportion to them and doubles until `k` live nodes are
+    /// found, the threshold cuts the list short or the graph is exhausted.
+    pub fn top_k(&self, query: &[f32], k: usize, threshold: f32) -> Vec<(usize, f32)> {
+        let live = self.positions.len();
+        if k == 0 || live == 0 {
+            return Vec::new();
+        }
+        let query_norm = norm(query);
+        let entry = self.descend(query, query_norm, 0);
+        let mut ef = self.ef_search.max(k) * self.ids.len() / live;
+        loop {
+            let found = self.search_layer(query, query_norm, &entry, ef, 0);
+            let exhausted =
+                ef >= self.ids.len() || found.last().is_some_and(|worst| worst.score < threshold);
+            let hits: Vec<(usize, f32)> = found
+                .into_iter()
+                .filter(|s| !self.deleted[s.index] && s.score >= threshold)
+                .take(k)
+                .map(|s| (s.index, s.score))
+                .collect();
+            if hits.len() == k || exhausted {
+                return hits;
+            }
+            ef *= 2;
+        }
+    }
+
+    fn check_dim(&self, vector: &[f32]) -> PyResult<()> {
+        if vector.len() != self.dim {
+            return Err(dimension_mismatch(vector.len(), self.dim));
+        }
+        Ok(())
+    }
+
+    fn with_ids(&self, hits: Vec<(usize, f32)>) -> Vec<(String, f32)> {
+        hits.into_iter()
+            .map(|(node, score)| (self.ids[node].clone(), score))
+            .collect()
+    }
+
+    /// Encodes the whole graph, deleted nodes included, for a knowledge
+    /// base file.
+    pub fn write_to(&self, w: &mut Writer) {
+        w.usize(self.dim);
+        w.usize(self.m);
+        w.usize(self.ef_construction);
+        w.usize(self.ef_search);
+        w.f64(self.level_mult);
+        w.u64(self.rng.0);
+        w.f32s(&self.data);
+        w.f32s(&self.norms);
+        w.usize(self.ids.len());
+        for (id, node_links) in self.ids.iter().zip(&self.links) {
+            w.str(id);
+            w.usize(node_links.len());
+            for level in node_links {
+                w.u32s(level);
+            }
+        }
+        let deleted: Vec<u8> = self.deleted.iter().map(|&d| d as u8).collect();
+        w.bytes(&deleted);
+        w.u64(self.entry_point.map_or(u64::MAX, |node| node as u64));
+        w.usize(self.max_level);
+    }
+
+    /// Decodes a graph written by [`write_to`](Self::write_to).
+    pub fn read_from(r: &mut Reader<'_>) -> Result<Self, String> {
+        let corrupt = || "corrupt HNSW index".to_string();
+        let dim = r.usize()?;
+        let m = r.usize()?;
+        let ef_construction = r.usize()?;
+        let ef_search = r.usize()?;
+        let level_mult = r.f64()?;
+        let rng = SplitMix64(r.u64()?);
+        let data = r.f32s()?;
+        let norms = r.f32s()?;
+        let nodes = r.usize()?;
+        let mut ids = Vec::new();
+        let mut links = Vec::new();
+        for _ in 0..nodes {
+            ids.push(r.string()?);
+            let levels = r.usize()?;
+            let mut node_links = Vec::new();
+            for _ in 0..levels {
+                let level = r.u32s()?;
+                if level.iter().any(|&n| n as usize >= nodes) {
+                    return Err(corrupt());
+                }
+                node_links.push(level);
+            }
+            links.push(node_links);
+        }
+        let deleted: Vec<bool> = r.bytes()?.iter().map(|&d| d != 0).collect();
+        let entry_point = match r.u64()? {
+            u64::MAX => None,
+            node => Some(node as usize),
+        };
+        let max_level = r.usize()?;
+        if dim == 0
+            || m < 2
+            || ef_construction == 0
+            || ef_search == 0
+            || !(level_mult.is_finite() && level_mult > 0.0)
+            || data.len() != nodes * dim
+            || norms.len() != nodes
+            || deleted.len() != nodes
+            || entry_point.is_some_and(|e| e >= nodes || links[e].len() <= max_level)
+            // Searches index `links[neighbour][level]` for every link
+            || links.iter().any(|levels| {
+                levels.iter().enumerate().any(|(level, neighbours)| {
+                    neighbours.iter().any(|&n| links[n as usize].len() <= level)
+                })
+            })
+        {
+            return Err(corrupt());
+        }
+        let mut positions = HashMap::new();
+        for (node, id) in ids.iter().enumerate() {
+            // One live node per chunk id
+            if !deleted[node] && positions.insert(id.clone(), node).is_some() {
+                return Err(corrupt());
+            }
+        }
+        let tombstones = nodes - positions.len();
+        Ok(Self {
+            dim,
+            model: None,
+            m,
+            ef_construction,
+            ef_search,
+            level_mult,
+            rng,
+            data,
+            norms,
+            ids,
+            deleted,
+            tombstones,
+            positions,
+            links,
+            entry_point,
+            max_level,
+        })
+    }
+}
+
+#[pymethods]
+impl HnswIndex {
+    /// Creates an empty index.
+    ///
+    /// `m` is the number of links per node on upper layers (twice that on
+    /// layer 0), `ef_construction` the candidate list size while inserting
+    /// and `ef_search` the candidate list size while querying. Larger values
+    /// trade speed and memory for recall. `model` names the embedding model,
+    /// which calls that name a model are checked against. `metric` must be
+    /// `"cosine"`, the only metric the graph is built for.
+    #[new]
+    #[pyo3(signature = (dim, m = 16, ef_construction = 200, ef_search = 50, seed = 42, model = None, metric = Metric::Cosine))]
+    fn new(
+        dim: usize,
+        m: usize,
+        ef_construction: usize,
+        ef_search: usize,
+        seed: u64,
+        model: Option<String>,
+        metric: Metric,
+    ) -> PyResult<Self> {
+        require_cosine("HnswIndex", metric)?;
+        if dim == 0 {
+            return Err(PyValueError::new_err("dimension must be positive"));
+        }
+        if m < 2 {
+            return Err(PyValueError::new_err("m must be at least 2"));
+        }
+        if ef_construction == 0 || ef_search == 0 {
+            return Err(PyValueError::new_err("ef values must be positive"));
+        }
+        Ok(Self {
+            dim,
+            model,
+            m,
+            ef_construction,
+            ef_search,
+            level_mult: 1.0 / (m as f64).ln(),
+            rng: SplitMix64(seed),
+            data: Vec::new(),
+            norms: Vec::new(),
+            ids: Vec::new(),
+            deleted: Vec::new(),
+            tombstones: 0,
+            positions: HashMap::new(),
+            links: Vec::new(),
+            entry_point: None,
+            max_level: 0,
+        })
+    }
+
+    /// Dimension every stored and queried embedding must have.
+    #[getter]
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Embedding model the index was created for, or `None`.
+    #[getter]
+    fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    #[getter]
+    fn m(&self) -> usize {
+        self.m
+    }
+
+    #[getter]
+    fn ef_construction(&self) -> usize {
+        self.ef_construction
+    }
+
+    /// Candidate list size used by `search`; may be tuned between queries.
+    #[getter]
+    fn ef_search(&self) -> usize {
+        self.ef_search
+    }
+
+    #[setter]
+    fn set_ef_search(&mut self, ef_search: usize) -> PyResult<()> {
+        if ef_search == 0 {
+            return Err(PyValueError::new_err("ef_search must be positive"));
+        }
+        self.ef_search = ef_search;
+        Ok(())
+    }
+
+    /// Adds the embedding for `chunk_id`, replacing any previous one.
+    #[pyo3(signature = (chunk_id, embedding, model = None))]
+    fn add(
+        &mut self,
+        chunk_id: String,
+        embedding: VectorArg<'_>,
+        model: Option<&str>,
+    ) -> PyResult<()> {
+        check_model(self.model.as_deref(), model)?;
+        let embedding = embedding.to_f32();
+        self.check_dim(&embedding)?;
+        self.insert(chunk_id, &embedding);
+        Ok(())
+    }
+
+    /// Adds one embedding per chunk id from a 2-D matrix.
+    #[pyo3(signature = (chunk_ids, embeddings, model = None))]
+    fn add_many(
+        &mut self,
+        py: Python<'_>,
+        chunk_ids: Vec<String>,
+        embeddings: MatrixArg<'_>,
+        model: Option<&str>,
+    ) -> PyResult<()> {
+        check_model(self.model.as_deref(), model)?;
+        let matrix = embeddings.view()?;
+        matrix.check_rows(chunk_ids.len())?;
+        matrix.check_dim(self.dim)?;
+        let rows = matrix.to_f32_vec();
+        py.allow_threads(|| {
+            for (id, row) in chunk_ids.into_iter().zip(rows.chunks_exact(self.dim)) {
+                self.insert(id, row);
+            }
+        });
+        Ok(())
+    }
+
+    /// Removes the embedding stored for `chunk_id`.
+    fn remove(&mut self, chunk_id: &str) -> PyResult<()> {
+        if !self.delete(chunk_id) {
+            return Err(PyKeyError::new_err(chunk_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the graph without the nodes of removed or replaced chunks.
+    /// Happens on its own once they outnumber the live nodes.
+    #[pyo3(name = "compact")]
+    fn py_compact(&mut self, py: Python<'_>) {
+        py.allow_threads(|| self.compact());
+    }
+
+    /// Number of graph nodes, deleted ones included.
+    #[getter]
+    fn nodes(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Returns up to `k` approximate `(chunk_id, score)` pairs, best first.
+    ///
+    /// The GIL is released while the graph is searched.
+    #[pyo3(signature = (query, k, threshold = f32::NEG_INFINITY, model = None))]
+    fn search(
+        &self,
+        py: Python<'_>,
+        query: VectorArg<'_>,
+        k: usize,
+        threshold: f32,
+        model: Option<&str>,
+    ) -> PyResult<Vec<(String, f32)>> {
+        check_model(self.model.as_deref(), model)?;
+        let query = query.to_f32();
+        self.check_dim(&query)?;
+        let hits = py.allow_threads(|| self.top_k(&query, k, threshold));
+        Ok(self.with_ids(hits))
+    }
+
+    /// Searches one query per row of `queries` in parallel across CPU cores.
+    #[pyo3(signature = (queries, k, threshold = f32::NEG_INFINITY, model = None))]
+    fn batch_search(
+        &self,
+        py: Python<'_>,
+        queries: MatrixArg<'_>,
+        k: usize,
+        threshold: f32,
+        model: Option<&str>,
+    ) -> PyResult<Vec<Vec<(String, f32)>>> {
+        check_model(self.model.as_deref(), model)?;
+        let queries = queries.view()?;
+        queries.check_dim(self.dim)?;
+        let queries = queries.to_f32_vec();
+        let hits: Vec<_> = py.allow_threads(|| {
+            queries
+                .par_chunks_exact(self.dim)
+                .map(|query| self.top_k(query, k, threshold))
+                .collect()
+        });
+        Ok(hits.into_iter().map(|hits| self.with_ids(hits)).collect())
+    }
+
+    /// Number of live (not deleted) embeddings.
+    fn __len__(&self) -> usize {
+        self.positions.len()
+    }
+
+    fn __contains__(&self, chunk_id: &str) -> bool {
+        self.positions.contains_key(chunk_id)
+    }
+}
@@ -0,0 +1,88 @@
+//! Rank fusion for hybrid lexical + vector retrieval.
+
+use std::collections::HashMap;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::search::TopK;
+
+/// Rescales scores to [0, 1] so lists on different scales can be blended.
+/// A list whose scores are all equal maps to 1.
+fn min_max(ranking: &[(String, f32)]) -> Vec<f32> {
+    let (lo, hi) = ranking
+        .iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), (_, s)| {
+            (lo.min(*s), hi.max(*s))
+        });
+    ranking
+        .iter()
+        .map(|(_, s)| if hi > lo { (s - lo) / (hi - lo) } else { 1.0 })
+        .collect()
+}
+
+/// Combines several rankings of chunk ids into one.
+///
+/// Each ranking is a list of `(chunk_id, score)` pairs sorted best first,
+/// such as the output of a vector index and of a `Bm25Index`.
+///
+/// With `method="rrf"` (reciprocal rank fusion) a chunk scores
+/// `sum(weight / (rrf_k + rank))` over the rankings it appears in, which
+/// ignores the raw scores entirely. With `method="weighted"` each ranking's
+/// scores are min-max normalized and blended as `sum(weight * score)`.
+/// `weights` defaults to 1 per ranking. Returns up to `k` pairs, best first.
+#[pyfunction]
+#[pyo3(signature = (rankings, k, method = "rrf", weights = None, rrf_k = 60.0))]
+pub fn fuse(
+    rankings: Vec<Vec<(String, f32)>>,
+    k: usize,
+    method: &str,
+    weights: Option<Vec<f32>>,
+    rrf_k: f32,
+) -> PyResult<Vec<(String, f32)>> {
+    let weights = weights.unwrap_or_else(|| vec![1.0; rankings.len()]);
+    if weights.len() != rankings.len() {
+        return Err(PyValueError::new_err(format!(
+            "got {} weights for {} rankings",
+            weights.len(),
+            rankings.len()
+        )));
+    }
+
+    let mut ids: Vec<&str> = Vec::new();
+    let mut totals: Vec<f32> = Vec::new();
+    let mut slots: HashMap<&str, usize> = HashMap::new();
+    for (ranking, weight) in rankings.iter().zip(&weights) {
+        let contributions: Vec<f32> = match method {
+            "rrf" => (0..ranking.len())
+                .map(|rank| weight / (rrf_k + rank as f32 + 1.0))
+                .collect(),
+            "weighted" => min_max(ranking).into_iter().map(|s| weight * s).collect(),
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown fusion method {:?}, expected \"rrf\" or \"weighted\"",
+                    other
+                )))
+            }
+        };
+        for ((id, _), contribution) in ranking.iter().zip(contributions) {
+            let slot = *slots.entry(id.as_str()).or_insert_with(|| {
+                ids.push(id.as_str());
+                totals.push(0.0);
+                ids.len() - 1
+            });
+            totals[slot] += contribution;
+        }
+    }
+
+    // Slots follow first appearance, so ties favour the earlier ranking
+    let mut top = TopK::new(k);
+    for (slot, total) in totals.iter().enumerate() {
+        top.push(slot, *total);
+    }
+    Ok(top
+        .into_sorted_vec()
+        .into_iter()
+        .map(|(slot, score)| (ids[slot].to_string(), score))
+        .collect())
+}
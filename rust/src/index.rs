@@ -0,0 +1,304 @@
+//! Persistent embedding matrix owned by Rust.
+//!
+//! Rows live in one contiguous `Vec<f32>` keyed by chunk id, so adding or
+//! removing a chunk touches only that row instead of rebuilding the matrix.
+//! Each row may carry [`Attributes`] that searches filter on while scanning.
+
+use std::collections::HashMap;
+
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use rayon::prelude::*;
+
+use crate::array::{MatrixArg, VectorArg};
+use crate::filter::{Attributes, Filter};
+use crate::metric::Metric;
+use crate::provenance::{check_model, dimension_mismatch};
+use crate::search::{norm, normalized, TopK};
+
+/// An incrementally maintained, brute-force index over chunk embeddings.
+#[pyclass]
+pub struct EmbeddingIndex {
+    dim: usize,
+    model: Option<String>,
+    metric: Metric,
+    data: Vec<f32>,
+    norms: Vec<f32>,
+    attributes: Vec<Attributes>,
+    ids: Vec<String>,
+    positions: HashMap<String, usize>,
+}
+
+impl EmbeddingIndex {
+    pub fn with_dim(dim: usize, metric: Metric) -> Self {
+        Self {
+            dim,
+            model: None,
+            metric,
+            data: Vec::new(),
+            norms: Vec::new(),
+            attributes: Vec::new(),
+            ids: Vec::new(),
+            positions: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn row(&self, position: usize) -> &[f32] {
+        &self.data[position * self.dim..(position + 1) * self.dim]
+    }
+
+    fn check_dim(&self, vector: &[f32]) -> PyResult<()> {
+        if vector.len() != self.dim {
+            return Err(dimension_mismatch(vector.len(), self.dim));
+        }
+        Ok(())
+    }
+
+    /// Inserts a row, overwriting the existing row and attributes if `id`
+    /// is already indexed.
+    pub fn insert(&mut self, id: String, vector: &[f32], attributes: Attributes) {
+        let unit;
+        let vector = if self.metric.normalizes() {
+            unit = normalized(vector);
+            &unit
+        } else {
+            vector
+        };
+        match self.positions.get(&id) {
+            Some(&position) => {
+                let start = position * self.dim;
+                self.data[start..start + self.dim].copy_from_slice(vector);
+                self.norms[position] = norm(vector);
+                self.attributes[position] = attributes;
+            }
+            None => {
+                self.positions.insert(id.clone(), self.ids.len());
+                self.ids.push(id);
+                self.data.extend_from_slice(vector);
+                self.norms.push(norm(vector));
+                self.attributes.push(attributes);
+            }
+        }
+    }
+
+    /// Removes a row by moving the last row into its slot.
+    pub fn delete(&mut self, id: &str) -> bool {
+        let Some(position) = self.positions.remove(id) else {
+            return false;
+        };
+        let last = self.ids.len() - 1;
+        if position != last {
+            let (head, tail) = self.data.split_at_mut(last * self.dim);
+            head[position * self.dim..(position + 1) * self.dim].copy_from_slice(tail);
+            self.ids.swap(position, last);
+            self.norms.swap(position, last);
+            self.attributes.swap(position, last);
+            self.positions.insert(self.ids[position].clone(), position);
+        }
+        self.ids.pop();
+        self.norms.pop();
+        self.attributes.pop();
+        self.data.truncate(last * self.dim);
+        true
+    }
+
+    fn with_ids(&self, hits: Vec<(usize, f32)>) -> Vec<(String, f32)> {
+        hits.into_iter()
+            .map(|(position, score)| (self.ids[position].clone(), score))
+            .collect()
+    }
+
+    /// Returns the best `(position, score)` pairs that satisfy `threshold`
+    /// under the index metric. Rows rejected by `filter` are skipped before
+    /// scoring, so they never take a top-k slot.
+    pub fn top_k(
+        &self,
+        query: &[f32],
+        k: usize,
+        threshold: Option<f32>,
+        filter: Option<&Filter>,
+    ) -> Vec<(usize, f32)> {
+        let unit;
+        let query = if self.metric.normalizes() {
+            unit = normalized(query);
+            &unit
+        } else {
+            query
+        };
+        let query_norm = norm(query);
+        let bound = self.metric.key_bound(threshold);
+        let mut top = TopK::new(k);
+        for position in 0..self.len() {
+            if filter.is_some_and(|filter| !filter.matches(&self.attributes[position])) {
+                continue;
+            }
+            let key = self
+                .metric
+                .key(query, query_norm, self.row(position), self.norms[position]);
+            if key >= bound {
+                top.push(position, key);
+            }
+        }
+        top.into_sorted_vec()
+            .into_iter()
+            .map(|(position, key)| (position, self.metric.score(key)))
+            .collect()
+    }
+}
+
+#[pymethods]
+impl EmbeddingIndex {
+    /// Creates an empty index scored by `metric`: `"cosine"`, `"dot"`,
+    /// `"ip_normalized"` or `"l2"`. `model` names the embedding model,
+    /// which calls that name a model are checked against.
+    #[new]
+    #[pyo3(signature = (dim, metric = Metric::Cosine, model = None))]
+    fn new(dim: usize, metric: Metric, model: Option<String>) -> PyResult<Self> {
+        if dim == 0 {
+            return Err(PyValueError::new_err("dimension must be positive"));
+        }
+        Ok(Self {
+            model,
+            ..Self::with_dim(dim, metric)
+        })
+    }
+
+    /// Dimension every stored and queried embedding must have.
+    #[getter(dim)]
+    fn py_dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Embedding model the index was created for, or `None`.
+    #[getter]
+    fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    /// Name of the metric results are scored with.
+    #[getter]
+    fn metric(&self) -> &'static str {
+        self.metric.name()
+    }
+
+    /// Adds or replaces the embedding stored for `chunk_id`, along with a
+    /// dict of string or numeric attributes to filter on.
+    #[pyo3(signature = (chunk_id, embedding, attributes = None, model = None))]
+    fn add(
+        &mut self,
+        chunk_id: String,
+        embedding: VectorArg<'_>,
+        attributes: Option<Attributes>,
+        model: Option<&str>,
+    ) -> PyResult<()> {
+        check_model(self.model.as_deref(), model)?;
+        let embedding = embedding.to_f32();
+        self.check_dim(&embedding)?;
+        self.insert(chunk_id, &embedding, attributes.unwrap_or_default());
+        Ok(())
+    }
+
+    /// Adds or replaces one embedding per chunk id from a 2-D matrix, with
+    /// an optional attribute dict per row.
+    #[pyo3(signature = (chunk_ids, embeddings, attributes = None, model = None))]
+    fn add_many(
+        &mut self,
+        chunk_ids: Vec<String>,
+        embeddings: MatrixArg<'_>,
+        attributes: Option<Vec<Attributes>>,
+        model: Option<&str>,
+    ) -> PyResult<()> {
+        check_model(self.model.as_deref(), model)?;
+        let matrix = embeddings.view()?;
+        matrix.check_rows(chunk_ids.len())?;
+        matrix.check_dim(self.dim)?;
+        let mut attributes = match attributes {
+            Some(attributes) if attributes.len() != chunk_ids.len() => {
+                return Err(PyValueError::new_err(format!(
+                    "got {} attribute dicts for {} chunk ids",
+                    attributes.len(),
+                    chunk_ids.len()
+                )))
+            }
+            Some(attributes) => attributes.into_iter(),
+            None => Vec::new().into_iter(),
+        };
+        let mut chunk_ids = chunk_ids.into_iter();
+        matrix.for_each_row(|row| {
+            if let Some(id) = chunk_ids.next() {
+                self.insert(id, row, attributes.next().unwrap_or_default());
+            }
+        });
+        Ok(())
+    }
+
+    /// Removes the embedding stored for `chunk_id`.
+    fn remove(&mut self, chunk_id: &str) -> PyResult<()> {
+        if !self.delete(chunk_id) {
+            return Err(PyKeyError::new_err(chunk_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Returns up to `k` `(chunk_id, score)` pairs, best first.
+    ///
+    /// `threshold` is a minimum score, or a maximum distance for `"l2"`;
+    /// `None` keeps every row. `filter` restricts the scan to rows whose
+    /// attributes match. The GIL is released while scoring so Python threads
+    /// keep running.
+    #[pyo3(signature = (query, k, threshold = None, filter = None, model = None))]
+    fn search(
+        &self,
+        py: Python<'_>,
+        query: VectorArg<'_>,
+        k: usize,
+        threshold: Option<f32>,
+        filter: Option<Filter>,
+        model: Option<&str>,
+    ) -> PyResult<Vec<(String, f32)>> {
+        check_model(self.model.as_deref(), model)?;
+        let query = query.to_f32();
+        self.check_dim(&query)?;
+        let hits = py.allow_threads(|| self.top_k(&query, k, threshold, filter.as_ref()));
+        Ok(self.with_ids(hits))
+    }
+
+    /// Searches one query per row of `queries` in parallel across CPU cores.
+    ///
+    /// Returns one list of `(chunk_id, score)` pairs per query, in query
+    /// order. The GIL is released for the whole batch.
+    #[pyo3(signature = (queries, k, threshold = None, filter = None, model = None))]
+    fn batch_search(
+        &self,
+        py: Python<'_>,
+        queries: MatrixArg<'_>,
+        k: usize,
+        threshold: Option<f32>,
+        filter: Option<Filter>,
+        model: Option<&str>,
+    ) -> PyResult<Vec<Vec<(String, f32)>>> {
+        check_model(self.model.as_deref(), model)?;
+        let queries = queries.view()?;
+        queries.check_dim(self.dim)?;
+        let queries = queries.to_f32_vec();
+        let hits: Vec<_> = py.allow_threads(|| {
+            queries
+                .par_chunks_exact(self.dim)
+                .map(|query| self.top_k(query, k, threshold, filter.as_ref()))
+                .collect()
+        });
+        Ok(hits.into_iter().map(|hits| self.with_ids(hits)).collect())
+    }
+
+    fn __len__(&self) -> usize {
+        self.len()
+    }
+
+    fn __contains__(&self, chunk_id: &str) -> bool {
+        self.positions.contains_key(chunk_id)
+    }
+}
@@ -0,0 +1,483 @@
+//! Parallel ingestion of a directory tree into chunks.
+//!
+//! The tree is walked in parallel, honouring `.gitignore`, `.ignore` and
+//! hidden-file rules, and every matching file is read, decoded and chunked
+//! on the rayon pool. Markdown is split along its headings, source code at
+//! definition boundaries and everything else with one of the
+//! [`chunk_text`](crate::chunk::chunk_text) strategies.
+
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chardetng::EncodingDetector;
+use encoding_rs::Encoding;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::overrides::OverrideBuilder;
+use ignore::{WalkBuilder, WalkState};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::chunk::{chunk_spans, Lines, Strategy, DEFAULT_SEPARATORS};
+use crate::code::{code_chunks, Language};
+use crate::documents::document_to_markdown;
+use crate::markdown::markdown_chunks;
+use crate::pdf::pdf_pages;
+
+/// Files whose first 8 KiB contain a NUL byte are treated as binary.
+const BINARY_SNIFF_LEN: usize = 8192;
+
+/// One chunk of an ingested file.
+#[pyclass(get_all, frozen)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IngestedChunk {
+    /// Path of the file relative to the ingested directory, `/`-separated.
+    source: String,
+    /// Position of the chunk within its file.
+    index: usize,
+    /// Byte range of the chunk in the file's text: decoded to UTF-8, or as
+    /// extracted from a PDF or converted from HTML, DOCX or EPUB.
+    start_byte: usize,
+    end_byte: usize,
+    /// 1-based, inclusive line range of the chunk.
+    start_line: usize,
+    end_line: usize,
+    text: String,
+    /// BLAKE3 hash of `text`, hex-encoded.
+    content_hash: String,
+    /// Name of the chunker that produced the chunk: `"markdown"`, `"code"`
+    /// or a [`chunk_text`](crate::chunk::chunk_text) strategy.
+    strategy: String,
+    /// Encoding the file was decoded from, e.g. `"UTF-8"` or
+    /// `"windows-1252"`, or the format text was extracted from: `"pdf"`,
+    /// `"html"`, `"docx"` or `"epub"`.
+    encoding: String,
+    /// 1-based page number for paginated documents such as PDFs.
+    page: Option<usize>,
+    /// Titles of the enclosing markdown headings, outermost first.
+    heading_path: Vec<String>,
+    /// Language and qualified symbol name for source code.
+    language: Option<String>,
+    symbol: Option<String>,
+}
+
+#[pymethods]
+impl IngestedChunk {
+    fn __repr__(&self) -> String {
+        format!(
+            "IngestedChunk(source={:?}, index={}, bytes={}..{})",
+            self.source, self.index, self.start_byte, self.end_byte
+        )
+    }
+}
+
+/// How files that are neither markdown nor source code are chunked.
+pub(crate) struct Chunking {
+    /// `None` selects the markdown chunker for markdown files and the
+    /// recursive strategy for other text.
+    strategy: Option<Strategy>,
+    size: usize,
+    overlap: usize,
+    separators: Vec<String>,
+}
+
+impl Chunking {
+    /// Identifies the settings and chunker version, so chunks produced
+    /// under different ones are never mistaken for current.
+    pub(crate) fn fingerprint(&self) -> String {
+        format!(
+            "{} {} {} {:?} {}",
+            self.strategy.map_or("markdown", strategy_name),
+            self.size,
+            self.overlap,
+            self.separators,
+            env!("CARGO_PKG_VERSION")
+        )
+    }
+}
+
+/// Validated arguments shared by the directory ingestion functions.
+pub(crate) struct Options {
+    pub(crate) globs: GlobSet,
+    pub(crate) ignore: Vec<String>,
+    pub(crate) chunking: Chunking,
+}
+
+impl Options {
+    pub(crate) fn new(
+        path: &Path,
+        globs: Option<Vec<String>>,
+        ignore: Option<Vec<String>>,
+        strategy: &str,
+        size: usize,
+        overlap: usize,
+        separators: Option<Vec<String>>,
+    ) -> PyResult<Self> {
+        if !path.is_dir() {
+            return Err(PyValueError::new_err(format!(
+                "not a directory: {}",
+                path.display()
+            )));
+        }
+        let strategy = match strategy {
+            "markdown" => None,
+            other => Some(Strategy::parse(other)?),
+        };
+        if size == 0 {
+            return Err(PyValueError::new_err("size must be positive"));
+        }
+        if overlap >= size {
+            return Err(PyValueError::new_err("overlap must be smaller than size"));
+        }
+        Ok(Self {
+            globs: glob_set(&globs.unwrap_or_default()).map_err(PyValueError::new_err)?,
+            ignore: ignore.unwrap_or_default(),
+            chunking: Chunking {
+                strategy,
+                size,
+                overlap,
+                separators: separators
+                    .unwrap_or_else(|| DEFAULT_SEPARATORS.iter().map(|s| s.to_string()).collect()),
+            },
+        })
+    }
+}
+
+/// A chunk before it is tied to its file.
+struct Piece {
+    span: Range<usize>,
+    strategy: &'static str,
+    heading_path: Vec<String>,
+    language: Option<Language>,
+    symbol: Option<String>,
+    page: Option<usize>,
+}
+
+impl Piece {
+    fn plain(span: Range<usize>, strategy: &'static str) -> Self {
+        Self {
+            span,
+            strategy,
+            heading_path: Vec::new(),
+            language: None,
+            symbol: None,
+            page: None,
+        }
+    }
+}
+
+/// A file's text and how it was obtained.
+struct Loaded {
+    text: String,
+    encoding: &'static str,
+    /// Byte range of each page in `text`, for paginated formats.
+    pages: Vec<Range<usize>>,
+    /// Whether `text` is markdown, either as written or converted from a
+    /// structured document.
+    markdown: bool,
+}
+
+fn strategy_name(strategy: Strategy) -> &'static str {
+    match strategy {
+        Strategy::Fixed => "fixed",
+        Strategy::Sentence => "sentence",
+        Strategy::Recursive => "recursive",
+        Strategy::Tokens => "tokens",
+    }
+}
+
+/// Decodes file contents to text, returning it with the name of the
+/// encoding, or `None` for binary files.
+///
+/// A byte-order mark decides the encoding; otherwise valid UTF-8 is taken
+/// as is and anything else is decoded with the encoding `chardetng` guesses.
+pub fn decode(bytes: &[u8]) -> Option<(String, &'static str)> {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+        return Some((text.into_owned(), encoding.name()));
+    }
+    if bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+        return None;
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Some((text.to_string(), "UTF-8"));
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, true);
+    let (text, _) = encoding.decode_without_bom_handling(bytes);
+    Some((text.into_owned(), encoding.name()))
+}
+
+/// Extracts the text of a file with extension `extension`, or returns
+/// `None` for binary files in no supported format.
+fn load(bytes: &[u8], extension: &str) -> Result<Option<Loaded>, String> {
+    if let Some(markdown) = document_to_markdown(extension, bytes) {
+        return Ok(Some(Loaded {
+            text: markdown?,
+            encoding: match extension {
+                "docx" => "docx",
+                "epub" => "epub",
+                _ => "html",
+            },
+            pages: Vec::new(),
+            markdown: true,
+        }));
+    }
+    if extension == "pdf" {
+        let mut text = String::new();
+        let mut pages = Vec::new();
+        for page in pdf_pages(bytes)? {
+            if !text.is_empty() {
+                text.push_str("\n\n");
+            }
+            let start = text.len();
+            text.push_str(&page);
+            pages.push(start..text.len());
+        }
+        return Ok(Some(Loaded {
+            text,
+            encoding: "pdf",
+            pages,
+            markdown: false,
+        }));
+    }
+    Ok(decode(bytes).map(|(text, encoding)| Loaded {
+        text,
+        encoding,
+        pages: Vec::new(),
+        markdown: matches!(extension, "md" | "markdown"),
+    }))
+}
+
+/// Chunks the text of a file with extension `extension`.
+fn split_file(
+    extension: &str,
+    text: &str,
+    markdown: bool,
+    chunking: &Chunking,
+) -> Result<Vec<Piece>, String> {
+    if let Some(language) = Language::from_extension(extension) {
+        return Ok(code_chunks(text, language, chunking.size)?
+            .into_iter()
+            .map(|(span, symbol, _, _)| Piece {
+                language: Some(language),
+                symbol,
+                ..Piece::plain(span, "code")
+            })
+            .collect());
+    }
+    let strategy = match chunking.strategy {
+        Some(strategy) => strategy,
+        None if markdown => {
+            return Ok(markdown_chunks(text, chunking.size)
+                .into_iter()
+                .map(|(span, heading_path)| Piece {
+                    heading_path,
+                    ..Piece::plain(span, "markdown")
+                })
+                .collect());
+        }
+        None => Strategy::Recursive,
+    };
+    Ok(chunk_spans(
+        text,
+        strategy,
+        chunking.size,
+        chunking.overlap,
+        &chunking.separators,
+    )
+    .into_iter()
+    .map(|span| Piece::plain(span, strategy_name(strategy)))
+    .collect())
+}
+
+/// Reads, decodes and chunks one file. Binary files yield no chunks.
+pub(crate) fn ingest_file(
+    root: &Path,
+    path: &Path,
+    chunking: &Chunking,
+) -> Result<Vec<IngestedChunk>, String> {
+    let source = relative_source(root, path);
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", source, e))?;
+    let Some(Loaded {
+        text,
+        encoding,
+        pages,
+        markdown,
+    }) = load(&bytes, &extension).map_err(|e| format!("{}: {}", source, e))?
+    else {
+        return Ok(Vec::new());
+    };
+    let split = |text: &str| {
+        split_file(&extension, text, markdown, chunking).map_err(|e| format!("{}: {}", source, e))
+    };
+    let pieces = if pages.is_empty() {
+        split(&text)?
+    } else {
+        // Pages are chunked separately so every chunk cites a single page
+        let mut pieces = Vec::new();
+        for (number, page) in pages.iter().enumerate() {
+            for piece in split(&text[page.clone()])? {
+                pieces.push(Piece {
+                    span: piece.span.start + page.start..piece.span.end + page.start,
+                    page: Some(number + 1),
+                    ..piece
+                });
+            }
+        }
+        pieces
+    };
+    let lines = Lines::new(&text);
+    Ok(pieces
+        .into_iter()
+        .enumerate()
+        .map(|(index, piece)| {
+            let chunk = &text[piece.span.clone()];
+            let (start_line, end_line) = lines.range(&piece.span);
+            IngestedChunk {
+                source: source.clone(),
+                index,
+                start_byte: piece.span.start,
+                end_byte: piece.span.end,
+                start_line,
+                end_line,
+                text: chunk.to_string(),
+                content_hash: blake3::hash(chunk.as_bytes()).to_hex().to_string(),
+                strategy: piece.strategy.to_string(),
+                encoding: encoding.to_string(),
+                heading_path: piece.heading_path,
+                language: piece.language.map(|l| l.name().to_string()),
+                symbol: piece.symbol,
+                page: piece.page,
+            }
+        })
+        .collect())
+}
+
+pub(crate) fn relative_source(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub(crate) fn glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).map_err(|e| e.to_string())?);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Walks `root` in parallel and returns the sorted paths of the files that
+/// match one of `globs` (all files when empty) and none of `ignore`, along
+/// with any errors met on the way.
+pub(crate) fn walk(
+    root: &Path,
+    globs: &GlobSet,
+    ignore: &[String],
+) -> Result<(Vec<PathBuf>, Vec<String>), String> {
+    let mut overrides = OverrideBuilder::new(root);
+    for pattern in ignore {
+        overrides
+            .add(&format!("!{}", pattern))
+            .map_err(|e| e.to_string())?;
+    }
+    let overrides = overrides.build().map_err(|e| e.to_string())?;
+
+    let paths = Mutex::new(Vec::new());
+    let errors = Mutex::new(Vec::new());
+    WalkBuilder::new(root)
+        .overrides(overrides)
+        // Honour .gitignore files even outside a git checkout
+        .require_git(false)
+        .build_parallel()
+        .run(|| {
+            Box::new(|entry| {
+                match entry {
+                    Ok(entry) if entry.file_type().is_some_and(|t| t.is_file()) => {
+                        let relative = relative_source(root, entry.path());
+                        if globs.is_empty() || globs.is_match(&relative) {
+                            paths.lock().unwrap().push(entry.into_path());
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => errors.lock().unwrap().push(e.to_string()),
+                }
+                WalkState::Continue
+            })
+        });
+    let mut paths = paths.into_inner().unwrap();
+    paths.sort();
+    Ok((paths, errors.into_inner().unwrap()))
+}
+
+/// Loads every matching file under `path` and chunks it.
+///
+/// `globs` selects files by their path relative to `path` (e.g. `"*.md"`,
+/// `"docs/**/*.txt"`; all files when omitted) and `ignore` holds extra
+/// gitignore-style patterns to skip (e.g. `"node_modules/"`), on top of the
+/// `.gitignore` files found in the tree. Hidden files and binary files are
+/// skipped, except for PDFs, whose text is extracted and chunked page by
+/// page, and HTML, DOCX and EPUB documents, which are converted to
+/// markdown. Markdown is split along its headings and Python, Rust,
+/// JavaScript and Go sources at definition boundaries; other files use
+/// `strategy` (`"markdown"` meaning `"recursive"` for them), `size`,
+/// `overlap` and `separators` as in `chunk_text`.
+///
+/// Returns the chunks of all files in path order and the errors for files
+/// that could not be read, as `"<source>: <message>"` strings.
+#[pyfunction]
+#[pyo3(signature = (
+    path,
+    globs = None,
+    ignore = None,
+    strategy = "markdown",
+    size = 1000,
+    overlap = 200,
+    separators = None
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn ingest_directory(
+    py: Python<'_>,
+    path: PathBuf,
+    globs: Option<Vec<String>>,
+    ignore: Option<Vec<String>>,
+    strategy: &str,
+    size: usize,
+    overlap: usize,
+    separators: Option<Vec<String>>,
+) -> PyResult<(Vec<IngestedChunk>, Vec<String>)> {
+    let Options {
+        globs,
+        ignore,
+        chunking,
+    } = Options::new(&path, globs, ignore, strategy, size, overlap, separators)?;
+
+    py.allow_threads(|| {
+        let (paths, mut errors) = walk(&path, &globs, &ignore).map_err(PyValueError::new_err)?;
+        let results: Vec<_> = paths
+            .par_iter()
+            .map(|file| ingest_file(&path, file, &chunking))
+            .collect();
+        let mut chunks = Vec::new();
+        for result in results {
+            match result {
+                Ok(file_chunks) => chunks.extend(file_chunks),
+                Err(e) => errors.push(e),
+            }
+        }
+        Ok((chunks, errors))
+    })
+}
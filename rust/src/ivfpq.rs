@@ -0,0 +1,509 @@
+//! Inverted-file index with product quantization (IVF-PQ).
+//!
+//! A coarse k-means quantizer splits the space into `nlist` cells. Each
+//! vector is stored in its nearest cell as a residual compressed by a product
+//! quantizer: the residual is cut into `m` sub-vectors and each is replaced by
+//! the index of its nearest sub-centroid, so a vector costs `m` bytes instead
+//! of `4 * dim`. Queries probe the `nprobe` nearest cells and score codes with
+//! asymmetric distance computation (ADC): the query stays uncompressed and
+//! distances are summed from per-cell lookup tables.
+//!
+//! Vectors are normalized before quantization, so squared distances map back
+//! to cosine similarity as `1 - d / 2`.
+
+use std::collections::HashMap;
+
+use pyo3::exceptions::{PyKeyError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use rayon::prelude::*;
+
+use crate::array::{MatrixArg, VectorArg};
+use crate::kbfile::{Reader, Writer};
+use crate::kmeans;
+use crate::metric::{require_cosine, Metric};
+use crate::provenance::{check_model, dimension_mismatch};
+use crate::rng::SplitMix64;
+use crate::search::{l2_squared, normalized, TopK};
+
+#[derive(Default)]
+struct InvertedList {
+    ids: Vec<String>,
+    codes: Vec<u8>,
+}
+
+/// A compressed, approximate cosine index over chunk embeddings.
+#[pyclass]
+pub struct IvfPqIndex {
+    dim: usize,
+    /// Embedding model the index was created for, if named.
+    pub(crate) model: Option<String>,
+    nlist: usize,
+    m: usize,
+    ksub: usize,
+    nprobe: usize,
+    rng: SplitMix64,
+    /// `nlist x dim` coarse centroids; empty until trained.
+    coarse: Vec<f32>,
+    /// `m` codebooks of `ksub x (dim / m)` sub-centroids, stored back to back.
+    codebooks: Vec<f32>,
+    lists: Vec<InvertedList>,
+    positions: HashMap<String, (usize, usize)>,
+}
+
+impl IvfPqIndex {
+    fn dsub(&self) -> usize {
+        self.dim / self.m
+    }
+
+    pub fn is_trained(&self) -> bool {
+        !self.coarse.is_empty()
+    }
+
+    fn codebook(&self, sub: usize) -> &[f32] {
+        let size = self.ksub * self.dsub();
+        &self.codebooks[sub * size..(sub + 1) * size]
+    }
+
+    /// Trains on the rows of `data`, discarding every stored vector. Needs
+    /// at least `max(nlist, ksub)` rows.
+    pub fn retrain(&mut self, data: &[f32], iterations: usize) -> Result<(), String> {
+        let rows = data.len() / self.dim;
+        let needed = self.nlist.max(self.ksub);
+        if rows < needed {
+            return Err(format!(
+                "training needs at least {} embeddings, got {}",
+                needed, rows
+            ));
+        }
+        self.fit(data, iterations);
+        self.lists = (0..self.nlist).map(|_| InvertedList::default()).collect();
+        self.positions.clear();
+        Ok(())
+    }
+
+    /// Trains the coarse quantizer on `data`, then one sub-quantizer per
+    /// sub-space on the residuals to the coarse centroids.
+    pub fn fit(&mut self, data: &[f32], iterations: usize) {
+        let data: Vec<f32> = data.chunks_exact(self.dim).flat_map(normalized).collect();
+        self.coarse = kmeans::train(&data, self.dim, self.nlist, iterations, &mut self.rng);
+
+        let residuals: Vec<f32> = data
+            .par_chunks_exact(self.dim)
+            .flat_map_iter(|row| self.residual(row, kmeans::nearest(&self.coarse, self.dim, row).0))
+            .collect();
+        let dsub = self.dsub();
+        let mut codebooks = Vec::with_capacity(self.m * self.ksub * dsub);
+        for sub in 0..self.m {
+            let slice: Vec<f32> = residuals
+                .chunks_exact(self.dim)
+                .flat_map(|row| row[sub * dsub..(sub + 1) * dsub].iter().copied())
+                .collect();
+            codebooks.extend(kmeans::train(
+                &slice,
+                dsub,
+                self.ksub,
+                iterations,
+                &mut self.rng,
+            ));
+        }
+        self.codebooks = codebooks;
+    }
+
+    fn residual(&self, vector: &[f32], cell: usize) -> Vec<f32> {
+        let centroid = &self.coarse[cell * self.dim..(cell + 1) * self.dim];
+        vector.iter().zip(centroid).map(|(v, c)| v - c).collect()
+    }
+
+    /// Returns the cell and PQ code for a vector.
+    fn encode(&self, vector: &[f32]) -> (usize, Vec<u8>) {
+        let vector = normalized(vector);
+        let cell = kmeans::nearest(&self.coarse, self.dim, &vector).0;
+        let residual = self.residual(&vector, cell);
+        let code = residual
+            .chunks_exact(self.dsub())
+            .enumerate()
+            .map(|(sub, part)| kmeans::nearest(self.codebook(sub), self.dsub(), part).0 as u8)
+            .collect();
+        (cell, code)
+    }
+
+    /// Stores a vector, replacing any previous vector for `id`.
+    pub fn insert(&mut self, id: String, vector: &[f32]) {
+        self.delete(&id);
+        let (cell, code) = self.encode(vector);
+        let list = &mut self.lists[cell];
+        self.positions.insert(id.clone(), (cell, list.ids.len()));
+        list.ids.push(id);
+        list.codes.extend(code);
+    }
+
+    /// Removes the vector for `id`. Returns false if `id` is unknown.
+    pub fn delete(&mut self, id: &str) -> bool {
+        let Some((cell, position)) = self.positions.remove(id) else {
+            return false;
+        };
+        let m = self.m;
+        let list = &mut self.lists[cell];
+        let last = list.ids.len() - 1;
+        if position != last {
+            list.codes
+                .copy_within(last * m..(last + 1) * m, position * m);
+            list.ids.swap(position, last);
+            self.positions
+                .insert(list.ids[position].clone(), (cell, position));
+        }
+        list.ids.pop();
+        list.codes.truncate(last * m);
+        true
+    }
+
+    /// Returns up to `k` `((cell, position), score)` pairs, best first.
+    pub fn top_k(&self, query: &[f32], k: usize, threshold: f32) -> Vec<((usize, usize), f32)> {
+        let query = normalized(query);
+        let mut cells: Vec<(usize, f32)> = self
+            .coarse
+            .chunks_exact(self.dim)
+            .map(|centroid| l2_squared(centroid, &query))
+            .enumerate()
+            .collect();
+        cells.sort_unstable_by(|a, b| a.1.total_cmp(&b.1));
+
+        let dsub = self.dsub();
+        let mut table = vec![0f32; self.m * self.ksub];
+        let mut top = TopK::new(k);
+        for &(cell, _) in cells.iter().take(self.nprobe) {
+            let list = &self.lists[cell];
+            if list.ids.is_empty() {
+                continue;
+            }
+            let residual = self.residual(&query, cell);
+            for (sub, part) in residual.chunks_exact(dsub).enumerate() {
+                for (code, centroid) in self.codebook(sub).chunks_exact(dsub).enumerate() {
+                    table[sub * self.ksub + code] = l2_squared(part, centroid);
+                }
+            }
+            for (position, code) in list.codes.chunks_exact(self.m).enumerate() {
+                let distance: f32 = code
+                    .iter()
+                    .enumerate()
+                    .map(|(sub, &c)| table[sub * self.ksub + c as usize])
+                    .sum();
+                let score = 1.0 - distance / 2.0;
+                if score >= threshold {
+                    top.push((cell, position), score);
+                }
+            }
+        }
+        top.into_sorted_vec()
+    }
+
+    fn check_dim(&self, vector: &[f32]) -> PyResult<()> {
+        if vector.len() != self.dim {
+            return Err(dimension_mismatch(vector.len(), self.dim));
+        }
+        Ok(())
+    }
+
+    fn check_trained(&self) -> PyResult<()> {
+        if !self.is_trained() {
+            return Err(PyRuntimeError::new_err("index must be trained before use"));
+        }
+        Ok(())
+    }
+
+    fn with_ids(&self, hits: Vec<((usize, usize), f32)>) -> Vec<(String, f32)> {
+        hits.into_iter()
+            .map(|((cell, position), score)| (self.lists[cell].ids[position].clone(), score))
+            .collect()
+    }
+
+    /// Encodes the quantizers and inverted lists for a knowledge base file.
+    pub fn write_to(&self, w: &mut Writer) {
+        w.usize(self.dim);
+        w.usize(self.nlist);
+        w.usize(self.m);
+        w.usize(self.ksub);
+        w.usize(self.nprobe);
+        w.u64(self.rng.0);
+        w.f32s(&self.coarse);
+        w.f32s(&self.codebooks);
+        for list in &self.lists {
+            w.usize(list.ids.len());
+            list.ids.iter().for_each(|id| w.str(id));
+            w.bytes(&list.codes);
+        }
+    }
+
+    /// Decodes an index written by [`write_to`](Self::write_to).
+    pub fn read_from(r: &mut Reader<'_>) -> Result<Self, String> {
+        let corrupt = || "corrupt IVF-PQ index".to_string();
+        let dim = r.usize()?;
+        let nlist = r.usize()?;
+        let m = r.usize()?;
+        let ksub = r.usize()?;
+        let nprobe = r.usize()?;
+        let rng = SplitMix64(r.u64()?);
+        let coarse = r.f32s()?;
+        let codebooks = r.f32s()?;
+        // An untrained index has neither quantizer, and so no vectors either
+        let trained = !coarse.is_empty();
+        if dim == 0
+            || nlist == 0
+            || m == 0
+            || nprobe == 0
+            || !dim.is_multiple_of(m)
+            || !(2..=256).contains(&ksub)
+            || trained == codebooks.is_empty()
+            || (trained && Some(coarse.len()) != nlist.checked_mul(dim))
+            || (trained && Some(codebooks.len()) != ksub.checked_mul(dim))
+        {
+            return Err(corrupt());
+        }
+        let mut lists = Vec::new();
+        let mut positions = HashMap::new();
+        for cell in 0..nlist {
+            let count = r.usize()?;
+            if count > 0 && !trained {
+                return Err(corrupt());
+            }
+            let mut ids = Vec::new();
+            for position in 0..count {
+                let id = r.string()?;
+                if positions.insert(id.clone(), (cell, position)).is_some() {
+                    return Err(format!("IVF-PQ index stores chunk {} twice", id));
+                }
+                ids.push(id);
+            }
+            let codes = r.bytes()?.to_vec();
+            if Some(codes.len()) != count.checked_mul(m)
+                || codes.iter().any(|&c| c as usize >= ksub)
+            {
+                return Err(corrupt());
+            }
+            lists.push(InvertedList { ids, codes });
+        }
+        Ok(Self {
+            dim,
+            model: None,
+            nlist,
+            m,
+            ksub,
+            nprobe,
+            rng,
+            coarse,
+            codebooks,
+            lists,
+            positions,
+        })
+    }
+}
+
+#[pymethods]
+impl IvfPqIndex {
+    /// Creates an untrained index.
+    ///
+    /// `nlist` is the number of coarse cells, `m` the number of sub-quantizers
+    /// (bytes per stored vector; must divide `dim`), `nbits` the bits per
+    /// sub-quantizer code (at most 8) and `nprobe` the number of cells
+    /// scanned per query. `model` names the embedding model, which calls
+    /// that name a model are checked against. `metric` must be `"cosine"`,
+    /// the only metric the quantizers are trained for.
+    #[new]
+    #[pyo3(signature = (dim, nlist = 256, m = 8, nbits = 8, nprobe = 8, seed = 42, model = None, metric = Metric::Cosine))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        dim: usize,
+        nlist: usize,
+        m: usize,
+        nbits: u32,
+        nprobe: usize,
+        seed: u64,
+        model: Option<String>,
+        metric: Metric,
+    ) -> PyResult<Self> {
+        require_cosine("IvfPqIndex", metric)?;
+        if dim == 0 || nlist == 0 || m == 0 || nprobe == 0 {
+            return Err(PyValueError::new_err(
+                "dim, nlist, m and nprobe must be positive",
+            ));
+        }
+        if !dim.is_multiple_of(m) {
+            return Err(PyValueError::new_err(format!(
+                "m ({}) must divide the dimension ({})",
+                m, dim
+            )));
+        }
+        if !(1..=8).contains(&nbits) {
+            return Err(PyValueError::new_err("nbits must be between 1 and 8"));
+        }
+        Ok(Self {
+            dim,
+            model,
+            nlist,
+            m,
+            ksub: 1 << nbits,
+            nprobe,
+            rng: SplitMix64(seed),
+            coarse: Vec::new(),
+            codebooks: Vec::new(),
+            lists: (0..nlist).map(|_| InvertedList::default()).collect(),
+            positions: HashMap::new(),
+        })
+    }
+
+    #[getter]
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Embedding model the index was created for, or `None`.
+    #[getter]
+    fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    #[getter]
+    fn nlist(&self) -> usize {
+        self.nlist
+    }
+
+    #[getter]
+    fn m(&self) -> usize {
+        self.m
+    }
+
+    /// Whether `train` has been called.
+    #[getter]
+    fn trained(&self) -> bool {
+        self.is_trained()
+    }
+
+    /// Number of cells scanned per query; may be tuned between queries.
+    #[getter]
+    fn nprobe(&self) -> usize {
+        self.nprobe
+    }
+
+    #[setter]
+    fn set_nprobe(&mut self, nprobe: usize) -> PyResult<()> {
+        if nprobe == 0 {
+            return Err(PyValueError::new_err("nprobe must be positive"));
+        }
+        self.nprobe = nprobe;
+        Ok(())
+    }
+
+    /// Trains the coarse and product quantizers with k-means on a sample of
+    /// embeddings. Needs at least `max(nlist, 2 ** nbits)` rows. Retraining
+    /// discards every stored vector.
+    #[pyo3(signature = (embeddings, iterations = 20))]
+    fn train(
+        &mut self,
+        py: Python<'_>,
+        embeddings: MatrixArg<'_>,
+        iterations: usize,
+    ) -> PyResult<()> {
+        let matrix = embeddings.view()?;
+        matrix.check_dim(self.dim)?;
+        let data = matrix.to_f32_vec();
+        py.allow_threads(|| self.retrain(&data, iterations))
+            .map_err(PyValueError::new_err)
+    }
+
+    /// Encodes and stores the embedding for `chunk_id`, replacing any previous one.
+    #[pyo3(signature = (chunk_id, embedding, model = None))]
+    fn add(
+        &mut self,
+        chunk_id: String,
+        embedding: VectorArg<'_>,
+        model: Option<&str>,
+    ) -> PyResult<()> {
+        check_model(self.model.as_deref(), model)?;
+        self.check_trained()?;
+        let embedding = embedding.to_f32();
+        self.check_dim(&embedding)?;
+        self.insert(chunk_id, &embedding);
+        Ok(())
+    }
+
+    /// Encodes and stores one embedding per chunk id from a 2-D matrix.
+    #[pyo3(signature = (chunk_ids, embeddings, model = None))]
+    fn add_many(
+        &mut self,
+        chunk_ids: Vec<String>,
+        embeddings: MatrixArg<'_>,
+        model: Option<&str>,
+    ) -> PyResult<()> {
+        check_model(self.model.as_deref(), model)?;
+        self.check_trained()?;
+        let matrix = embeddings.view()?;
+        matrix.check_rows(chunk_ids.len())?;
+        matrix.check_dim(self.dim)?;
+        let mut chunk_ids = chunk_ids.into_iter();
+        matrix.for_each_row(|row| {
+            if let Some(id) = chunk_ids.next() {
+                self.insert(id, row);
+            }
+        });
+        Ok(())
+    }
+
+    /// Removes the embedding stored for `chunk_id`.
+    fn remove(&mut self, chunk_id: &str) -> PyResult<()> {
+        if !self.delete(chunk_id) {
+            return Err(PyKeyError::new_err(chunk_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Returns up to `k` `(chunk_id, score)` pairs, best first, where the
+    /// score estimates cosine similarity from the compressed codes.
+    #[pyo3(signature = (query, k, threshold = f32::NEG_INFINITY, model = None))]
+    fn search(
+        &self,
+        py: Python<'_>,
+        query: VectorArg<'_>,
+        k: usize,
+        threshold: f32,
+        model: Option<&str>,
+    ) -> PyResult<Vec<(String, f32)>> {
+        check_model(self.model.as_deref(), model)?;
+        self.check_trained()?;
+        let query = query.to_f32();
+        self.check_dim(&query)?;
+        let hits = py.allow_threads(|| self.top_k(&query, k, threshold));
+        Ok(self.with_ids(hits))
+    }
+
+    /// Searches one query per row of `queries` in parallel across CPU cores.
+    #[pyo3(signature = (queries, k, threshold = f32::NEG_INFINITY, model = None))]
+    fn batch_search(
+        &self,
+        py: Python<'_>,
+        queries: MatrixArg<'_>,
+        k: usize,
+        threshold: f32,
+        model: Option<&str>,
+    ) -> PyResult<Vec<Vec<(String, f32)>>> {
+        check_model(self.model.as_deref(), model)?;
+        self.check_trained()?;
+        let queries = queries.view()?;
+        queries.check_dim(self.dim)?;
+        let queries = queries.to_f32_vec();
+        let hits: Vec<_> = py.allow_threads(|| {
+            queries
+                .par_chunks_exact(self.dim)
+                .map(|query| self.top_k(query, k, threshold))
+                .collect()
+        });
+        Ok(hits.into_iter().map(|hits| self.with_ids(hits)).collect())
+    }
+
+    fn __len__(&self) -> usize {
+        self.positions.len()
+    }
+
+    fn __contains__(&self, chunk_id: &str) -> bool {
+        self.positions.contains_key(chunk_id)
+    }
+}
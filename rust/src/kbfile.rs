@@ -0,0 +1,636 @@
+//! Versioned on-disk format for a knowledge base.
+//!
+//! A knowledge base file holds the chunks of a knowledge base (ids, sources,
+//! texts, JSON metadata and creation times), their embeddings and optionally a built HNSW or
+//! IVF-PQ index, so it can be reopened without re-embedding or re-indexing.
+//! Files are memory-mapped when opened: the embedding matrix is handed to
+//! NumPy without a copy and strings are only decoded when asked for.
+//!
+//! Layout, with every integer little-endian:
+//!
+//! ```text
+//! magic     8 bytes   b"LLKB\r\n\x1a\n"
+//! version   u32       FORMAT_VERSION
+//! dim       u32       embedding dimension, 0 without embeddings
+//! count     u64       number of chunks
+//! sections  u64       number of section table entries
+//! table     sections x (tag [u8; 8], offset u64, length u64)
+//! payloads  one per section, each starting at a multiple of 64 bytes
+//! ```
+//!
+//! `IDS`, `SOURCES`, `TEXTS` and `METADATA` are string columns: `count + 1`
+//! u64 offsets followed by the UTF-8 bytes they index. `CREATED` holds
+//! `count` f64 creation times in seconds since the Unix epoch. `EMBED` is the
+//! row-major `count x dim` f32 matrix, `INFO` a JSON object describing the
+//! knowledge base and `INDEX` an index kind (u32) followed by that index's
+//! encoding. `IDXMODEL` names the embedding model the index was created for,
+//! when it has one. Readers skip sections they do not know, so adding a section
+//! does not need a new version.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use memmap2::Mmap;
+use numpy::ndarray::{Array2, ArrayView2};
+use numpy::npyffi::flags::NPY_ARRAY_WRITEABLE;
+use numpy::{PyArray2, PyUntypedArrayMethods};
+use pyo3::exceptions::{PyFileNotFoundError, PyOSError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::array::{Matrix, MatrixArg};
+use crate::hnsw::HnswIndex;
+use crate::ivfpq::IvfPqIndex;
+
+const MAGIC: &[u8; 8] = b"LLKB\r\n\x1a\n";
+const FORMAT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 32;
+const ENTRY_LEN: usize = 24;
+/// Payload alignment, enough for any SIMD load from the embedding matrix.
+const ALIGN: usize = 64;
+
+const IDS: &[u8; 8] = b"IDS\0\0\0\0\0";
+const SOURCES: &[u8; 8] = b"SOURCES\0";
+const TEXTS: &[u8; 8] = b"TEXTS\0\0\0";
+const METADATA: &[u8; 8] = b"METADATA";
+const CREATED: &[u8; 8] = b"CREATED\0";
+const EMBED: &[u8; 8] = b"EMBED\0\0\0";
+const INFO: &[u8; 8] = b"INFO\0\0\0\0";
+const INDEX: &[u8; 8] = b"INDEX\0\0\0";
+const INDEX_MODEL: &[u8; 8] = b"IDXMODEL";
+
+const HNSW: u32 = 1;
+const IVFPQ: u32 = 2;
+
+/// Little-endian encoder for index structures.
+#[derive(Default)]
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    pub fn f64(&mut self, value: f64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a length-prefixed byte string.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.usize(bytes.len());
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    pub fn u32s(&mut self, values: &[u32]) {
+        self.usize(values.len());
+        values.iter().for_each(|&v| self.u32(v));
+    }
+
+    pub fn f32s(&mut self, values: &[f32]) {
+        self.usize(values.len());
+        for value in values {
+            self.buf.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+/// Decoder for what [`Writer`] wrote. Every read fails cleanly on
+/// truncated or corrupt input instead of panicking.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if len > self.bytes.len() {
+            return Err("truncated knowledge base file".to_string());
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn usize(&mut self) -> Result<usize, String> {
+        usize::try_from(self.u64()?).map_err(|e| e.to_string())
+    }
+
+    pub fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.usize()?;
+        self.take(len)
+    }
+
+    pub fn string(&mut self) -> Result<String, String> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())
+    }
+
+    /// Reads a length-prefixed array of `width`-byte values.
+    fn values(&mut self, width: usize) -> Result<&'a [u8], String> {
+        let len = self.usize()?;
+        let size = len
+            .checked_mul(width)
+            .ok_or("truncated knowledge base file")?;
+        self.take(size)
+    }
+
+    pub fn u32s(&mut self) -> Result<Vec<u32>, String> {
+        Ok(self
+            .values(4)?
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .collect())
+    }
+
+    pub fn f32s(&mut self) -> Result<Vec<f32>, String> {
+        Ok(self
+            .values(4)?
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect())
+    }
+}
+
+/// Encodes a string column.
+fn string_column(values: &[String]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(8 * (values.len() + 1));
+    let mut offset = 0u64;
+    out.extend_from_slice(&offset.to_le_bytes());
+    for value in values {
+        offset += value.len() as u64;
+        out.extend_from_slice(&offset.to_le_bytes());
+    }
+    for value in values {
+        out.extend_from_slice(value.as_bytes());
+    }
+    out
+}
+
+/// The embedding matrix as little-endian bytes, borrowed where possible.
+fn matrix_bytes<'a>(matrix: &'a Matrix<'_>) -> Cow<'a, [u8]> {
+    match matrix {
+        Matrix::F32(view) if cfg!(target_endian = "little") => {
+            let data: &[f32] = &view.data;
+            // SAFETY: any f32 is four initialised bytes, already in file order
+            Cow::Borrowed(unsafe {
+                std::slice::from_raw_parts(data.as_ptr().cast::<u8>(), std::mem::size_of_val(data))
+            })
+        }
+        _ => Cow::Owned(
+            matrix
+                .to_f32_vec()
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect(),
+        ),
+    }
+}
+
+fn padding(len: usize) -> usize {
+    (ALIGN - len % ALIGN) % ALIGN
+}
+
+/// Writes `sections` to `path`, replacing it only once fully written.
+fn write_file(
+    path: &Path,
+    dim: usize,
+    count: usize,
+    sections: &[(&[u8; 8], Cow<'_, [u8]>)],
+) -> io::Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = path.with_extension("partial");
+    let mut out = BufWriter::new(File::create(&partial)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    out.write_all(&(dim as u32).to_le_bytes())?;
+    out.write_all(&(count as u64).to_le_bytes())?;
+    out.write_all(&(sections.len() as u64).to_le_bytes())?;
+    let mut offset = HEADER_LEN + ENTRY_LEN * sections.len();
+    for (tag, payload) in sections {
+        offset += padding(offset);
+        out.write_all(*tag)?;
+        out.write_all(&(offset as u64).to_le_bytes())?;
+        out.write_all(&(payload.len() as u64).to_le_bytes())?;
+        offset += payload.len();
+    }
+    let mut written = HEADER_LEN + ENTRY_LEN * sections.len();
+    for (_, payload) in sections {
+        let pad = padding(written);
+        out.write_all(&[0; ALIGN][..pad])?;
+        out.write_all(payload)?;
+        written += pad + payload.len();
+    }
+    out.into_inner()?.sync_all()?;
+    fs::rename(&partial, path)
+}
+
+/// Writes a knowledge base to `path` in the versioned, memory-mappable
+/// format read by `load_kb`.
+///
+/// `chunk_ids`, `sources`, `texts` and `metadata` (one JSON document per
+/// chunk) must have the same length; `created_at`, if given, holds each
+/// chunk's creation time as a Unix timestamp and `embeddings` one row per
+/// chunk. `index` may be an `HnswIndex` or `IvfPqIndex` built over the
+/// embeddings, which is then stored as built; the flat and quantized
+/// indexes are cheap to rebuild from the embeddings and are not stored.
+/// `info` is a JSON object describing the knowledge base. The file is
+/// written next to `path` and renamed over it, so readers never see a
+/// partial file.
+#[pyfunction]
+#[pyo3(signature = (
+    path,
+    chunk_ids,
+    sources,
+    texts,
+    metadata,
+    embeddings = None,
+    index = None,
+    info = None,
+    created_at = None
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn save_kb(
+    py: Python<'_>,
+    path: PathBuf,
+    chunk_ids: Vec<String>,
+    sources: Vec<String>,
+    texts: Vec<String>,
+    metadata: Vec<String>,
+    embeddings: Option<MatrixArg<'_>>,
+    index: Option<&Bound<'_, PyAny>>,
+    info: Option<&str>,
+    created_at: Option<Vec<f64>>,
+) -> PyResult<()> {
+    let count = chunk_ids.len();
+    if sources.len() != count || texts.len() != count || metadata.len() != count {
+        return Err(PyValueError::new_err(
+            "chunk_ids, sources, texts and metadata must have the same length",
+        ));
+    }
+    if created_at
+        .as_ref()
+        .is_some_and(|times| times.len() != count)
+    {
+        return Err(PyValueError::new_err(
+            "created_at must have one timestamp per chunk",
+        ));
+    }
+    let matrix = embeddings.as_ref().map(MatrixArg::view).transpose()?;
+    if let Some(matrix) = &matrix {
+        matrix.check_rows(count)?;
+    }
+    let dim = matrix.as_ref().map_or(0, Matrix::cols);
+    if dim > u32::MAX as usize {
+        return Err(PyValueError::new_err("embedding dimension is too large"));
+    }
+
+    let index = match index {
+        None => None,
+        Some(index) => {
+            let mut writer = Writer::default();
+            let model = if let Ok(hnsw) = index.downcast::<HnswIndex>() {
+                writer.u32(HNSW);
+                let hnsw = hnsw.borrow();
+                hnsw.write_to(&mut writer);
+                hnsw.model.clone()
+            } else if let Ok(ivfpq) = index.downcast::<IvfPqIndex>() {
+                writer.u32(IVFPQ);
+                let ivfpq = ivfpq.borrow();
+                ivfpq.write_to(&mut writer);
+                ivfpq.model.clone()
+            } else {
+                return Err(PyValueError::new_err(
+                    "only HnswIndex and IvfPqIndex are stored; other indexes are rebuilt from the embeddings",
+                ));
+            };
+            Some((writer.buf, model))
+        }
+    };
+
+    py.allow_threads(|| {
+        let mut sections = vec![
+            (IDS, Cow::Owned(string_column(&chunk_ids))),
+            (SOURCES, Cow::Owned(string_column(&sources))),
+            (TEXTS, Cow::Owned(string_column(&texts))),
+            (METADATA, Cow::Owned(string_column(&metadata))),
+        ];
+        if let Some(times) = &created_at {
+            let bytes = times.iter().flat_map(|t| t.to_le_bytes()).collect();
+            sections.push((CREATED, Cow::Owned(bytes)));
+        }
+        if let Some(matrix) = &matrix {
+            sections.push((EMBED, matrix_bytes(matrix)));
+        }
+        if let Some(info) = info {
+            sections.push((INFO, Cow::Borrowed(info.as_bytes())));
+        }
+        if let Some((index, model)) = index {
+            sections.push((INDEX, Cow::Owned(index)));
+            if let Some(model) = model {
+                sections.push((INDEX_MODEL, Cow::Owned(model.into_bytes())));
+            }
+        }
+        write_file(&path, dim, count, &sections)
+    })
+    .map_err(|e| PyOSError::new_err(format!("{}: {}", path.display(), e)))
+}
+
+/// A knowledge base file opened by `load_kb`, memory-mapped read-only.
+#[pyclass(frozen)]
+pub struct KnowledgeBaseFile {
+    mmap: Mmap,
+    version: u32,
+    dim: usize,
+    count: usize,
+    sections: HashMap<[u8; 8], Range<usize>>,
+}
+
+impl KnowledgeBaseFile {
+    fn open(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        // SAFETY: the file is only replaced by renaming a new one over it,
+        // which leaves this mapping intact
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|e| e.to_string())?;
+        let mut header = Reader::new(&mmap);
+        if header.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err("not a knowledge base file".to_string());
+        }
+        let version = header.u32()?;
+        if version > FORMAT_VERSION {
+            return Err(format!(
+                "knowledge base file version {} is newer than the supported version {}",
+                version, FORMAT_VERSION
+            ));
+        }
+        let dim = header.u32()? as usize;
+        let count = header.usize()?;
+        let mut sections = HashMap::new();
+        for _ in 0..header.usize()? {
+            let tag = header.array::<8>()?;
+            let offset = header.usize()?;
+            let len = header.usize()?;
+            if offset.checked_add(len).is_none_or(|end| end > mmap.len()) {
+                return Err("truncated knowledge base file".to_string());
+            }
+            sections.insert(tag, offset..offset + len);
+        }
+        let file = Self {
+            mmap,
+            version,
+            dim,
+            count,
+            sections,
+        };
+        for tag in [IDS, SOURCES, TEXTS, METADATA] {
+            file.column_offsets(tag)?;
+        }
+        if file
+            .section(CREATED)
+            .is_some_and(|created| Some(created.len()) != count.checked_mul(8))
+        {
+            return Err("creation time section does not match the chunk count".to_string());
+        }
+        if let Some(embed) = file.section(EMBED) {
+            if Some(embed.len()) != count.checked_mul(dim).and_then(|n| n.checked_mul(4)) {
+                return Err("embedding section does not match its dimensions".to_string());
+            }
+        }
+        Ok(file)
+    }
+
+    fn section(&self, tag: &[u8; 8]) -> Option<&[u8]> {
+        self.sections
+            .get(tag)
+            .map(|range| &self.mmap[range.clone()])
+    }
+
+    /// Validates a string column, returning its offsets and data.
+    fn column_offsets(&self, tag: &[u8; 8]) -> Result<(&[u8], &[u8]), String> {
+        let name = String::from_utf8_lossy(tag)
+            .trim_end_matches('\0')
+            .to_string();
+        let section = self
+            .section(tag)
+            .ok_or_else(|| format!("missing {} section", name))?;
+        let table = (self.count + 1)
+            .checked_mul(8)
+            .filter(|&len| len <= section.len())
+            .ok_or_else(|| format!("truncated {} section", name))?;
+        let (offsets, data) = section.split_at(table);
+        let mut previous = 0;
+        for offset in offsets.chunks_exact(8) {
+            let offset = u64::from_le_bytes(offset.try_into().unwrap());
+            if offset < previous || offset > data.len() as u64 {
+                return Err(format!("corrupt {} section", name));
+            }
+            previous = offset;
+        }
+        Ok((offsets, data))
+    }
+
+    fn column(&self, tag: &[u8; 8]) -> PyResult<Vec<String>> {
+        let (offsets, data) = self.column_offsets(tag).map_err(PyValueError::new_err)?;
+        let offsets: Vec<usize> = offsets
+            .chunks_exact(8)
+            .map(|o| u64::from_le_bytes(o.try_into().unwrap()) as usize)
+            .collect();
+        offsets
+            .windows(2)
+            .map(|w| {
+                std::str::from_utf8(&data[w[0]..w[1]])
+                    .map(str::to_string)
+                    .map_err(|e| PyValueError::new_err(e.to_string()))
+            })
+            .collect()
+    }
+}
+
+#[pymethods]
+impl KnowledgeBaseFile {
+    /// Format version the file was written with.
+    #[getter]
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Embedding dimension, 0 when the file holds no embeddings.
+    #[getter]
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// The JSON object describing the knowledge base, if one was saved.
+    #[getter]
+    fn info(&self) -> PyResult<Option<String>> {
+        self.section(INFO)
+            .map(|info| {
+                std::str::from_utf8(info)
+                    .map(str::to_string)
+                    .map_err(|e| PyValueError::new_err(e.to_string()))
+            })
+            .transpose()
+    }
+
+    /// Returns `(chunk_id, source, text, metadata)` for every chunk, in
+    /// the order they were saved; `metadata` is the saved JSON document.
+    fn chunks(&self) -> PyResult<Vec<(String, String, String, String)>> {
+        let columns = [IDS, SOURCES, TEXTS, METADATA].map(|tag| self.column(tag));
+        let [ids, sources, texts, metadata] = columns;
+        Ok(ids?
+            .into_iter()
+            .zip(sources?)
+            .zip(texts?)
+            .zip(metadata?)
+            .map(|(((id, source), text), metadata)| (id, source, text, metadata))
+            .collect())
+    }
+
+    /// Each chunk's creation time as a Unix timestamp, in the order they
+    /// were saved, or `None` for files saved without them.
+    fn created_at(&self) -> Option<Vec<f64>> {
+        let created = self.section(CREATED)?;
+        Some(
+            created
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+                .collect(),
+        )
+    }
+
+    /// The `len(self) x dim` float32 embedding matrix, or `None` when the
+    /// file holds no embeddings.
+    ///
+    /// The array is a read-only view of the mapped file, so opening even a
+    /// large knowledge base copies nothing; it keeps the file mapped for as
+    /// long as it is alive.
+    fn embeddings<'py>(slf: &Bound<'py, Self>) -> Option<Bound<'py, PyArray2<f32>>> {
+        let this = slf.get();
+        let bytes = this.section(EMBED)?;
+        let shape = (this.count, this.dim);
+        let aligned = bytes.as_ptr().align_offset(std::mem::align_of::<f32>()) == 0;
+        if !aligned || cfg!(target_endian = "big") {
+            let values = bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect();
+            let array = Array2::from_shape_vec(shape, values).ok()?;
+            return Some(PyArray2::from_owned_array_bound(slf.py(), array));
+        }
+        // SAFETY: the bytes are aligned little-endian f32 values, and the
+        // mapping they live in is owned by `slf`, which the array keeps alive
+        let values = unsafe {
+            std::slice::from_raw_parts(bytes.as_ptr().cast::<f32>(), this.count * this.dim)
+        };
+        let view = ArrayView2::from_shape(shape, values).ok()?;
+        let array = unsafe { PyArray2::borrow_from_array_bound(&view, slf.clone().into_any()) };
+        // The mapping is read-only, so writes through the array must fail
+        unsafe { (*array.as_array_ptr()).flags &= !NPY_ARRAY_WRITEABLE };
+        Some(array)
+    }
+
+    /// The stored `HnswIndex` or `IvfPqIndex`, or `None`.
+    fn index(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let Some(section) = self.section(INDEX) else {
+            return Ok(None);
+        };
+        let mut reader = Reader::new(section);
+        let kind = reader.u32().map_err(PyValueError::new_err)?;
+        let model = match self.section(INDEX_MODEL) {
+            Some(bytes) => Some(
+                String::from_utf8(bytes.to_vec())
+                    .map_err(|_| PyValueError::new_err("index model is not valid UTF-8"))?,
+            ),
+            None => None,
+        };
+        let index = match kind {
+            HNSW => {
+                let mut index = py
+                    .allow_threads(|| HnswIndex::read_from(&mut reader))
+                    .map_err(PyValueError::new_err)?;
+                index.model = model;
+                Py::new(py, index)?.into_py(py)
+            }
+            IVFPQ => {
+                let mut index = py
+                    .allow_threads(|| IvfPqIndex::read_from(&mut reader))
+                    .map_err(PyValueError::new_err)?;
+                index.model = model;
+                Py::new(py, index)?.into_py(py)
+            }
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown index kind {}",
+                    other
+                )))
+            }
+        };
+        Ok(Some(index))
+    }
+
+    fn __len__(&self) -> usize {
+        self.count
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "KnowledgeBaseFile(version={}, chunks={}, dim={})",
+            self.version, self.count, self.dim
+        )
+    }
+}
+
+/// Opens a knowledge base file written by `save_kb`.
+///
+/// The file is memory-mapped and its header and string tables validated;
+/// chunks, embeddings and the index are read through the returned
+/// `KnowledgeBaseFile`. Raises `ValueError` for files that are corrupt or
+/// were written by a newer version.
+#[pyfunction]
+pub fn load_kb(py: Python<'_>, path: PathBuf) -> PyResult<KnowledgeBaseFile> {
+    if !path.is_file() {
+        return Err(PyFileNotFoundError::new_err(format!(
+            "{}: no such file",
+            path.display()
+        )));
+    }
+    py.allow_threads(|| KnowledgeBaseFile::open(&path))
+        .map_err(|e| PyValueError::new_err(format!("{}: {}", path.display(), e)))
+}
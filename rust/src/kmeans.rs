@@ -0,0 +1,73 @@
+//! Lloyd's k-means, used to train coarse and product quantizers.
+
+use rayon::prelude::*;
+
+use crate::rng::SplitMix64;
+use crate::search::l2_squared;
+
+/// Returns the index of the centroid nearest to `vector` and its squared
+/// distance. `centroids` is a row-major `k x dim` matrix with `k >= 1`.
+pub fn nearest(centroids: &[f32], dim: usize, vector: &[f32]) -> (usize, f32) {
+    centroids
+        .chunks_exact(dim)
+        .map(|centroid| l2_squared(centroid, vector))
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .expect("at least one centroid")
+}
+
+/// Clusters the rows of `data` into `k` groups and returns the `k x dim`
+/// centroid matrix.
+///
+/// Centroids start at `k` distinct random rows. A cluster that empties out
+/// is re-seeded from a random row so every centroid stays in use. Stops
+/// early once no assignment changes. Requires at least `k` rows.
+pub fn train(
+    data: &[f32],
+    dim: usize,
+    k: usize,
+    iterations: usize,
+    rng: &mut SplitMix64,
+) -> Vec<f32> {
+    let n = data.len() / dim;
+    let mut centroids: Vec<f32> = rng
+        .sample_indices(n, k)
+        .into_iter()
+        .flat_map(|i| data[i * dim..(i + 1) * dim].iter().copied())
+        .collect();
+    let mut assignments = vec![usize::MAX; n];
+
+    for _ in 0..iterations {
+        let next: Vec<usize> = data
+            .par_chunks_exact(dim)
+            .map(|row| nearest(&centroids, dim, row).0)
+            .collect();
+        if next == assignments {
+            break;
+        }
+        assignments = next;
+
+        let mut sums = vec![0f32; k * dim];
+        let mut counts = vec![0usize; k];
+        for (row, &cluster) in data.chunks_exact(dim).zip(&assignments) {
+            counts[cluster] += 1;
+            let sum = &mut sums[cluster * dim..(cluster + 1) * dim];
+            sum.iter_mut().zip(row).for_each(|(s, x)| *s += x);
+        }
+        for cluster in 0..k {
+            let centroid = &mut centroids[cluster * dim..(cluster + 1) * dim];
+            if counts[cluster] == 0 {
+                let i = rng.below(n);
+                centroid.copy_from_slice(&data[i * dim..(i + 1) * dim]);
+                continue;
+            }
+            let count = counts[cluster] as f32;
+            let sum = &sums[cluster * dim..(cluster + 1) * dim];
+            centroid
+                .iter_mut()
+                .zip(sum)
+                .for_each(|(c, s)| *c = s / count);
+        }
+    }
+    centroids
+}
@@ -1,24 +1,127 @@
+use numpy::PyArray1;
 use pyo3::prelude::*;
 
+mod array;
+mod bert;
+mod bm25;
+mod bpe;
+mod chunk;
+mod code;
+mod documents;
+mod embed;
+mod filter;
+mod hnsw;
+mod hybrid;
+mod index;
+mod ingest;
+mod ivfpq;
+mod kbfile;
+mod kmeans;
+mod manifest;
+mod markdown;
+mod metric;
+mod pdf;
+mod provenance;
+mod quantize;
+mod rerank;
+mod rng;
+mod search;
+mod simd;
+mod store;
+mod watch;
+
+use array::{Matrix, MatrixArg, VectorArg};
+
 /// Formats the sum of two numbers as a string.
 #[pyfunction]
 fn sum_as_string(a: usize, b: usize) -> PyResult<String> {
     Ok((a + b).to_string())
 }
 
-/// A placeholder function for potential computationally intensive tasks.
+/// Returns the `k` rows of `matrix` most similar to `query` by cosine
+/// similarity, as `(index, score)` pairs sorted best first.
+///
+/// Rows scoring below `threshold` are skipped during the scan, so the result
+/// may hold fewer than `k` pairs. `float32` and `float16` NumPy arrays are
+/// read in place without copying.
+#[pyfunction]
+#[pyo3(signature = (query, matrix, k, threshold = f32::NEG_INFINITY))]
+fn cosine_top_k(
+    query: VectorArg<'_>,
+    matrix: MatrixArg<'_>,
+    k: usize,
+    threshold: f32,
+) -> PyResult<Vec<(usize, f32)>> {
+    let query = query.to_f32();
+    let matrix = matrix.view()?;
+    matrix.check_dim(query.len())?;
+    Ok(match &matrix {
+        Matrix::F32(view) => search::cosine_top_k(&query, view.rows(), k, threshold),
+        Matrix::F16(view) => search::cosine_top_k(&query, view.rows(), k, threshold),
+    })
+}
+
+/// Returns the cosine similarity of `query` against every row of `matrix`
+/// as a 1-D `float32` NumPy array.
 #[pyfunction]
-fn accelerated_computation(data: Vec<i64>) -> PyResult<i64> {
-    // Replace with actual heavy computation (e.g., processing data)
-    let sum: i64 = data.iter().sum();
-    Ok(sum * 2) // Example operation
+fn cosine_similarities<'py>(
+    py: Python<'py>,
+    query: VectorArg<'_>,
+    matrix: MatrixArg<'_>,
+) -> PyResult<Bound<'py, PyArray1<f32>>> {
+    let query = query.to_f32();
+    let matrix = matrix.view()?;
+    matrix.check_dim(query.len())?;
+    let scores = match &matrix {
+        Matrix::F32(view) => search::cosine_all(&query, view.rows()),
+        Matrix::F16(view) => search::cosine_all(&query, view.rows()),
+    };
+    Ok(PyArray1::from_vec_bound(py, scores))
 }
 
+/// Returns the name of the distance kernel selected for this CPU:
+/// `"avx512"`, `"avx2"` or `"scalar"`.
+#[pyfunction]
+fn simd_kernel() -> &'static str {
+    simd::kernel().name
+}
 
 /// A Python module implemented in Rust.
 #[pymodule]
-fn llamasearch_experimentalagents_rust_lib(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(sum_as_string, m)?).unwrap();
-    m.add_function(wrap_pyfunction!(accelerated_computation, m)?).unwrap();
+fn llamasearch_experimentalagents_rust_lib(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(sum_as_string, m)?)?;
+    m.add_function(wrap_pyfunction!(cosine_top_k, m)?)?;
+    m.add_function(wrap_pyfunction!(cosine_similarities, m)?)?;
+    m.add_function(wrap_pyfunction!(simd_kernel, m)?)?;
+    provenance::register(m)?;
+    m.add_class::<index::EmbeddingIndex>()?;
+    m.add_class::<hnsw::HnswIndex>()?;
+    m.add_class::<ivfpq::IvfPqIndex>()?;
+    m.add_class::<quantize::QuantizedIndex>()?;
+    m.add_function(wrap_pyfunction!(quantize::quantize_int8, m)?)?;
+    m.add_function(wrap_pyfunction!(quantize::dequantize_int8, m)?)?;
+    m.add_function(wrap_pyfunction!(quantize::quantize_binary, m)?)?;
+    m.add_function(wrap_pyfunction!(quantize::dequantize_binary, m)?)?;
+    m.add_function(wrap_pyfunction!(quantize::rerank, m)?)?;
+    m.add_class::<bm25::Bm25Index>()?;
+    m.add_function(wrap_pyfunction!(bm25::py_tokenize, m)?)?;
+    m.add_function(wrap_pyfunction!(hybrid::fuse, m)?)?;
+    m.add_function(wrap_pyfunction!(chunk::chunk_text, m)?)?;
+    m.add_function(wrap_pyfunction!(markdown::chunk_markdown, m)?)?;
+    m.add_function(wrap_pyfunction!(code::chunk_code, m)?)?;
+    m.add_class::<ingest::IngestedChunk>()?;
+    m.add_function(wrap_pyfunction!(ingest::ingest_directory, m)?)?;
+    m.add_class::<manifest::IngestDelta>()?;
+    m.add_function(wrap_pyfunction!(manifest::ingest_changes, m)?)?;
+    m.add_class::<watch::DirectoryWatcher>()?;
+    m.add_class::<kbfile::KnowledgeBaseFile>()?;
+    m.add_function(wrap_pyfunction!(kbfile::save_kb, m)?)?;
+    m.add_function(wrap_pyfunction!(kbfile::load_kb, m)?)?;
+    m.add_class::<store::KnowledgeStore>()?;
+    m.add_class::<embed::TextEmbedder>()?;
+    m.add_class::<rerank::CrossEncoder>()?;
+    m.add_class::<bpe::BpeTokenizer>()?;
+    m.add_function(wrap_pyfunction!(pdf::extract_pdf_pages, m)?)?;
+    m.add_function(wrap_pyfunction!(documents::convert_document, m)?)?;
     Ok(())
-} 
\ No newline at end of file
+}
@@ -0,0 +1,250 @@
+//! Incremental ingestion against a manifest of previously ingested files.
+//!
+//! The manifest records the modification time, size and chunks of every
+//! file ingested from a directory. Files whose time and size are unchanged
+//! are not read again; the others are re-chunked and their chunks compared
+//! with the recorded ones, so callers only re-embed what actually changed.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ingest::{ingest_file, relative_source, walk, IngestedChunk, Options};
+
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Default, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    /// Fingerprint of the chunking settings the files were ingested with.
+    settings: String,
+    /// Ingested files by path relative to the directory.
+    files: BTreeMap<String, FileEntry>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct FileEntry {
+    /// Modification time in nanoseconds since the Unix epoch.
+    modified: u64,
+    size: u64,
+    chunks: Vec<IngestedChunk>,
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`; a missing file is an empty manifest.
+    fn read(path: &Path) -> Result<Self, String> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.to_string()),
+        };
+        let manifest: Self = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+        if manifest.version != MANIFEST_VERSION {
+            return Err(format!("unsupported manifest version {}", manifest.version));
+        }
+        Ok(manifest)
+    }
+
+    fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Renamed into place so an interrupted save never truncates it
+        let partial = path.with_extension("partial");
+        fs::write(&partial, serde_json::to_vec(self)?)?;
+        fs::rename(&partial, path)
+    }
+}
+
+/// Modification time and size of the file at `path`.
+fn stamp(path: &Path) -> io::Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    Ok((modified, metadata.len()))
+}
+
+/// The chunks of a directory compared with those recorded in its manifest.
+///
+/// Chunks are paired by file and position: a chunk that differs from the
+/// recorded chunk at the same position, in text or in any metadata, is
+/// changed. Text inserted early in a file therefore changes every later
+/// chunk, but their `content_hash` is unchanged wherever only the position
+/// moved, so embeddings can be reused by hash.
+#[pyclass(frozen)]
+pub struct IngestDelta {
+    /// Chunks at positions that did not exist before.
+    #[pyo3(get)]
+    added: Vec<IngestedChunk>,
+    /// `(old, new)` pairs of chunks that differ from the recorded ones.
+    #[pyo3(get)]
+    changed: Vec<(IngestedChunk, IngestedChunk)>,
+    /// Recorded chunks that no longer exist, including every chunk of
+    /// deleted files.
+    #[pyo3(get)]
+    deleted: Vec<IngestedChunk>,
+    /// Chunks identical to the recorded ones. Files that could not be read
+    /// keep their recorded chunks here.
+    #[pyo3(get)]
+    unchanged: Vec<IngestedChunk>,
+    /// `"<source>: <message>"` strings for files that could not be read.
+    #[pyo3(get)]
+    errors: Vec<String>,
+    manifest: Manifest,
+    path: PathBuf,
+}
+
+impl IngestDelta {
+    fn compare(&mut self, old: Vec<IngestedChunk>, new: &[IngestedChunk]) {
+        let mut old = old.into_iter();
+        for chunk in new {
+            match old.next() {
+                Some(previous) if previous == *chunk => self.unchanged.push(previous),
+                Some(previous) => self.changed.push((previous, chunk.clone())),
+                None => self.added.push(chunk.clone()),
+            }
+        }
+        self.deleted.extend(old);
+    }
+}
+
+#[pymethods]
+impl IngestDelta {
+    /// Writes the new state to the manifest, so the next `ingest_changes`
+    /// reports changes relative to it.
+    fn save(&self, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| self.manifest.write(&self.path))
+            .map_err(|e| PyOSError::new_err(format!("{}: {}", self.path.display(), e)))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "IngestDelta(added={}, changed={}, deleted={}, unchanged={})",
+            self.added.len(),
+            self.changed.len(),
+            self.deleted.len(),
+            self.unchanged.len()
+        )
+    }
+}
+
+/// Ingests the directory at `path` like `ingest_directory` and compares the
+/// result with the manifest at `manifest`.
+///
+/// Files whose modification time and size match the manifest are not read
+/// again. A missing manifest reports every chunk as added, and so does one
+/// that cannot be read, noting why in `errors`. Chunks recorded under other
+/// chunking settings are all reported deleted. The manifest is only updated by
+/// [`IngestDelta.save`](IngestDelta::save), once the caller has applied the
+/// delta.
+#[pyfunction]
+#[pyo3(signature = (
+    path,
+    manifest,
+    globs = None,
+    ignore = None,
+    strategy = "markdown",
+    size = 1000,
+    overlap = 200,
+    separators = None
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn ingest_changes(
+    py: Python<'_>,
+    path: PathBuf,
+    manifest: PathBuf,
+    globs: Option<Vec<String>>,
+    ignore: Option<Vec<String>>,
+    strategy: &str,
+    size: usize,
+    overlap: usize,
+    separators: Option<Vec<String>>,
+) -> PyResult<IngestDelta> {
+    let Options {
+        globs,
+        ignore,
+        chunking,
+    } = Options::new(&path, globs, ignore, strategy, size, overlap, separators)?;
+    let settings = chunking.fingerprint();
+
+    py.allow_threads(|| {
+        let (paths, errors) = walk(&path, &globs, &ignore).map_err(PyValueError::new_err)?;
+        let mut delta = IngestDelta {
+            added: Vec::new(),
+            changed: Vec::new(),
+            deleted: Vec::new(),
+            unchanged: Vec::new(),
+            errors,
+            manifest: Manifest {
+                version: MANIFEST_VERSION,
+                settings: settings.clone(),
+                files: BTreeMap::new(),
+            },
+            path: manifest.clone(),
+        };
+        let mut previous = match Manifest::read(&manifest) {
+            Ok(previous) if previous.settings == settings || previous.files.is_empty() => previous,
+            Ok(previous) => {
+                for entry in previous.files.into_values() {
+                    delta.deleted.extend(entry.chunks);
+                }
+                Manifest::default()
+            }
+            Err(e) => {
+                delta.errors.push(format!("{}: {}", manifest.display(), e));
+                Manifest::default()
+            }
+        };
+
+        let results: Vec<_> = paths
+            .par_iter()
+            .map(|file| {
+                let source = relative_source(&path, file);
+                let (modified, size) = stamp(file).map_err(|e| format!("{}: {}", source, e))?;
+                if let Some(entry) = previous.files.get(&source) {
+                    if entry.modified == modified && entry.size == size {
+                        return Ok(entry.clone());
+                    }
+                }
+                Ok(FileEntry {
+                    modified,
+                    size,
+                    chunks: ingest_file(&path, file, &chunking)?,
+                })
+            })
+            .collect();
+
+        for (file, result) in paths.iter().zip(results) {
+            let source = relative_source(&path, file);
+            let recorded = previous.files.remove(&source);
+            match result {
+                Ok(entry) => {
+                    let old = recorded.map(|r| r.chunks).unwrap_or_default();
+                    delta.compare(old, &entry.chunks);
+                    delta.manifest.files.insert(source, entry);
+                }
+                Err(e) => {
+                    delta.errors.push(e);
+                    if let Some(recorded) = recorded {
+                        delta.unchanged.extend(recorded.chunks.iter().cloned());
+                        delta.manifest.files.insert(source, recorded);
+                    }
+                }
+            }
+        }
+        // Whatever was not walked again has been deleted or is now ignored
+        for entry in previous.files.into_values() {
+            delta.deleted.extend(entry.chunks);
+        }
+        Ok(delta)
+    })
+}
@@ -0,0 +1,184 @@
+//! Markdown chunking along the document's heading structure.
+//!
+//! The document is cut into sections at every heading, so a chunk never
+//! spans two sections. Each section's top-level blocks (paragraphs, lists,
+//! code blocks, tables, ...) are packed into chunks of at most `size`
+//! characters. Headings, code blocks and tables are never split, even when
+//! larger than `size`; other blocks that do not fit are split like the
+//! `"recursive"` strategy of [`chunk_text`](crate::chunk::chunk_text).
+//! Every chunk carries the titles of the headings above it.
+
+use std::ops::Range;
+
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::chunk::{chunk_spans, pack, trim, Strategy, DEFAULT_SEPARATORS};
+
+enum Block {
+    Heading {
+        level: usize,
+        title: String,
+    },
+    /// Code blocks, tables, HTML and rules, kept whole.
+    Atomic,
+    Text,
+}
+
+/// A chunk's byte range and the titles of its enclosing headings, outermost
+/// first.
+pub type MarkdownChunk = (Range<usize>, Vec<String>);
+
+/// `(start, end, chunk, heading_path)` as returned to Python.
+type MarkdownTuple = (usize, usize, String, Vec<String>);
+
+/// Returns the document's top-level blocks with their byte ranges.
+fn blocks(text: &str) -> Vec<(Range<usize>, Block)> {
+    let mut blocks = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut atomic = false;
+    let mut title: Option<String> = None;
+    for (event, range) in Parser::new_ext(text, Options::ENABLE_TABLES).into_offset_iter() {
+        match event {
+            Event::Start(tag) => {
+                if depth == 0 {
+                    start = range.start;
+                    atomic = matches!(
+                        tag,
+                        Tag::CodeBlock(_) | Tag::Table(_) | Tag::HtmlBlock | Tag::MetadataBlock(_)
+                    );
+                    if matches!(tag, Tag::Heading { .. }) {
+                        title = Some(String::new());
+                    }
+                }
+                depth += 1;
+            }
+            Event::End(tag) => {
+                depth -= 1;
+                if depth > 0 {
+                    continue;
+                }
+                let block = match (tag, title.take()) {
+                    (TagEnd::Heading(level), Some(title)) => Block::Heading {
+                        level: level as usize,
+                        title: title.trim().to_string(),
+                    },
+                    _ if atomic => Block::Atomic,
+                    _ => Block::Text,
+                };
+                blocks.push((start..range.end, block));
+            }
+            Event::Text(s) | Event::Code(s) | Event::InlineMath(s) => {
+                if let Some(title) = title.as_mut() {
+                    title.push_str(&s);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some(title) = title.as_mut() {
+                    title.push(' ');
+                }
+            }
+            Event::Rule if depth == 0 => blocks.push((range, Block::Atomic)),
+            _ => {}
+        }
+    }
+    blocks
+}
+
+/// Packs the blocks of one section into chunk ranges.
+fn pack_section(text: &str, section: &[(Range<usize>, bool)], size: usize) -> Vec<Range<usize>> {
+    let separators: Vec<String> = DEFAULT_SEPARATORS.iter().map(|s| s.to_string()).collect();
+    let mut units: Vec<Range<usize>> = Vec::new();
+    for (span, atomic) in section {
+        if *atomic || text[span.clone()].chars().count() <= size {
+            units.push(span.clone());
+        } else {
+            let slice = &text[span.clone()];
+            units.extend(
+                chunk_spans(slice, Strategy::Recursive, size, 0, &separators)
+                    .into_iter()
+                    .map(|r| r.start + span.start..r.end + span.start),
+            );
+        }
+    }
+    // Stretch each unit up to the next so the gaps count towards `size`
+    for i in 1..units.len() {
+        units[i - 1].end = units[i].start;
+    }
+    pack(&units, |span| text[span.clone()].chars().count(), size, 0)
+}
+
+/// Chunks a markdown document, returning each chunk's byte range along
+/// with its heading path.
+///
+/// A section holding nothing but its heading yields no chunk; its title
+/// still appears in the heading path of the sections below it.
+pub fn markdown_chunks(text: &str, size: usize) -> Vec<MarkdownChunk> {
+    let mut chunks = Vec::new();
+    let mut path: Vec<(usize, String)> = Vec::new();
+    // Blocks of the current section, flagged when they must stay whole;
+    // the heading line itself is never split
+    let mut section: Vec<(Range<usize>, bool)> = Vec::new();
+    let mut has_body = false;
+
+    let mut flush =
+        |section: &mut Vec<(Range<usize>, bool)>, path: &[(usize, String)], has_body| {
+            if has_body {
+                let titles: Vec<String> = path.iter().map(|(_, title)| title.clone()).collect();
+                for span in pack_section(text, section, size) {
+                    let span = trim(text, span);
+                    if !span.is_empty() {
+                        chunks.push((span, titles.clone()));
+                    }
+                }
+            }
+            section.clear();
+        };
+
+    for (span, block) in blocks(text) {
+        match block {
+            Block::Heading { level, title } => {
+                flush(&mut section, &path, has_body);
+                has_body = false;
+                while path.last().is_some_and(|(l, _)| *l >= level) {
+                    path.pop();
+                }
+                path.push((level, title));
+                section.push((span, true));
+            }
+            Block::Atomic => {
+                has_body = true;
+                section.push((span, true));
+            }
+            Block::Text => {
+                has_body = true;
+                section.push((span, false));
+            }
+        }
+    }
+    flush(&mut section, &path, has_body);
+    chunks
+}
+
+/// Splits a markdown document into `(start, end, chunk, heading_path)`
+/// tuples, where `start..end` is the chunk's UTF-8 byte range in `text` and
+/// `heading_path` lists the titles of the enclosing headings, outermost
+/// first, e.g. `["Neural Networks", "Training", "Backprop"]`.
+///
+/// Chunks never cross a heading and hold at most `size` characters, except
+/// for headings, code blocks and tables, which are never split. The output depends
+/// only on the arguments.
+#[pyfunction]
+#[pyo3(signature = (text, size = 1000))]
+pub fn chunk_markdown(py: Python<'_>, text: &str, size: usize) -> PyResult<Vec<MarkdownTuple>> {
+    if size == 0 {
+        return Err(PyValueError::new_err("size must be positive"));
+    }
+    let chunks = py.allow_threads(|| markdown_chunks(text, size));
+    Ok(chunks
+        .into_iter()
+        .map(|(span, path)| (span.start, span.end, text[span].to_string(), path))
+        .collect())
+}
@@ -0,0 +1,105 @@
+//! Similarity metrics selectable per index.
+//!
+//! Every metric is ranked internally by a key where higher is better, so the
+//! same [`TopK`](crate::search::TopK) collector serves all of them. The score
+//! reported to Python, and the meaning of `threshold`, depend on the metric:
+//!
+//! | metric          | score                        | threshold          |
+//! |-----------------|------------------------------|--------------------|
+//! | `cosine`        | cosine similarity in [-1, 1] | minimum similarity |
+//! | `dot`           | raw dot product              | minimum product    |
+//! | `ip_normalized` | dot product of unit vectors  | minimum similarity |
+//! | `l2`            | Euclidean distance           | maximum distance   |
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::search::{cosine_with_norms, dot, l2_squared};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// Cosine similarity with norms cached per row.
+    Cosine,
+    /// Unnormalized inner product, for models trained with dot-product loss.
+    Dot,
+    /// Inner product of vectors normalized on insert. Ranks like cosine but
+    /// skips the norm division at query time.
+    NormalizedDot,
+    /// Euclidean distance; lower is better.
+    L2,
+}
+
+impl Metric {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "cosine" => Ok(Self::Cosine),
+            "dot" => Ok(Self::Dot),
+            "ip_normalized" => Ok(Self::NormalizedDot),
+            "l2" => Ok(Self::L2),
+            other => Err(PyValueError::new_err(format!(
+                "unknown metric {:?}, expected one of cosine, dot, ip_normalized, l2",
+                other
+            ))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Cosine => "cosine",
+            Self::Dot => "dot",
+            Self::NormalizedDot => "ip_normalized",
+            Self::L2 => "l2",
+        }
+    }
+
+    /// Whether stored rows and queries are scaled to unit length first.
+    pub fn normalizes(self) -> bool {
+        self == Self::NormalizedDot
+    }
+
+    /// Ranking key of `row` for `query`; higher is better. `row_norm` is only
+    /// read by [`Metric::Cosine`].
+    pub fn key(self, query: &[f32], query_norm: f32, row: &[f32], row_norm: f32) -> f32 {
+        match self {
+            Self::Cosine => cosine_with_norms(query, query_norm, row, row_norm),
+            Self::Dot | Self::NormalizedDot => dot(query, row),
+            Self::L2 => -l2_squared(query, row),
+        }
+    }
+
+    /// Converts a ranking key into the score reported to Python.
+    pub fn score(self, key: f32) -> f32 {
+        match self {
+            Self::L2 => (-key).sqrt(),
+            _ => key,
+        }
+    }
+
+    /// The lowest ranking key that satisfies `threshold`.
+    pub fn key_bound(self, threshold: Option<f32>) -> f32 {
+        match (self, threshold) {
+            (_, None) => f32::NEG_INFINITY,
+            (Self::L2, Some(max_distance)) => -(max_distance.max(0.0).powi(2)),
+            (_, Some(min_score)) => min_score,
+        }
+    }
+}
+
+/// Refuses any metric but cosine for `index`, an index whose graph, cells or
+/// codes are built around cosine similarity.
+pub fn require_cosine(index: &str, metric: Metric) -> PyResult<()> {
+    if metric != Metric::Cosine {
+        return Err(PyValueError::new_err(format!(
+            "{} only supports the cosine metric, not {}; use an EmbeddingIndex for {1}",
+            index,
+            metric.name()
+        )));
+    }
+    Ok(())
+}
+
+impl<'py> FromPyObject<'py> for Metric {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        Self::parse(ob.extract::<&str>()?)
+    }
+}
@@ -0,0 +1,27 @@
+//! Text extraction from PDF documents.
+
+use std::fs;
+use std::path::PathBuf;
+
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+
+/// Extracts the text of each page of a PDF document, in page order.
+pub fn pdf_pages(bytes: &[u8]) -> Result<Vec<String>, String> {
+    // The extractor panics on some malformed documents instead of failing
+    match std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(bytes)) {
+        Ok(Ok(pages)) => Ok(pages),
+        Ok(Err(e)) => Err(format!("cannot extract PDF text: {}", e)),
+        Err(_) => Err("cannot extract PDF text: malformed document".to_string()),
+    }
+}
+
+/// Returns the text of each page of the PDF file at `path`; page `n` is at
+/// index `n - 1`. Scanned pages without a text layer come back empty.
+#[pyfunction]
+pub fn extract_pdf_pages(py: Python<'_>, path: PathBuf) -> PyResult<Vec<String>> {
+    let bytes =
+        fs::read(&path).map_err(|e| PyOSError::new_err(format!("{}: {}", path.display(), e)))?;
+    py.allow_threads(|| pdf_pages(&bytes))
+        .map_err(PyValueError::new_err)
+}
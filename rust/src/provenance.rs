@@ -0,0 +1,71 @@
+//! Embedding model provenance for the native indexes.
+//!
+//! Embeddings of different models are not comparable, even when their
+//! dimensions happen to agree, so every index can record the model it was
+//! created for next to its dimension. Inserts and queries that name another
+//! model, or carry vectors of another dimension, fail with the typed errors
+//! below instead of returning meaningless scores. Indexes for different
+//! models can live side by side, e.g. while a corpus is re-embedded with a
+//! new model.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+create_exception!(
+    llamasearch_experimentalagents_rust_lib,
+    EmbeddingMismatchError,
+    PyValueError,
+    "Embeddings do not match the model or dimension an index was created for."
+);
+create_exception!(
+    llamasearch_experimentalagents_rust_lib,
+    DimensionMismatchError,
+    EmbeddingMismatchError,
+    "An embedding's dimension differs from the index's."
+);
+create_exception!(
+    llamasearch_experimentalagents_rust_lib,
+    ModelMismatchError,
+    EmbeddingMismatchError,
+    "Embeddings come from another model than the index's."
+);
+
+/// The error for a `got`-dimensional embedding where `expected` was due.
+pub fn dimension_mismatch(got: usize, expected: usize) -> PyErr {
+    DimensionMismatchError::new_err(format!(
+        "embedding has dimension {}, expected {}",
+        got, expected
+    ))
+}
+
+/// Fails if both the index's model and the caller's are named and differ.
+/// An index created without a model, or a call that names none, is not
+/// checked.
+pub fn check_model(index: Option<&str>, given: Option<&str>) -> PyResult<()> {
+    match (index, given) {
+        (Some(index), Some(given)) if index != given => Err(ModelMismatchError::new_err(format!(
+            "embeddings of {} do not belong in an index of {}",
+            given, index
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Registers the exception types on the module.
+pub fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add(
+        "EmbeddingMismatchError",
+        py.get_type_bound::<EmbeddingMismatchError>(),
+    )?;
+    m.add(
+        "DimensionMismatchError",
+        py.get_type_bound::<DimensionMismatchError>(),
+    )?;
+    m.add(
+        "ModelMismatchError",
+        py.get_type_bound::<ModelMismatchError>(),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,486 @@
+//! Scalar int8 and binary quantization of embeddings.
+//!
+//! Int8 codes scale each vector by its largest absolute component, cutting
+//! memory 4x. Binary codes keep one sign bit per dimension, cutting it 32x,
+//! and compare vectors by Hamming distance. Both lose some ranking quality,
+//! so searches can over-fetch candidates and re-rank them against the float
+//! embeddings with [`rerank`].
+
+use std::collections::HashMap;
+
+use numpy::{
+    PyArray1, PyArray2, PyArrayMethods, PyReadonlyArray1, PyReadonlyArray2, PyUntypedArrayMethods,
+};
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use rayon::prelude::*;
+
+use crate::array::{Matrix, MatrixArg, VectorArg};
+use crate::metric::{require_cosine, Metric};
+use crate::provenance::{check_model, dimension_mismatch};
+use crate::search::{self, cosine_with_norms, norm, TopK};
+
+/// Writes the int8 code of `vector` into `out` and returns its scale, so
+/// that `vector[i] ~= out[i] * scale`.
+pub fn quantize_int8_into(vector: &[f32], out: &mut [i8]) -> f32 {
+    let max_abs = vector.iter().fold(0f32, |m, x| m.max(x.abs()));
+    if max_abs == 0.0 {
+        out.fill(0);
+        return 0.0;
+    }
+    let scale = max_abs / 127.0;
+    for (o, x) in out.iter_mut().zip(vector) {
+        *o = (x / scale).round().clamp(-127.0, 127.0) as i8;
+    }
+    scale
+}
+
+/// Writes the sign bits of `vector` into `out`, most significant bit first,
+/// matching `numpy.packbits(vector > 0, axis=-1)`.
+pub fn quantize_binary_into(vector: &[f32], out: &mut [u8]) {
+    out.fill(0);
+    for (i, x) in vector.iter().enumerate() {
+        if *x > 0.0 {
+            out[i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+}
+
+/// Number of bytes in the binary code of a `dim`-dimensional vector.
+pub fn binary_code_len(dim: usize) -> usize {
+    dim.div_ceil(8)
+}
+
+/// Number of differing bits between two binary codes.
+pub fn hamming(a: &[u8], b: &[u8]) -> u32 {
+    a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+}
+
+fn as_i8(code: &[u8]) -> &[i8] {
+    // SAFETY: `u8` and `i8` have identical size and alignment.
+    unsafe { std::slice::from_raw_parts(code.as_ptr().cast(), code.len()) }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Int8,
+    Binary,
+}
+
+/// A brute-force index over int8 or binary codes.
+///
+/// Int8 scores are cosine similarities between the float query and the
+/// decoded vectors. Binary scores are `1 - 2 * hamming / dim`, which is 1
+/// for identical sign patterns and -1 for opposite ones.
+#[pyclass]
+pub struct QuantizedIndex {
+    dim: usize,
+    model: Option<String>,
+    kind: Kind,
+    code_len: usize,
+    codes: Vec<u8>,
+    /// Norm of each decoded int8 code; unused for binary codes.
+    norms: Vec<f32>,
+    ids: Vec<String>,
+    positions: HashMap<String, usize>,
+}
+
+impl QuantizedIndex {
+    fn code(&self, position: usize) -> &[u8] {
+        &self.codes[position * self.code_len..(position + 1) * self.code_len]
+    }
+
+    fn encode(&self, vector: &[f32], out: &mut [u8]) -> f32 {
+        match self.kind {
+            Kind::Int8 => {
+                let mut code = vec![0i8; self.dim];
+                quantize_int8_into(vector, &mut code);
+                out.iter_mut().zip(&code).for_each(|(o, c)| *o = *c as u8);
+                norm(&code)
+            }
+            Kind::Binary => {
+                quantize_binary_into(vector, out);
+                0.0
+            }
+        }
+    }
+
+    /// Inserts a code, overwriting the existing code if `id` is already indexed.
+    pub fn insert(&mut self, id: String, vector: &[f32]) {
+        let mut code = vec![0u8; self.code_len];
+        let code_norm = self.encode(vector, &mut code);
+        match self.positions.get(&id) {
+            Some(&position) => {
+                let start = position * self.code_len;
+                self.codes[start..start + self.code_len].copy_from_slice(&code);
+                self.norms[position] = code_norm;
+            }
+            None => {
+                self.positions.insert(id.clone(), self.ids.len());
+                self.ids.push(id);
+                self.codes.extend_from_slice(&code);
+                self.norms.push(code_norm);
+            }
+        }
+    }
+
+    /// Removes a code by moving the last code into its slot.
+    pub fn delete(&mut self, id: &str) -> bool {
+        let Some(position) = self.positions.remove(id) else {
+            return false;
+        };
+        let last = self.ids.len() - 1;
+        if position != last {
+            let width = self.code_len;
+            self.codes
+                .copy_within(last * width..(last + 1) * width, position * width);
+            self.ids.swap(position, last);
+            self.norms.swap(position, last);
+            self.positions.insert(self.ids[position].clone(), position);
+        }
+        self.ids.pop();
+        self.norms.pop();
+        self.codes.truncate(last * self.code_len);
+        true
+    }
+
+    /// Returns the best `(position, score)` pairs at or above `threshold`.
+    pub fn top_k(&self, query: &[f32], k: usize, threshold: f32) -> Vec<(usize, f32)> {
+        let mut top = TopK::new(k);
+        match self.kind {
+            Kind::Int8 => {
+                let query_norm = norm(query);
+                for position in 0..self.ids.len() {
+                    let code = as_i8(self.code(position));
+                    let score = cosine_with_norms(query, query_norm, code, self.norms[position]);
+                    if score >= threshold {
+                        top.push(position, score);
+                    }
+                }
+            }
+            Kind::Binary => {
+                let mut code = vec![0u8; self.code_len];
+                quantize_binary_into(query, &mut code);
+                for position in 0..self.ids.len() {
+                    let distance = hamming(&code, self.code(position));
+                    let score = 1.0 - 2.0 * distance as f32 / self.dim as f32;
+                    if score >= threshold {
+                        top.push(position, score);
+                    }
+                }
+            }
+        }
+        top.into_sorted_vec()
+    }
+
+    fn check_dim(&self, vector: &[f32]) -> PyResult<()> {
+        if vector.len() != self.dim {
+            return Err(dimension_mismatch(vector.len(), self.dim));
+        }
+        Ok(())
+    }
+
+    fn with_ids(&self, hits: Vec<(usize, f32)>) -> Vec<(String, f32)> {
+        hits.into_iter()
+            .map(|(position, score)| (self.ids[position].clone(), score))
+            .collect()
+    }
+}
+
+#[pymethods]
+impl QuantizedIndex {
+    /// Creates an empty index storing `"int8"` or `"binary"` codes for
+    /// embeddings of `model`, if named. `metric` must be `"cosine"`, the
+    /// only metric the codes are scored with.
+    #[new]
+    #[pyo3(signature = (dim, kind = "int8", model = None, metric = Metric::Cosine))]
+    fn new(dim: usize, kind: &str, model: Option<String>, metric: Metric) -> PyResult<Self> {
+        require_cosine("QuantizedIndex", metric)?;
+        if dim == 0 {
+            return Err(PyValueError::new_err("dimension must be positive"));
+        }
+        let (kind, code_len) = match kind {
+            "int8" => (Kind::Int8, dim),
+            "binary" => (Kind::Binary, binary_code_len(dim)),
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown quantization kind: {}",
+                    other
+                )))
+            }
+        };
+        Ok(Self {
+            dim,
+            model,
+            kind,
+            code_len,
+            codes: Vec::new(),
+            norms: Vec::new(),
+            ids: Vec::new(),
+            positions: HashMap::new(),
+        })
+    }
+
+    #[getter]
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Embedding model the index was created for, or `None`.
+    #[getter]
+    fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    #[getter]
+    fn kind(&self) -> &'static str {
+        match self.kind {
+            Kind::Int8 => "int8",
+            Kind::Binary => "binary",
+        }
+    }
+
+    /// Bytes of code memory held per stored embedding.
+    #[getter]
+    fn bytes_per_vector(&self) -> usize {
+        self.code_len
+    }
+
+    /// Quantizes and stores the embedding for `chunk_id`, replacing any previous one.
+    #[pyo3(signature = (chunk_id, embedding, model = None))]
+    fn add(
+        &mut self,
+        chunk_id: String,
+        embedding: VectorArg<'_>,
+        model: Option<&str>,
+    ) -> PyResult<()> {
+        check_model(self.model.as_deref(), model)?;
+        let embedding = embedding.to_f32();
+        self.check_dim(&embedding)?;
+        self.insert(chunk_id, &embedding);
+        Ok(())
+    }
+
+    /// Quantizes and stores one embedding per chunk id from a 2-D matrix.
+    #[pyo3(signature = (chunk_ids, embeddings, model = None))]
+    fn add_many(
+        &mut self,
+        chunk_ids: Vec<String>,
+        embeddings: MatrixArg<'_>,
+        model: Option<&str>,
+    ) -> PyResult<()> {
+        check_model(self.model.as_deref(), model)?;
+        let matrix = embeddings.view()?;
+        matrix.check_rows(chunk_ids.len())?;
+        matrix.check_dim(self.dim)?;
+        let mut chunk_ids = chunk_ids.into_iter();
+        matrix.for_each_row(|row| {
+            if let Some(id) = chunk_ids.next() {
+                self.insert(id, row);
+            }
+        });
+        Ok(())
+    }
+
+    /// Removes the code stored for `chunk_id`.
+    fn remove(&mut self, chunk_id: &str) -> PyResult<()> {
+        if !self.delete(chunk_id) {
+            return Err(PyKeyError::new_err(chunk_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Returns up to `k` `(chunk_id, score)` pairs scored on the codes, best first.
+    #[pyo3(signature = (query, k, threshold = f32::NEG_INFINITY, model = None))]
+    fn search(
+        &self,
+        py: Python<'_>,
+        query: VectorArg<'_>,
+        k: usize,
+        threshold: f32,
+        model: Option<&str>,
+    ) -> PyResult<Vec<(String, f32)>> {
+        check_model(self.model.as_deref(), model)?;
+        let query = query.to_f32();
+        self.check_dim(&query)?;
+        let hits = py.allow_threads(|| self.top_k(&query, k, threshold));
+        Ok(self.with_ids(hits))
+    }
+
+    /// Searches one query per row of `queries` in parallel across CPU cores.
+    #[pyo3(signature = (queries, k, threshold = f32::NEG_INFINITY, model = None))]
+    fn batch_search(
+        &self,
+        py: Python<'_>,
+        queries: MatrixArg<'_>,
+        k: usize,
+        threshold: f32,
+        model: Option<&str>,
+    ) -> PyResult<Vec<Vec<(String, f32)>>> {
+        check_model(self.model.as_deref(), model)?;
+        let queries = queries.view()?;
+        queries.check_dim(self.dim)?;
+        let queries = queries.to_f32_vec();
+        let hits: Vec<_> = py.allow_threads(|| {
+            queries
+                .par_chunks_exact(self.dim)
+                .map(|query| self.top_k(query, k, threshold))
+                .collect()
+        });
+        Ok(hits.into_iter().map(|hits| self.with_ids(hits)).collect())
+    }
+
+    fn __len__(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn __contains__(&self, chunk_id: &str) -> bool {
+        self.positions.contains_key(chunk_id)
+    }
+}
+
+/// Int8 codes and their per-row scales.
+type Int8Codes<'py> = (Bound<'py, PyArray2<i8>>, Bound<'py, PyArray1<f32>>);
+
+/// Quantizes each row of `matrix` to int8.
+///
+/// Returns `(codes, scales)` where `codes` is an `int8` array of the same
+/// shape and `matrix[i] ~= codes[i] * scales[i]`.
+#[pyfunction]
+pub fn quantize_int8<'py>(py: Python<'py>, matrix: MatrixArg<'_>) -> PyResult<Int8Codes<'py>> {
+    let matrix = matrix.view()?;
+    let (rows, cols) = (matrix.rows(), matrix.cols());
+    let mut codes = vec![0i8; rows * cols];
+    let mut scales = Vec::with_capacity(rows);
+    let mut out = codes.chunks_exact_mut(cols.max(1));
+    matrix.for_each_row(|row| {
+        if let Some(code) = out.next() {
+            scales.push(quantize_int8_into(row, code));
+        }
+    });
+    Ok((
+        to_pyarray2(py, codes, rows, cols)?,
+        PyArray1::from_vec_bound(py, scales),
+    ))
+}
+
+/// Reconstructs `float32` rows from int8 codes and per-row scales.
+#[pyfunction]
+pub fn dequantize_int8<'py>(
+    py: Python<'py>,
+    codes: PyReadonlyArray2<'_, i8>,
+    scales: PyReadonlyArray1<'_, f32>,
+) -> PyResult<Bound<'py, PyArray2<f32>>> {
+    let [rows, cols] = [codes.shape()[0], codes.shape()[1]];
+    if scales.len() != rows {
+        return Err(PyValueError::new_err(format!(
+            "got {} scales for {} codes",
+            scales.len(),
+            rows
+        )));
+    }
+    let codes = codes.as_array();
+    let scales = scales.as_array();
+    let data = codes
+        .rows()
+        .into_iter()
+        .zip(scales.iter())
+        .flat_map(|(row, scale)| {
+            row.iter()
+                .map(move |c| *c as f32 * scale)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    to_pyarray2(py, data, rows, cols)
+}
+
+/// Packs the sign bit of every component of each row, most significant bit
+/// first. Returns a `uint8` array with `ceil(dim / 8)` columns.
+#[pyfunction]
+pub fn quantize_binary<'py>(
+    py: Python<'py>,
+    matrix: MatrixArg<'_>,
+) -> PyResult<Bound<'py, PyArray2<u8>>> {
+    let matrix = matrix.view()?;
+    let rows = matrix.rows();
+    let width = binary_code_len(matrix.cols());
+    let mut codes = vec![0u8; rows * width];
+    let mut out = codes.chunks_exact_mut(width.max(1));
+    matrix.for_each_row(|row| {
+        if let Some(code) = out.next() {
+            quantize_binary_into(row, code);
+        }
+    });
+    to_pyarray2(py, codes, rows, width)
+}
+
+/// Unpacks binary codes into `float32` rows of +1 and -1.
+#[pyfunction]
+pub fn dequantize_binary<'py>(
+    py: Python<'py>,
+    codes: PyReadonlyArray2<'_, u8>,
+    dim: usize,
+) -> PyResult<Bound<'py, PyArray2<f32>>> {
+    let rows = codes.shape()[0];
+    if codes.shape()[1] != binary_code_len(dim) {
+        return Err(PyValueError::new_err(format!(
+            "codes have {} bytes per row, expected {} for dimension {}",
+            codes.shape()[1],
+            binary_code_len(dim),
+            dim
+        )));
+    }
+    let codes = codes.as_array();
+    let data = codes
+        .rows()
+        .into_iter()
+        .flat_map(|row| {
+            (0..dim)
+                .map(|i| {
+                    if row[i / 8] & (0x80 >> (i % 8)) != 0 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                })
+                .collect::<Vec<f32>>()
+        })
+        .collect();
+    to_pyarray2(py, data, rows, dim)
+}
+
+/// Re-scores candidate chunks against their float embeddings.
+///
+/// `embeddings[i]` must be the embedding of `chunk_ids[i]`. Returns up to
+/// `k` `(chunk_id, score)` pairs by exact cosine similarity, best first.
+#[pyfunction]
+#[pyo3(signature = (query, chunk_ids, embeddings, k, threshold = f32::NEG_INFINITY))]
+pub fn rerank(
+    query: VectorArg<'_>,
+    chunk_ids: Vec<String>,
+    embeddings: MatrixArg<'_>,
+    k: usize,
+    threshold: f32,
+) -> PyResult<Vec<(String, f32)>> {
+    let query = query.to_f32();
+    let matrix = embeddings.view()?;
+    matrix.check_rows(chunk_ids.len())?;
+    matrix.check_dim(query.len())?;
+    let hits = match &matrix {
+        Matrix::F32(view) => search::cosine_top_k(&query, view.rows(), k, threshold),
+        Matrix::F16(view) => search::cosine_top_k(&query, view.rows(), k, threshold),
+    };
+    Ok(hits
+        .into_iter()
+        .map(|(i, score)| (chunk_ids[i].clone(), score))
+        .collect())
+}
+
+fn to_pyarray2<T: numpy::Element>(
+    py: Python<'_>,
+    data: Vec<T>,
+    rows: usize,
+    cols: usize,
+) -> PyResult<Bound<'_, PyArray2<T>>> {
+    PyArray1::from_vec_bound(py, data).reshape([rows, cols])
+}
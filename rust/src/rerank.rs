@@ -0,0 +1,148 @@
+//! Cross-encoder reranking on the CPU.
+//!
+//! A cross-encoder reads a query and a passage together and scores how well
+//! the passage answers the query. That is far slower than comparing
+//! embeddings, so it only reorders the candidates a vector search found, but
+//! it also ranks passages that answer the query in other words than it uses.
+//! Loads BERT sequence classification checkpoints with a single output, such
+//! as `cross-encoder/ms-marco-MiniLM-L-6-v2`, from a local [`Checkpoint`].
+
+use std::path::PathBuf;
+
+use candle_core::Module;
+use candle_nn::{linear, Linear};
+use candle_transformers::models::bert::BertModel;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use tokenizers::{EncodeInput, Encoding, Tokenizer};
+
+use crate::bert::{batch_tensors, by_length, Checkpoint};
+use crate::search::TopK;
+
+/// A cross-encoder model that scores (query, passage) pairs locally.
+#[pyclass]
+pub struct CrossEncoder {
+    name: String,
+    model: BertModel,
+    pooler: Linear,
+    classifier: Linear,
+    tokenizer: Tokenizer,
+    batch_size: usize,
+}
+
+impl CrossEncoder {
+    /// Scores one padded batch of pairs, from 0 for irrelevant to 1.
+    fn score_batch(&self, encodings: &[Encoding]) -> candle_core::Result<Vec<f32>> {
+        let (ids, type_ids, mask) = batch_tensors(encodings, &self.model.device)?;
+        let states = self.model.forward(&ids, &type_ids, Some(&mask))?;
+        let first = states.narrow(1, 0, 1)?.squeeze(1)?;
+        let pooled = self.pooler.forward(&first)?.tanh()?;
+        let logits = self.classifier.forward(&pooled)?.squeeze(1)?;
+        candle_nn::ops::sigmoid(&logits)?.to_vec1()
+    }
+
+    /// Scores `passages` against `query`, in passage order.
+    pub fn score_pairs(&self, query: &str, passages: &[String]) -> Result<Vec<f32>, String> {
+        let order = by_length(passages.iter().map(String::len));
+        let mut scores = vec![0.0; passages.len()];
+        for batch in order.chunks(self.batch_size) {
+            let inputs: Vec<EncodeInput> = batch
+                .iter()
+                .map(|&i| (query, passages[i].as_str()).into())
+                .collect();
+            let encodings = self
+                .tokenizer
+                .encode_batch(inputs, true)
+                .map_err(|e| e.to_string())?;
+            let batch_scores = self.score_batch(&encodings).map_err(|e| e.to_string())?;
+            for (&i, score) in batch.iter().zip(batch_scores) {
+                scores[i] = score;
+            }
+        }
+        Ok(scores)
+    }
+}
+
+#[pymethods]
+impl CrossEncoder {
+    /// Loads the model in directory `path`.
+    ///
+    /// Query and passage together are truncated to `max_length` tokens, or
+    /// the model's own limit if lower, and `batch_size` pairs run at a time.
+    #[new]
+    #[pyo3(signature = (path, max_length = 512, batch_size = 16, name = None))]
+    fn new(
+        path: PathBuf,
+        max_length: usize,
+        batch_size: usize,
+        name: Option<String>,
+    ) -> PyResult<Self> {
+        if batch_size == 0 {
+            return Err(PyValueError::new_err("batch_size must be positive"));
+        }
+        let checkpoint = Checkpoint::open(path, max_length)?;
+        let config = &checkpoint.config;
+        let weights = &checkpoint.weights;
+        let model =
+            BertModel::load(weights.clone(), config).map_err(|e| checkpoint.invalid_weights(e))?;
+        // The pooler sits next to the encoder, under the model type prefix if it has one
+        let pooler_weights = match &config.model_type {
+            Some(prefix) if weights.contains_tensor(&format!("{}.pooler.dense.weight", prefix)) => {
+                weights.pp(prefix).pp("pooler.dense")
+            }
+            _ => weights.pp("pooler.dense"),
+        };
+        let pooler = linear(config.hidden_size, config.hidden_size, pooler_weights)
+            .map_err(|e| checkpoint.invalid_weights(e))?;
+        let classifier = linear(config.hidden_size, 1, weights.pp("classifier")).map_err(|e| {
+            checkpoint.invalid_weights(format!(
+                "not a cross-encoder with a single relevance output ({})",
+                e
+            ))
+        })?;
+        Ok(Self {
+            name: checkpoint.name(name),
+            model,
+            pooler,
+            classifier,
+            tokenizer: checkpoint.tokenizer,
+            batch_size,
+        })
+    }
+
+    #[getter]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the relevance of each passage to `query`, between 0 and 1,
+    /// in passage order. The GIL is released while the model runs.
+    fn score(&self, py: Python<'_>, query: &str, passages: Vec<String>) -> PyResult<Vec<f32>> {
+        py.allow_threads(|| self.score_pairs(query, &passages))
+            .map_err(PyRuntimeError::new_err)
+    }
+
+    /// Returns up to `top_k` `(position, score)` pairs for the passages most
+    /// relevant to `query`, best first; every passage when `top_k` is `None`.
+    #[pyo3(signature = (query, passages, top_k = None))]
+    fn rerank(
+        &self,
+        py: Python<'_>,
+        query: &str,
+        passages: Vec<String>,
+        top_k: Option<usize>,
+    ) -> PyResult<Vec<(usize, f32)>> {
+        let scores = py
+            .allow_threads(|| self.score_pairs(query, &passages))
+            .map_err(PyRuntimeError::new_err)?;
+        let mut top = TopK::new(top_k.unwrap_or(passages.len()));
+        for (position, score) in scores.into_iter().enumerate() {
+            top.push(position, score);
+        }
+        Ok(top.into_sorted_vec())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("CrossEncoder(name={:?})", self.name)
+    }
+}
@@ -0,0 +1,38 @@
+//! Small deterministic random number generator.
+//!
+//! Index construction only needs reproducible, well-mixed draws, so a
+//! seeded SplitMix64 is used instead of pulling in an RNG crate.
+
+/// SplitMix64 generator (Steele, Lea & Flood, 2014).
+pub struct SplitMix64(pub u64);
+
+impl SplitMix64 {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform sample from `(0, 1]`.
+    pub fn next_unit(&mut self) -> f64 {
+        1.0 - (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform sample from `0..n`. `n` must be positive.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Returns `k` distinct indices drawn from `0..n`, with `k <= n`.
+    pub fn sample_indices(&mut self, n: usize, k: usize) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..n).collect();
+        for i in 0..k {
+            let j = i + self.below(n - i);
+            indices.swap(i, j);
+        }
+        indices.truncate(k);
+        indices
+    }
+}
@@ -0,0 +1,202 @@
+//! Brute-force similarity search over dense embedding rows.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use half::f16;
+
+use crate::simd;
+
+/// Added to the norm product so zero vectors score 0 instead of NaN.
+/// Matches the epsilon used by the NumPy, MLX and JAX backends.
+pub const NORM_EPSILON: f32 = 1e-8;
+
+/// Element types embeddings may be stored in.
+pub trait Scalar: Copy {
+    fn to_f32(self) -> f32;
+
+    /// Dot product of an `f32` query with a row of this type.
+    fn dot(a: &[f32], b: &[Self]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y.to_f32()).sum()
+    }
+
+    /// Squared Euclidean norm of a row of this type.
+    fn norm_squared(a: &[Self]) -> f32 {
+        a.iter()
+            .map(|x| {
+                let x = x.to_f32();
+                x * x
+            })
+            .sum()
+    }
+}
+
+impl Scalar for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn dot(a: &[f32], b: &[f32]) -> f32 {
+        simd::dot(a, b)
+    }
+
+    fn norm_squared(a: &[f32]) -> f32 {
+        simd::dot(a, a)
+    }
+}
+
+impl Scalar for f16 {
+    fn to_f32(self) -> f32 {
+        f16::to_f32(self)
+    }
+}
+
+impl Scalar for i8 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+}
+
+/// Dot product of an `f32` query with an equally sized row.
+pub fn dot<T: Scalar>(a: &[f32], b: &[T]) -> f32 {
+    T::dot(a, b)
+}
+
+/// Euclidean norm of a vector.
+pub fn norm<T: Scalar>(a: &[T]) -> f32 {
+    T::norm_squared(a).sqrt()
+}
+
+/// Squared Euclidean distance between two equally sized vectors.
+pub fn l2_squared(a: &[f32], b: &[f32]) -> f32 {
+    simd::l2_squared(a, b)
+}
+
+/// Returns `a` scaled to unit length; zero vectors are returned unchanged.
+pub fn normalized(a: &[f32]) -> Vec<f32> {
+    let n = norm(a);
+    if n == 0.0 {
+        return a.to_vec();
+    }
+    a.iter().map(|x| x / n).collect()
+}
+
+/// Cosine similarity of `row` against a query whose norm is already known.
+pub fn cosine<T: Scalar>(query: &[f32], query_norm: f32, row: &[T]) -> f32 {
+    cosine_with_norms(query, query_norm, row, norm(row))
+}
+
+/// Cosine similarity when both norms are precomputed.
+pub fn cosine_with_norms<T: Scalar>(
+    query: &[f32],
+    query_norm: f32,
+    row: &[T],
+    row_norm: f32,
+) -> f32 {
+    dot(query, row) / (query_norm * row_norm + NORM_EPSILON)
+}
+
+/// A candidate row with its similarity score. `I` locates the row, by
+/// default as an index into one matrix.
+///
+/// Ordered so that "greater" means a better match: higher score first, and
+/// the lower index wins ties, mirroring a stable descending sort in Python.
+#[derive(Clone, Copy, Debug)]
+pub struct Scored<I = usize> {
+    pub index: I,
+    pub score: f32,
+}
+
+impl<I: Ord> PartialEq for Scored<I> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<I: Ord> Eq for Scored<I> {}
+
+impl<I: Ord> PartialOrd for Scored<I> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<I: Ord> Ord for Scored<I> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+/// Bounded collector that keeps the `k` best candidates seen so far.
+pub struct TopK<I = usize> {
+    k: usize,
+    heap: BinaryHeap<Reverse<Scored<I>>>,
+}
+
+impl<I: Ord> TopK<I> {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            heap: BinaryHeap::with_capacity(k.saturating_add(1).min(1024)),
+        }
+    }
+
+    /// Offers a candidate, evicting the weakest one once `k` are held.
+    pub fn push(&mut self, index: I, score: f32) {
+        if self.k == 0 {
+            return;
+        }
+        let candidate = Scored { index, score };
+        if self.heap.len() < self.k {
+            self.heap.push(Reverse(candidate));
+        } else if let Some(Reverse(weakest)) = self.heap.peek() {
+            if candidate > *weakest {
+                self.heap.pop();
+                self.heap.push(Reverse(candidate));
+            }
+        }
+    }
+
+    /// Returns the collected `(index, score)` pairs, best first.
+    pub fn into_sorted_vec(self) -> Vec<(I, f32)> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(s)| (s.index, s.score))
+            .collect()
+    }
+}
+
+/// Scores every row against `query` and returns the `k` best rows whose
+/// cosine similarity is at least `threshold`, best first.
+///
+/// Rows must have the same dimension as the query; callers validate this.
+pub fn cosine_top_k<'a, T, I>(query: &[f32], rows: I, k: usize, threshold: f32) -> Vec<(usize, f32)>
+where
+    T: Scalar + 'a,
+    I: IntoIterator<Item = &'a [T]>,
+{
+    let query_norm = norm(query);
+    let mut top = TopK::new(k);
+    for (index, row) in rows.into_iter().enumerate() {
+        let score = cosine(query, query_norm, row);
+        if score >= threshold {
+            top.push(index, score);
+        }
+    }
+    top.into_sorted_vec()
+}
+
+/// Cosine similarity of `query` against every row, in row order.
+pub fn cosine_all<'a, T, I>(query: &[f32], rows: I) -> Vec<f32>
+where
+    T: Scalar + 'a,
+    I: IntoIterator<Item = &'a [T]>,
+{
+    let query_norm = norm(query);
+    rows.into_iter()
+        .map(|row| cosine(query, query_norm, row))
+        .collect()
+}
@@ -0,0 +1,212 @@
+//! Runtime-dispatched `f32` distance kernels.
+//!
+//! The widest instruction set the CPU supports is detected once, on first
+//! use, and every dot product, norm and L2 distance over `f32` rows goes
+//! through the chosen kernel. Builds for other architectures always use the
+//! scalar kernel.
+
+use std::sync::OnceLock;
+
+/// A set of distance kernels for one instruction set.
+pub struct Kernel {
+    /// Short name reported to Python, e.g. `"avx2"`.
+    pub name: &'static str,
+    pub dot: fn(&[f32], &[f32]) -> f32,
+    pub l2_squared: fn(&[f32], &[f32]) -> f32,
+}
+
+const SCALAR: Kernel = Kernel {
+    name: "scalar",
+    dot: scalar::dot,
+    l2_squared: scalar::l2_squared,
+};
+
+#[cfg(target_arch = "x86_64")]
+const AVX2: Kernel = Kernel {
+    name: "avx2",
+    dot: avx2::dot,
+    l2_squared: avx2::l2_squared,
+};
+
+#[cfg(target_arch = "x86_64")]
+const AVX512: Kernel = Kernel {
+    name: "avx512",
+    dot: avx512::dot,
+    l2_squared: avx512::l2_squared,
+};
+
+/// Returns the fastest kernel supported by this CPU.
+pub fn kernel() -> &'static Kernel {
+    static KERNEL: OnceLock<&'static Kernel> = OnceLock::new();
+    KERNEL.get_or_init(detect)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn detect() -> &'static Kernel {
+    if is_x86_feature_detected!("avx512f") {
+        &AVX512
+    } else if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        &AVX2
+    } else {
+        &SCALAR
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn detect() -> &'static Kernel {
+    &SCALAR
+}
+
+/// Dot product of two equally sized vectors.
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    (kernel().dot)(a, b)
+}
+
+/// Squared Euclidean distance between two equally sized vectors.
+pub fn l2_squared(a: &[f32], b: &[f32]) -> f32 {
+    (kernel().l2_squared)(a, b)
+}
+
+mod scalar {
+    pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    pub fn l2_squared(a: &[f32], b: &[f32]) -> f32 {
+        a.iter()
+            .zip(b)
+            .map(|(x, y)| {
+                let d = x - y;
+                d * d
+            })
+            .sum()
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    const LANES: usize = 8;
+
+    pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+        // SAFETY: this kernel is only selected when AVX2 and FMA are available.
+        unsafe { dot_avx2(a, b) }
+    }
+
+    pub fn l2_squared(a: &[f32], b: &[f32]) -> f32 {
+        // SAFETY: as above.
+        unsafe { l2_squared_avx2(a, b) }
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn dot_avx2(a: &[f32], b: &[f32]) -> f32 {
+        let n = a.len().min(b.len());
+        let body = n - n % (2 * LANES);
+        let (mut acc0, mut acc1) = (_mm256_setzero_ps(), _mm256_setzero_ps());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut i = 0;
+        while i < body {
+            acc0 = _mm256_fmadd_ps(_mm256_loadu_ps(pa.add(i)), _mm256_loadu_ps(pb.add(i)), acc0);
+            acc1 = _mm256_fmadd_ps(
+                _mm256_loadu_ps(pa.add(i + LANES)),
+                _mm256_loadu_ps(pb.add(i + LANES)),
+                acc1,
+            );
+            i += 2 * LANES;
+        }
+        hsum(_mm256_add_ps(acc0, acc1)) + super::scalar::dot(&a[body..n], &b[body..n])
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn l2_squared_avx2(a: &[f32], b: &[f32]) -> f32 {
+        let n = a.len().min(b.len());
+        let body = n - n % (2 * LANES);
+        let (mut acc0, mut acc1) = (_mm256_setzero_ps(), _mm256_setzero_ps());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut i = 0;
+        while i < body {
+            let d0 = _mm256_sub_ps(_mm256_loadu_ps(pa.add(i)), _mm256_loadu_ps(pb.add(i)));
+            let d1 = _mm256_sub_ps(
+                _mm256_loadu_ps(pa.add(i + LANES)),
+                _mm256_loadu_ps(pb.add(i + LANES)),
+            );
+            acc0 = _mm256_fmadd_ps(d0, d0, acc0);
+            acc1 = _mm256_fmadd_ps(d1, d1, acc1);
+            i += 2 * LANES;
+        }
+        hsum(_mm256_add_ps(acc0, acc1)) + super::scalar::l2_squared(&a[body..n], &b[body..n])
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn hsum(v: __m256) -> f32 {
+        let pair = _mm_add_ps(_mm256_castps256_ps128(v), _mm256_extractf128_ps(v, 1));
+        let pair = _mm_add_ps(pair, _mm_movehl_ps(pair, pair));
+        _mm_cvtss_f32(_mm_add_ss(pair, _mm_shuffle_ps(pair, pair, 1)))
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx512 {
+    use std::arch::x86_64::*;
+
+    const LANES: usize = 16;
+
+    pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+        // SAFETY: this kernel is only selected when AVX-512F is available.
+        unsafe { dot_avx512(a, b) }
+    }
+
+    pub fn l2_squared(a: &[f32], b: &[f32]) -> f32 {
+        // SAFETY: as above.
+        unsafe { l2_squared_avx512(a, b) }
+    }
+
+    /// Masks in the first `len` lanes so the tail needs no scalar loop.
+    fn tail_mask(len: usize) -> __mmask16 {
+        ((1u32 << len) - 1) as __mmask16
+    }
+
+    #[target_feature(enable = "avx512f")]
+    unsafe fn dot_avx512(a: &[f32], b: &[f32]) -> f32 {
+        let n = a.len().min(b.len());
+        let body = n - n % LANES;
+        let mut acc = _mm512_setzero_ps();
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut i = 0;
+        while i < body {
+            acc = _mm512_fmadd_ps(_mm512_loadu_ps(pa.add(i)), _mm512_loadu_ps(pb.add(i)), acc);
+            i += LANES;
+        }
+        if body < n {
+            let mask = tail_mask(n - body);
+            let x = _mm512_maskz_loadu_ps(mask, pa.add(body));
+            let y = _mm512_maskz_loadu_ps(mask, pb.add(body));
+            acc = _mm512_fmadd_ps(x, y, acc);
+        }
+        _mm512_reduce_add_ps(acc)
+    }
+
+    #[target_feature(enable = "avx512f")]
+    unsafe fn l2_squared_avx512(a: &[f32], b: &[f32]) -> f32 {
+        let n = a.len().min(b.len());
+        let body = n - n % LANES;
+        let mut acc = _mm512_setzero_ps();
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut i = 0;
+        while i < body {
+            let d = _mm512_sub_ps(_mm512_loadu_ps(pa.add(i)), _mm512_loadu_ps(pb.add(i)));
+            acc = _mm512_fmadd_ps(d, d, acc);
+            i += LANES;
+        }
+        if body < n {
+            let mask = tail_mask(n - body);
+            let d = _mm512_sub_ps(
+                _mm512_maskz_loadu_ps(mask, pa.add(body)),
+                _mm512_maskz_loadu_ps(mask, pb.add(body)),
+            );
+            acc = _mm512_fmadd_ps(d, d, acc);
+        }
+        _mm512_reduce_add_ps(acc)
+    }
+}
@@ -0,0 +1,557 @@
+//! SQLite-backed storage of a knowledge base.
+//!
+//! A [`KnowledgeStore`] keeps documents, their chunks and the chunks'
+//! embeddings in one SQLite database, each embedding tagged with the model
+//! that produced it. The database uses a rollback journal rather than WAL,
+//! so once closed it is a single self-contained file that can be copied
+//! between machines. Vector indexes are filled straight from it with
+//! `load_index`, without the embeddings passing through Python.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use numpy::ndarray::Array2;
+use numpy::PyArray2;
+use pyo3::exceptions::{PyKeyError, PyOSError, PyValueError};
+use pyo3::prelude::*;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::array::MatrixArg;
+use crate::filter::{Attributes, Value};
+use crate::hnsw::HnswIndex;
+use crate::index::EmbeddingIndex;
+use crate::ivfpq::IvfPqIndex;
+use crate::provenance::{check_model, DimensionMismatchError};
+use crate::quantize::QuantizedIndex;
+
+const SCHEMA_VERSION: i32 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE documents (
+    id INTEGER PRIMARY KEY,
+    source TEXT NOT NULL UNIQUE
+);
+CREATE TABLE chunks (
+    id TEXT PRIMARY KEY,
+    document_id INTEGER NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    text TEXT NOT NULL,
+    metadata TEXT NOT NULL,
+    created_at REAL NOT NULL
+);
+CREATE INDEX chunks_by_document ON chunks(document_id);
+CREATE TABLE models (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    dim INTEGER NOT NULL,
+    created_at REAL NOT NULL
+);
+CREATE TABLE embeddings (
+    chunk_id TEXT NOT NULL REFERENCES chunks(id) ON DELETE CASCADE,
+    model_id INTEGER NOT NULL REFERENCES models(id) ON DELETE CASCADE,
+    vector BLOB NOT NULL,
+    PRIMARY KEY (chunk_id, model_id)
+);
+-- An embedding of text that has since changed is stale
+CREATE TRIGGER chunk_text_changed AFTER UPDATE OF text ON chunks
+WHEN old.text <> new.text
+BEGIN
+    DELETE FROM embeddings WHERE chunk_id = new.id;
+END;
+";
+
+/// Iterations of k-means when `load_index` trains an IVF-PQ index.
+const TRAIN_ITERATIONS: usize = 20;
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+/// `(chunk_id, source, text, metadata, created_at)` as returned by `chunks`.
+type StoredChunk = (String, String, String, String, f64);
+
+/// An embedding row as read back from the database.
+struct Row {
+    chunk_id: String,
+    vector: Vec<f32>,
+    source: String,
+    metadata: String,
+    created_at: f64,
+}
+
+impl Row {
+    /// The attributes the flat index filters on, as built by the Python
+    /// retriever: scalar metadata, source, filename and creation time.
+    fn attributes(&self) -> Attributes {
+        let mut attributes = Attributes::new();
+        if let Ok(serde_json::Value::Object(metadata)) = serde_json::from_str(&self.metadata) {
+            for (key, value) in metadata {
+                let value = match value {
+                    serde_json::Value::String(s) => Value::Str(s),
+                    serde_json::Value::Number(n) => match n.as_f64() {
+                        Some(n) => Value::Num(n),
+                        None => continue,
+                    },
+                    serde_json::Value::Bool(b) => Value::Num(b as u8 as f64),
+                    _ => continue,
+                };
+                attributes.insert(key, value);
+            }
+        }
+        let filename = self.source.rsplit('/').next().unwrap_or_default();
+        attributes.insert("filename".to_string(), Value::Str(filename.to_string()));
+        attributes.insert("source".to_string(), Value::Str(self.source.clone()));
+        attributes.insert("created_at".to_string(), Value::Num(self.created_at));
+        attributes
+    }
+}
+
+/// A knowledge base stored in a SQLite database.
+///
+/// Tables hold documents (one per source), chunks (text, JSON metadata and
+/// creation time), embedding models (name and dimension) and embeddings as
+/// little-endian `float32` blobs, one per chunk and model, so embeddings of
+/// several models can live side by side. Deleting a chunk deletes its
+/// embeddings, and changing a chunk's text deletes the now stale ones.
+#[pyclass]
+pub struct KnowledgeStore {
+    path: PathBuf,
+    conn: Connection,
+}
+
+impl KnowledgeStore {
+    fn error(&self, e: rusqlite::Error) -> PyErr {
+        PyOSError::new_err(format!("{}: {}", self.path.display(), e))
+    }
+
+    /// Id and dimension of `model`, if embeddings were ever stored for it.
+    fn model(&self, name: &str) -> rusqlite::Result<Option<(i64, usize)>> {
+        self.conn
+            .query_row("SELECT id, dim FROM models WHERE name = ?1", [name], |r| {
+                Ok((r.get(0)?, r.get::<_, i64>(1)? as usize))
+            })
+            .optional()
+    }
+
+    /// Id and dimension of `model`, raising `KeyError` if it is unknown.
+    fn known_model(&self, name: &str) -> PyResult<(i64, usize)> {
+        self.model(name)
+            .map_err(|e| self.error(e))?
+            .ok_or_else(|| PyKeyError::new_err(name.to_string()))
+    }
+
+    /// Reads the embeddings of `model_id` in chunk order.
+    fn rows(&self, model_id: i64, dim: usize) -> PyResult<Vec<Row>> {
+        let read = || -> rusqlite::Result<Vec<(Row, Vec<u8>)>> {
+            let mut statement = self.conn.prepare(
+                "SELECT e.chunk_id, e.vector, d.source, c.metadata, c.created_at
+                 FROM embeddings e
+                 JOIN chunks c ON c.id = e.chunk_id
+                 JOIN documents d ON d.id = c.document_id
+                 WHERE e.model_id = ?1
+                 ORDER BY c.document_id, c.rowid",
+            )?;
+            let rows = statement.query_map([model_id], |r| {
+                Ok((
+                    Row {
+                        chunk_id: r.get(0)?,
+                        vector: Vec::new(),
+                        source: r.get(2)?,
+                        metadata: r.get(3)?,
+                        created_at: r.get(4)?,
+                    },
+                    r.get(1)?,
+                ))
+            })?;
+            rows.collect()
+        };
+        read()
+            .map_err(|e| self.error(e))?
+            .into_iter()
+            .map(|(mut row, blob)| {
+                row.vector = self.vector(&row.chunk_id, &blob, dim)?;
+                Ok(row)
+            })
+            .collect()
+    }
+
+    /// Decodes the embedding blob of `chunk_id`.
+    fn vector(&self, chunk_id: &str, blob: &[u8], dim: usize) -> PyResult<Vec<f32>> {
+        if blob.len() != 4 * dim {
+            return Err(PyValueError::new_err(format!(
+                "{}: corrupt embedding for chunk {}",
+                self.path.display(),
+                chunk_id
+            )));
+        }
+        Ok(blob
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect())
+    }
+
+    /// Reads the embeddings of `model_id` for `chunk_ids`, in that order,
+    /// raising `KeyError` for a chunk without one.
+    fn rows_of(&self, model_id: i64, dim: usize, chunk_ids: Vec<String>) -> PyResult<Vec<Row>> {
+        let mut statement = self
+            .conn
+            .prepare_cached(
+                "SELECT e.vector, d.source, c.metadata, c.created_at
+                 FROM embeddings e
+                 JOIN chunks c ON c.id = e.chunk_id
+                 JOIN documents d ON d.id = c.document_id
+                 WHERE e.model_id = ?1 AND e.chunk_id = ?2",
+            )
+            .map_err(|e| self.error(e))?;
+        let mut rows = Vec::with_capacity(chunk_ids.len());
+        for chunk_id in chunk_ids {
+            let found = statement
+                .query_row(params![model_id, chunk_id], |r| {
+                    Ok((r.get::<_, Vec<u8>>(0)?, r.get(1)?, r.get(2)?, r.get(3)?))
+                })
+                .optional()
+                .map_err(|e| self.error(e))?;
+            let Some((blob, source, metadata, created_at)) = found else {
+                return Err(PyKeyError::new_err(chunk_id));
+            };
+            rows.push(Row {
+                vector: self.vector(&chunk_id, &blob, dim)?,
+                chunk_id,
+                source,
+                metadata,
+                created_at,
+            });
+        }
+        Ok(rows)
+    }
+}
+
+#[pymethods]
+impl KnowledgeStore {
+    /// Opens the database at `path`, creating it and its tables if needed.
+    /// Raises `ValueError` for a database written by a newer version.
+    #[new]
+    fn new(path: PathBuf) -> PyResult<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .map_err(|e| PyOSError::new_err(format!("{}: {}", parent.display(), e)))?;
+        }
+        let open = || -> rusqlite::Result<(Connection, i32)> {
+            let conn = Connection::open(&path)?;
+            conn.pragma_update(None, "foreign_keys", true)?;
+            let version = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+            if version == 0 {
+                conn.execute_batch(&format!(
+                    "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+                    SCHEMA, SCHEMA_VERSION
+                ))?;
+            }
+            Ok((conn, version))
+        };
+        let (conn, version) =
+            open().map_err(|e| PyOSError::new_err(format!("{}: {}", path.display(), e)))?;
+        if version > SCHEMA_VERSION {
+            return Err(PyValueError::new_err(format!(
+                "{}: written by a newer version (schema {})",
+                path.display(),
+                version
+            )));
+        }
+        Ok(Self { path, conn })
+    }
+
+    /// Adds or updates chunks, creating a document for each new source.
+    ///
+    /// `chunk_ids`, `sources`, `texts`, `metadata` (one JSON document per
+    /// chunk) and `created_at` (POSIX timestamps, default now) must have the
+    /// same length. Updating a chunk keeps its creation time, and its
+    /// embeddings unless its text changed.
+    #[pyo3(signature = (chunk_ids, sources, texts, metadata, created_at = None))]
+    fn add_chunks(
+        &mut self,
+        chunk_ids: Vec<String>,
+        sources: Vec<String>,
+        texts: Vec<String>,
+        metadata: Vec<String>,
+        created_at: Option<Vec<f64>>,
+    ) -> PyResult<()> {
+        let count = chunk_ids.len();
+        if sources.len() != count
+            || texts.len() != count
+            || metadata.len() != count
+            || created_at.as_ref().is_some_and(|c| c.len() != count)
+        {
+            return Err(PyValueError::new_err(
+                "chunk_ids, sources, texts, metadata and created_at must have the same length",
+            ));
+        }
+        let now = now();
+        let write = |conn: &mut Connection| -> rusqlite::Result<()> {
+            let tx = conn.transaction()?;
+            {
+                let mut document =
+                    tx.prepare("INSERT OR IGNORE INTO documents (source) VALUES (?1)")?;
+                let mut document_id = tx.prepare("SELECT id FROM documents WHERE source = ?1")?;
+                let mut chunk = tx.prepare(
+                    "INSERT INTO chunks (id, document_id, text, metadata, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT (id) DO UPDATE SET
+                         document_id = excluded.document_id,
+                         text = excluded.text,
+                         metadata = excluded.metadata",
+                )?;
+                for i in 0..count {
+                    document.execute([&sources[i]])?;
+                    let id: i64 = document_id.query_row([&sources[i]], |r| r.get(0))?;
+                    let created = created_at.as_ref().map_or(now, |c| c[i]);
+                    chunk.execute(params![chunk_ids[i], id, texts[i], metadata[i], created])?;
+                }
+            }
+            tx.execute(
+                "DELETE FROM documents WHERE id NOT IN (SELECT document_id FROM chunks)",
+                [],
+            )?;
+            tx.commit()
+        };
+        write(&mut self.conn).map_err(|e| self.error(e))
+    }
+
+    /// Stores one embedding per chunk id from a 2-D matrix, produced by
+    /// `model`, replacing the model's previous embeddings of those chunks.
+    ///
+    /// A model's dimension is recorded with its first embeddings; later
+    /// embeddings of another dimension raise `ValueError`. Raises
+    /// `KeyError` for a chunk id that is not stored.
+    fn add_embeddings(
+        &mut self,
+        model: &str,
+        chunk_ids: Vec<String>,
+        embeddings: MatrixArg<'_>,
+    ) -> PyResult<()> {
+        let matrix = embeddings.view()?;
+        matrix.check_rows(chunk_ids.len())?;
+        if chunk_ids.is_empty() {
+            return Ok(());
+        }
+        let dim = matrix.cols();
+        let model_id = match self.model(model).map_err(|e| self.error(e))? {
+            Some((_, recorded)) if recorded != dim => {
+                return Err(DimensionMismatchError::new_err(format!(
+                    "embedding has dimension {}, but {} embeddings have dimension {}",
+                    dim, model, recorded
+                )))
+            }
+            Some((id, _)) => Some(id),
+            None => None,
+        };
+        for chunk_id in &chunk_ids {
+            let known = self
+                .conn
+                .query_row("SELECT 1 FROM chunks WHERE id = ?1", [chunk_id], |_| Ok(()))
+                .optional()
+                .map_err(|e| self.error(e))?;
+            if known.is_none() {
+                return Err(PyKeyError::new_err(chunk_id.clone()));
+            }
+        }
+
+        let data = matrix.to_f32_vec();
+        let write = |conn: &mut Connection| -> rusqlite::Result<()> {
+            let tx = conn.transaction()?;
+            let model_id = match model_id {
+                Some(id) => id,
+                None => {
+                    tx.execute(
+                        "INSERT INTO models (name, dim, created_at) VALUES (?1, ?2, ?3)",
+                        params![model, dim as i64, now()],
+                    )?;
+                    tx.last_insert_rowid()
+                }
+            };
+            {
+                let mut embedding = tx.prepare(
+                    "INSERT INTO embeddings (chunk_id, model_id, vector) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (chunk_id, model_id) DO UPDATE SET vector = excluded.vector",
+                )?;
+                for (chunk_id, row) in chunk_ids.iter().zip(data.chunks_exact(dim)) {
+                    let blob: Vec<u8> = row.iter().flat_map(|v| v.to_le_bytes()).collect();
+                    embedding.execute(params![chunk_id, model_id, blob])?;
+                }
+            }
+            tx.commit()
+        };
+        write(&mut self.conn).map_err(|e| self.error(e))
+    }
+
+    /// Deletes chunks with their embeddings, and documents left without
+    /// chunks. Unknown ids are ignored. Returns the number deleted.
+    fn remove_chunks(&mut self, chunk_ids: Vec<String>) -> PyResult<usize> {
+        let write = |conn: &mut Connection| -> rusqlite::Result<usize> {
+            let tx = conn.transaction()?;
+            let mut removed = 0;
+            {
+                let mut chunk = tx.prepare("DELETE FROM chunks WHERE id = ?1")?;
+                for chunk_id in &chunk_ids {
+                    removed += chunk.execute([chunk_id])?;
+                }
+            }
+            tx.execute(
+                "DELETE FROM documents WHERE id NOT IN (SELECT document_id FROM chunks)",
+                [],
+            )?;
+            tx.commit()?;
+            Ok(removed)
+        };
+        write(&mut self.conn).map_err(|e| self.error(e))
+    }
+
+    /// Returns `(chunk_id, source, text, metadata, created_at)` for every
+    /// chunk, grouped by document in the order they were added.
+    fn chunks(&self) -> PyResult<Vec<StoredChunk>> {
+        let read = || -> rusqlite::Result<Vec<StoredChunk>> {
+            let mut statement = self.conn.prepare(
+                "SELECT c.id, d.source, c.text, c.metadata, c.created_at
+                 FROM chunks c JOIN documents d ON d.id = c.document_id
+                 ORDER BY c.document_id, c.rowid",
+            )?;
+            let rows = statement.query_map([], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?))
+            })?;
+            rows.collect()
+        };
+        read().map_err(|e| self.error(e))
+    }
+
+    /// Returns `(name, dim, count)` for every embedding model, where
+    /// `count` is the number of chunks embedded with it.
+    fn models(&self) -> PyResult<Vec<(String, usize, usize)>> {
+        let read = || -> rusqlite::Result<Vec<_>> {
+            let mut statement = self.conn.prepare(
+                "SELECT m.name, m.dim, COUNT(e.chunk_id)
+                 FROM models m LEFT JOIN embeddings e ON e.model_id = m.id
+                 GROUP BY m.id ORDER BY m.id",
+            )?;
+            let rows = statement.query_map([], |r| {
+                Ok((
+                    r.get(0)?,
+                    r.get::<_, i64>(1)? as usize,
+                    r.get::<_, i64>(2)? as usize,
+                ))
+            })?;
+            rows.collect()
+        };
+        read().map_err(|e| self.error(e))
+    }
+
+    /// Returns the chunk ids embedded with `model` and their embeddings as
+    /// a `float32` matrix, in chunk order. Raises `KeyError` for an unknown
+    /// model.
+    ///
+    /// With `chunk_ids`, only those chunks' embeddings are read, in the
+    /// given order, and a chunk without an embedding of `model` raises
+    /// `KeyError`. Re-ranking reads its candidates this way.
+    #[pyo3(signature = (model, chunk_ids = None))]
+    fn embeddings<'py>(
+        &self,
+        py: Python<'py>,
+        model: &str,
+        chunk_ids: Option<Vec<String>>,
+    ) -> PyResult<(Vec<String>, Bound<'py, PyArray2<f32>>)> {
+        let (model_id, dim) = self.known_model(model)?;
+        let rows = match chunk_ids {
+            Some(chunk_ids) => self.rows_of(model_id, dim, chunk_ids)?,
+            None => self.rows(model_id, dim)?,
+        };
+        let mut ids = Vec::with_capacity(rows.len());
+        let mut data = Vec::with_capacity(rows.len() * dim);
+        for row in rows {
+            ids.push(row.chunk_id);
+            data.extend(row.vector);
+        }
+        let array = Array2::from_shape_vec((ids.len(), dim), data)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok((ids, PyArray2::from_owned_array_bound(py, array)))
+    }
+
+    /// Adds the embeddings of `model` to a native index and returns how
+    /// many were added.
+    ///
+    /// `index` may be an `EmbeddingIndex`, which also receives each chunk's
+    /// filter attributes, a `QuantizedIndex`, an `HnswIndex` or an
+    /// `IvfPqIndex`, which is trained on the embeddings first if it has not
+    /// been. Its dimension must match the model's, and an index created
+    /// for another model is refused.
+    fn load_index(&self, py: Python<'_>, index: &Bound<'_, PyAny>, model: &str) -> PyResult<usize> {
+        let supported = index.is_instance_of::<EmbeddingIndex>()
+            || index.is_instance_of::<QuantizedIndex>()
+            || index.is_instance_of::<HnswIndex>()
+            || index.is_instance_of::<IvfPqIndex>();
+        if !supported {
+            return Err(PyValueError::new_err(
+                "expected an EmbeddingIndex, QuantizedIndex, HnswIndex or IvfPqIndex",
+            ));
+        }
+        let index_model: Option<String> = index.getattr("model")?.extract()?;
+        check_model(index_model.as_deref(), Some(model))?;
+        let (model_id, dim) = self.known_model(model)?;
+        let index_dim: usize = index.getattr("dim")?.extract()?;
+        if index_dim != dim {
+            return Err(DimensionMismatchError::new_err(format!(
+                "{} embeddings have dimension {}, the index {}",
+                model, dim, index_dim
+            )));
+        }
+        let rows = self.rows(model_id, dim)?;
+        let count = rows.len();
+
+        if let Ok(flat) = index.downcast::<EmbeddingIndex>() {
+            let mut flat = flat.borrow_mut();
+            for row in rows {
+                let attributes = row.attributes();
+                flat.insert(row.chunk_id, &row.vector, attributes);
+            }
+        } else if let Ok(quantized) = index.downcast::<QuantizedIndex>() {
+            let mut quantized = quantized.borrow_mut();
+            for row in rows {
+                quantized.insert(row.chunk_id, &row.vector);
+            }
+        } else if let Ok(hnsw) = index.downcast::<HnswIndex>() {
+            let mut hnsw = hnsw.borrow_mut();
+            let hnsw = &mut *hnsw;
+            py.allow_threads(move || {
+                for row in rows {
+                    hnsw.insert(row.chunk_id, &row.vector);
+                }
+            });
+        } else if let Ok(ivfpq) = index.downcast::<IvfPqIndex>() {
+            let mut ivfpq = ivfpq.borrow_mut();
+            let ivfpq = &mut *ivfpq;
+            py.allow_threads(move || -> Result<(), String> {
+                if !ivfpq.is_trained() {
+                    let data: Vec<f32> =
+                        rows.iter().flat_map(|r| r.vector.iter().copied()).collect();
+                    ivfpq.retrain(&data, TRAIN_ITERATIONS)?;
+                }
+                for row in rows {
+                    ivfpq.insert(row.chunk_id, &row.vector);
+                }
+                Ok(())
+            })
+            .map_err(PyValueError::new_err)?;
+        }
+        Ok(count)
+    }
+
+    fn __len__(&self) -> PyResult<usize> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM chunks", [], |r| r.get::<_, i64>(0))
+            .map(|n| n as usize)
+            .map_err(|e| self.error(e))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("KnowledgeStore({:?})", self.path.display().to_string())
+    }
+}
@@ -0,0 +1,220 @@
+//! Watching a knowledge directory for changes.
+//!
+//! A [`DirectoryWatcher`] subscribes to the operating system's file change
+//! notifications (inotify, FSEvents, ReadDirectoryChangesW) for a directory
+//! tree and collects the paths that changed until they are taken with
+//! `pending` or `wait`. Paths are filtered like `ingest_directory` filters
+//! files, so writes to hidden or ignored files, such as the ingestion
+//! manifest, are not reported.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use globset::GlobSet;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use notify::event::{Event, EventKind};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::ingest::{glob_set, relative_source};
+
+/// How often a blocking `wait` checks for Ctrl-C.
+const SIGNAL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Reported in place of paths when the change notifications overflowed and
+/// the whole directory must be rescanned.
+const RESCAN: &str = ".";
+
+/// Watches a directory tree and reports the files that changed in it.
+///
+/// `globs` and `ignore` select files as in `ingest_directory`; the
+/// directory's top-level `.gitignore` is honoured too. Deletions are always
+/// reported, since a deleted path may have been a directory.
+#[pyclass]
+pub struct DirectoryWatcher {
+    root: PathBuf,
+    globs: GlobSet,
+    ignore: Gitignore,
+    events: Receiver<notify::Result<Event>>,
+    /// Dropping the watcher stops the notifications.
+    watcher: Option<RecommendedWatcher>,
+}
+
+impl DirectoryWatcher {
+    /// Whether a change to `path` is of interest.
+    fn is_watched(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let hidden = relative
+            .components()
+            .any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+        if hidden
+            || self
+                .ignore
+                .matched_path_or_any_parents(relative, path.is_dir())
+                .is_ignore()
+        {
+            return false;
+        }
+        !path.is_file() || self.globs.is_empty() || self.globs.is_match(relative)
+    }
+
+    /// Adds the paths changed by `event` to `changed`.
+    fn collect(&self, event: notify::Result<Event>, changed: &mut BTreeSet<String>) {
+        let event = match event {
+            Ok(event) if event.need_rescan() => {
+                changed.insert(RESCAN.to_string());
+                return;
+            }
+            Ok(event) => event,
+            // Lost events leave no way of knowing what changed
+            Err(_) => {
+                changed.insert(RESCAN.to_string());
+                return;
+            }
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        for path in event.paths {
+            if self.is_watched(&path) {
+                changed.insert(relative_source(&self.root, &path));
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl DirectoryWatcher {
+    #[new]
+    #[pyo3(signature = (path, globs = None, ignore = None))]
+    fn new(
+        path: PathBuf,
+        globs: Option<Vec<String>>,
+        ignore: Option<Vec<String>>,
+    ) -> PyResult<Self> {
+        if !path.is_dir() {
+            return Err(PyValueError::new_err(format!(
+                "not a directory: {}",
+                path.display()
+            )));
+        }
+        // Events carry absolute paths, which must share the root's prefix
+        let root = path
+            .canonicalize()
+            .map_err(|e| PyOSError::new_err(format!("{}: {}", path.display(), e)))?;
+        let globs = glob_set(&globs.unwrap_or_default()).map_err(PyValueError::new_err)?;
+        let mut builder = GitignoreBuilder::new(&root);
+        builder.add(root.join(".gitignore"));
+        for pattern in ignore.unwrap_or_default() {
+            builder
+                .add_line(None, &pattern)
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        }
+        let ignore = builder
+            .build()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        let (sender, events) = channel();
+        let mut watcher =
+            notify::recommended_watcher(sender).map_err(|e| PyOSError::new_err(e.to_string()))?;
+        watcher
+            .watch(&root, RecursiveMode::Recursive)
+            .map_err(|e| PyOSError::new_err(format!("{}: {}", root.display(), e)))?;
+        Ok(Self {
+            root,
+            globs,
+            ignore,
+            events,
+            watcher: Some(watcher),
+        })
+    }
+
+    /// Returns the paths changed since the last call, relative to the
+    /// watched directory and sorted, without blocking. `"."` stands for
+    /// the whole directory when notifications were lost.
+    fn pending(&mut self) -> Vec<String> {
+        let mut changed = BTreeSet::new();
+        while let Ok(event) = self.events.try_recv() {
+            self.collect(event, &mut changed);
+        }
+        changed.into_iter().collect()
+    }
+
+    /// Blocks until a watched path changes, or for at most `timeout`
+    /// seconds, and returns the changed paths like `pending`.
+    ///
+    /// Editors and `git checkout` touch many files in quick succession, so
+    /// after the first change it keeps collecting until no event arrives
+    /// for `debounce` seconds. Returns an empty list on timeout or once the
+    /// watcher is closed. An infinite `timeout` waits like `None`; a
+    /// negative or NaN one, or a `debounce` that is not a finite,
+    /// non-negative number, raises `ValueError`.
+    #[pyo3(signature = (timeout = None, debounce = 0.2))]
+    fn wait(
+        &mut self,
+        py: Python<'_>,
+        timeout: Option<f64>,
+        debounce: f64,
+    ) -> PyResult<Vec<String>> {
+        if timeout.is_some_and(|t| t.is_nan() || t < 0.0) {
+            return Err(PyValueError::new_err(format!(
+                "timeout must be a non-negative number of seconds, not {}",
+                timeout.unwrap_or_default()
+            )));
+        }
+        let debounce = Duration::try_from_secs_f64(debounce).map_err(|_| {
+            PyValueError::new_err(format!(
+                "debounce must be a finite, non-negative number of seconds, not {}",
+                debounce
+            ))
+        })?;
+        // Timeouts too long for a deadline, such as infinity, never expire
+        let deadline = timeout
+            .and_then(|t| Duration::try_from_secs_f64(t).ok())
+            .and_then(|t| Instant::now().checked_add(t));
+        let this = &mut *self;
+        py.allow_threads(move || {
+            let mut changed = BTreeSet::new();
+            while changed.is_empty() {
+                let slice = match deadline {
+                    Some(deadline) => {
+                        let left = deadline.saturating_duration_since(Instant::now());
+                        if left.is_zero() {
+                            return Ok(Vec::new());
+                        }
+                        left.min(SIGNAL_INTERVAL)
+                    }
+                    None => SIGNAL_INTERVAL,
+                };
+                match this.events.recv_timeout(slice) {
+                    Ok(event) => this.collect(event, &mut changed),
+                    Err(RecvTimeoutError::Timeout) => Python::with_gil(|py| py.check_signals())?,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(Vec::new()),
+                }
+            }
+            while let Ok(event) = this.events.recv_timeout(debounce) {
+                this.collect(event, &mut changed);
+            }
+            Ok(changed.into_iter().collect())
+        })
+    }
+
+    /// Stops watching; later calls report no changes.
+    fn close(&mut self) {
+        self.watcher = None;
+        while self.events.try_recv().is_ok() {}
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "DirectoryWatcher({:?}, closed={})",
+            self.root.display().to_string(),
+            self.watcher.is_none()
+        )
+    }
+}
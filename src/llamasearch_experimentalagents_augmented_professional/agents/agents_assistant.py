@@ -20,6 +20,7 @@ from ..models.models_responses import ProfessionalResponse, SourceReference, Sug
 from ..integrations.knowledge_manager import KnowledgeManager
 from ..utils.logging_utils import get_db, log_interaction
 from ..utils.llm_router import execute_llm_prompt
+from ..utils.context_utils import DEFAULT_CONTEXT_BUDGET_TOKENS, format_source, load_tokenizer, pack_context
 
 logger = logging.getLogger(__name__)
 
@@ -32,6 +33,9 @@ class LlamaAssistant:
         knowledge_manager: KnowledgeManager,
         openai_client: Optional[OpenAI] = None,
         assistant_model: str = "gpt-4-turbo-preview",
+        context_budget_tokens: int = DEFAULT_CONTEXT_BUDGET_TOKENS,
+        tokenizer_path: Optional[str] = None,
+        hybrid: bool = False,
     ):
         """
         Initialize the LlamaAssistant.
@@ -40,10 +44,18 @@ class LlamaAssistant:
             knowledge_manager: The knowledge manager instance
             openai_client: Optional OpenAI client (created if not provided)
             assistant_model: The OpenAI model to use for the assistant
+            context_budget_tokens: Most tokens of knowledge base sources per prompt
+            tokenizer_path: The assistant model's tiktoken or Hugging Face tokenizer
+                file, to count those tokens with (estimated if not provided)
+            hybrid: Fuse BM25 keyword matches with the semantic results, so
+                exact identifiers and error codes in questions are found too
         """
         self.knowledge_manager = knowledge_manager
         self.client = openai_client or OpenAI()
         self.assistant_model = assistant_model
+        self.context_budget_tokens = context_budget_tokens
+        self.tokenizer = load_tokenizer(tokenizer_path)
+        self.hybrid = hybrid
         
         # Initialize SQLite logging database
         self.db = get_db()
@@ -72,7 +84,8 @@ class LlamaAssistant:
         results = self.knowledge_manager.search(
             query=query,
             top_k=top_k,
-            score_threshold=score_threshold
+            score_threshold=score_threshold,
+            hybrid=self.hybrid,
         )
         return results
     
@@ -99,14 +112,14 @@ class LlamaAssistant:
         )
     
     def _format_sources_for_context(self, results: List[Dict[str, Any]]) -> str:
-        """Format search results as context for the LLM."""
+        """Format search results as context for the LLM, cut to the context budget."""
+        results = pack_context(results, self.context_budget_tokens, self.tokenizer)
         if not results:
             return "No relevant information found in the knowledge base."
         
         context = "Here is relevant information from the knowledge base:\n\n"
         for i, result in enumerate(results, 1):
-            context += f"[Source {i}: {result['source']}]\n"
-            context += f"{result['content']}\n\n"
+            context += format_source(i, result)
         
         return context
     
@@ -240,6 +253,7 @@ class LlamaAssistant:
                 "sources": [
                     {
                         "source": "Source identifier",
+                        "page": 12,  # Page number if the source label gives one, otherwise null
                         "relevance": 0.9,  # A float between 0 and 1
                         "excerpt": "Brief excerpt if applicable"
                     }
@@ -279,7 +293,8 @@ class LlamaAssistant:
                 source_ref = SourceReference(
                     source=src.get("source", "Unknown"),
                     relevance=src.get("relevance", 0.0),
-                    excerpt=src.get("excerpt", None)
+                    excerpt=src.get("excerpt", None),
+                    page=src.get("page", None)
                 )
                 sources.append(source_ref)
 
@@ -2,12 +2,14 @@
 Semantic retrieval agent for LlamaSearch.
 
 This module implements the vector-based semantic search functionality using different
-backends (NumPy, MLX, JAX) for performance optimization.
+backends (NumPy, MLX, JAX, Rust) for performance optimization.
 """
 
 import logging
+import os
 import time
-from typing import List, Dict, Any, Optional, Union, Tuple
+from datetime import datetime
+from typing import Callable, List, Dict, Any, Optional, Set, Union, Tuple
 import math
 
 import numpy as np
@@ -26,19 +28,173 @@ try:
 except ImportError:
     HAS_JAX = False
 
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
 from ..models.models_knowledge import KnowledgeBase, KnowledgeChunk
 from ..models.models_responses import SearchResults
 
 logger = logging.getLogger(__name__)
 
+# Similarity metrics; "l2" scores are distances, so lower is better
+METRICS = ("cosine", "dot", "ip_normalized", "l2")
+
+# Index types storing codes instead of float32 vectors, whose memory saving
+# needs the chunks to let go of their float embeddings; see release_embeddings
+COMPRESSED_INDEX_TYPES = ("ivfpq", "int8", "binary")
+
+# Raised for embeddings of another model or dimension than an index holds;
+# the native indexes raise the same types
+if HAS_RUST:
+    EmbeddingMismatchError = rust_lib.EmbeddingMismatchError
+    DimensionMismatchError = rust_lib.DimensionMismatchError
+    ModelMismatchError = rust_lib.ModelMismatchError
+else:
+    class EmbeddingMismatchError(ValueError):
+        """Embeddings do not match the model or dimension an index was created for."""
+
+    class DimensionMismatchError(EmbeddingMismatchError):
+        """An embedding's dimension differs from the index's."""
+
+    class ModelMismatchError(EmbeddingMismatchError):
+        """Embeddings come from another model than the index's."""
+
+
+def chunk_attributes(chunk: KnowledgeChunk) -> Dict[str, Any]:
+    """
+    Collect the filterable attributes of a chunk.
+    
+    Scalar metadata entries (such as source_type) are kept alongside the
+    chunk's source, filename and created_at timestamp.
+    """
+    attributes = {
+        key: value
+        for key, value in chunk.metadata.items()
+        if isinstance(value, (str, int, float))
+    }
+    attributes["source"] = chunk.source
+    attributes["filename"] = os.path.basename(chunk.source)
+    attributes["created_at"] = chunk.created_at.timestamp()
+    return attributes
+
+
+def _filter_value(value: Any) -> Any:
+    """Compare datetimes as timestamps, like the native filter does."""
+    return value.timestamp() if isinstance(value, datetime) else value
+
+
+def matches_filter(attributes: Dict[str, Any], metadata_filter: Dict[str, Any]) -> bool:
+    """
+    Evaluate a metadata filter against chunk attributes.
+    
+    Each key of the filter names an attribute and every key must match. A
+    condition is either a plain value (equality) or a dict of operators:
+    "$eq", "$in", "$prefix", and the range operators "$gt", "$gte", "$lt"
+    and "$lte". Mirrors the filter applied inside the Rust index.
+    """
+    for key, condition in metadata_filter.items():
+        if key not in attributes:
+            return False
+        value = _filter_value(attributes[key])
+        if not isinstance(condition, dict):
+            condition = {"$eq": condition}
+        for op, operand in condition.items():
+            if op == "$in":
+                operand = [_filter_value(v) for v in operand]
+            else:
+                operand = _filter_value(operand)
+            try:
+                if op == "$eq":
+                    ok = value == operand
+                elif op == "$in":
+                    ok = value in operand
+                elif op == "$prefix":
+                    ok = isinstance(value, str) and value.startswith(operand)
+                elif op == "$gt":
+                    ok = value > operand
+                elif op == "$gte":
+                    ok = value >= operand
+                elif op == "$lt":
+                    ok = value < operand
+                elif op == "$lte":
+                    ok = value <= operand
+                else:
+                    raise ValueError(f"Unknown filter operator {op!r} for {key!r}")
+            except TypeError:
+                # Mixed string / number comparisons never match
+                ok = False
+            if not ok:
+                return False
+    return True
+
 
 class SemanticRetriever:
     """Retriever for semantic search operations with hardware acceleration."""
 
-    def __init__(self, knowledge_base: KnowledgeBase):
-        """Initialize the retriever with a knowledge base."""
+    def __init__(
+        self,
+        knowledge_base: KnowledgeBase,
+        index_type: str = "flat",
+        index_options: Optional[Dict[str, Any]] = None,
+        rerank_factor: int = 0,
+        metric: str = "cosine",
+        embedding_model: Optional[str] = None,
+        vector_source: Optional[Callable[[List[str]], np.ndarray]] = None,
+    ):
+        """
+        Initialize the retriever with a knowledge base.
+        
+        Args:
+            knowledge_base: The knowledge base to search
+            index_type: Native index used by the rust backend: "flat" for an
+                exact scan, "hnsw" for approximate search on large corpora, or
+                "ivfpq" for compressed approximate search in bounded memory, or
+                "int8" / "binary" for a quantized exact scan using 4x / 32x
+                less memory than float32
+            index_options: Extra constructor arguments for the native index,
+                e.g. {"m": 16, "ef_construction": 200, "ef_search": 50} for HNSW
+                or {"nlist": 256, "m": 8, "nprobe": 8} for IVF-PQ
+            rerank_factor: When positive, the native index fetches
+                top_k * rerank_factor candidates which are re-scored by exact
+                cosine against the float embeddings; 0 disables re-ranking
+            metric: Similarity metric: "cosine", "dot" for models trained on
+                inner products, "ip_normalized" for the dot product of unit
+                vectors, or "l2" for Euclidean distance. With "l2" scores are
+                distances and score_threshold is a maximum distance; other
+                metrics are only supported by the "flat" index
+            embedding_model: Model the embeddings come from. Native indexes
+                record it, and chunks whose metadata names another
+                "embedding_model" are refused with ModelMismatchError
+            vector_source: Returns the float embeddings of chunk ids, one row
+                per id, from wherever they were saved. Needed by
+                release_embeddings, after which re-ranking and the NumPy
+                fallback read the released embeddings from it
+        """
+        if index_type not in ("flat", "hnsw", "ivfpq", "int8", "binary"):
+            raise ValueError(f"Unknown index type: {index_type}")
+        if metric not in METRICS:
+            raise ValueError(f"Unknown metric: {metric}")
+        if metric != "cosine" and index_type != "flat":
+            raise ValueError(
+                f"The {index_type} index only supports the cosine metric, not {metric}; "
+                f'use index_type="flat" for {metric}'
+            )
         self.knowledge_base = knowledge_base
+        self.index_type = index_type
+        self.index_options = index_options or {}
+        self.rerank_factor = rerank_factor
+        self.metric = metric
+        self.embedding_model = embedding_model
+        self.vector_source = vector_source
         self._embeddings_cache = None
+        self._rust_index = None
+        self._lexical_index = None
+        self._chunks_by_id: Dict[str, KnowledgeChunk] = {}
+        # Chunks whose float embedding only the compressed native index holds
+        self._released: Set[str] = set()
         self._backend_capabilities = self._detect_backends()
         
         logger.info(f"Initialized SemanticRetriever with {len(knowledge_base)} chunks")
@@ -49,6 +205,7 @@ class SemanticRetriever:
         backends = {
             "mlx": HAS_MLX,
             "jax": HAS_JAX,
+            "rust": HAS_RUST,
             "numpy": True  # Fallback
         }
         
@@ -77,6 +234,8 @@ class SemanticRetriever:
             return "mlx"
         elif self._backend_capabilities.get("jax_gpu", False):
             return "jax"
+        elif self._backend_capabilities.get("rust", False):
+            return "rust"
         elif self._backend_capabilities.get("mlx", False):
             return "mlx"
         elif self._backend_capabilities.get("jax", False):
@@ -89,10 +248,16 @@ class SemanticRetriever:
         if self._embeddings_cache is not None:
             return
         
-        # Get raw embeddings
-        embeddings = self.knowledge_base.get_all_embeddings()
+        # Get raw embeddings, reading back the ones the native index holds
+        chunks = self.knowledge_base.chunks
+        released = [c.chunk_id for c in chunks if c.embedding is None and c.chunk_id in self._released]
+        vectors = dict(zip(released, self.vector_source(released))) if released else {}
+        embeddings = [
+            e for e in (vectors.get(c.chunk_id, c.embedding) for c in chunks) if e is not None
+        ]
         if not embeddings or not all(e is not None for e in embeddings):
             raise ValueError("Knowledge base contains chunks without embeddings")
+        self._check_embeddings(self.knowledge_base.chunks)
         
         # Create backend-specific cache
         self._embeddings_cache = {
@@ -106,6 +271,273 @@ class SemanticRetriever:
         if HAS_JAX:
             self._embeddings_cache["jax"] = jnp.array(embeddings, dtype=jnp.float32)
 
+    def _ensure_rust_index(self) -> None:
+        """Build the native index once; later chunks are added incrementally."""
+        if self._rust_index is not None:
+            return
+        
+        embedded = [c for c in self.knowledge_base.chunks if c.embedding is not None]
+        if not embedded:
+            raise ValueError("Knowledge base contains chunks without embeddings")
+        
+        index = self._new_rust_index(self._check_embeddings(embedded), len(embedded))
+        
+        # IVF-PQ learns its quantizers from the corpus before it can encode
+        if self.index_type == "ivfpq":
+            sample = np.array([c.embedding for c in embedded], dtype=np.float32)
+            # A lone embedding is repeated for the two entries of each codebook
+            index.train(np.repeat(sample, 2, axis=0) if len(sample) == 1 else sample)
+        
+        # Only a complete index is kept, so a failed build is retried by the next search
+        self._add_to_rust_index(index, embedded)
+        self._rust_index = index
+
+    def _new_rust_index(self, dim: int, rows: int) -> Any:
+        """
+        Create an empty native index of the configured type, recording the embedding model.
+        
+        IVF-PQ trains on the rows it will index and needs at least
+        max(nlist, 2 ** nbits) of them, so for fewer rows the index is
+        created with fewer cells and shorter codes than configured.
+        """
+        model = self.embedding_model
+        if self.index_type == "ivfpq":
+            options = dict(self.index_options)
+            options["nlist"] = max(min(options.get("nlist", 256), rows), 1)
+            options["nbits"] = max(min(options.get("nbits", 8), rows.bit_length() - 1), 1)
+            return rust_lib.IvfPqIndex(dim, model=model, **options)
+        if self.index_type in ("int8", "binary"):
+            return rust_lib.QuantizedIndex(dim, kind=self.index_type, model=model, **self.index_options)
+        if self.index_type == "flat":
+            return rust_lib.EmbeddingIndex(dim, metric=self.metric, model=model, **self.index_options)
+        return rust_lib.HnswIndex(dim, model=model, **self.index_options)
+
+    def _check_embeddings(self, chunks: List[KnowledgeChunk], dim: Optional[int] = None) -> Optional[int]:
+        """
+        Check that embedded chunks share one dimension and come from the retriever's model.
+        
+        dim is the dimension they must have, by default the first chunk's.
+        Chunks whose metadata does not name an "embedding_model" are taken
+        to be from the retriever's. Returns the dimension.
+        """
+        for chunk in chunks:
+            if chunk.embedding is None:
+                continue
+            model = chunk.metadata.get("embedding_model")
+            if model and self.embedding_model and model != self.embedding_model:
+                raise ModelMismatchError(
+                    f"embeddings of {model} do not belong in an index of {self.embedding_model}"
+                )
+            if dim is None:
+                dim = len(chunk.embedding)
+            elif len(chunk.embedding) != dim:
+                raise DimensionMismatchError(
+                    f"embedding has dimension {len(chunk.embedding)}, expected {dim}"
+                )
+        return dim
+
+    def load_index(self, store: Any, model: str) -> None:
+        """
+        Build the native index straight from a KnowledgeStore's embeddings for model.
+        
+        The rows go from SQLite into the index inside the Rust crate; an
+        IVF-PQ index is trained on them first. Raises KeyError when the
+        store holds no embeddings for model.
+        """
+        found = next(((dim, count) for name, dim, count in store.models() if name == model), None)
+        if found is None:
+            raise KeyError(model)
+        dim, rows = found
+        index = self._new_rust_index(dim, rows)
+        store.load_index(index, model)
+        self.adopt_index(index)
+
+    def _ensure_lexical_index(self) -> None:
+        """Build the native BM25 index over every chunk's text once."""
+        if self._lexical_index is not None:
+            return
+        
+        chunks = self.knowledge_base.chunks
+        index = rust_lib.Bm25Index()
+        index.add_many(
+            [c.chunk_id for c in chunks],
+            [c.content for c in chunks],
+            [chunk_attributes(c) for c in chunks],
+        )
+        self._chunks_by_id.update((c.chunk_id, c) for c in chunks)
+        self._lexical_index = index
+
+    def index_chunks(self, chunks: List[KnowledgeChunk]) -> None:
+        """
+        Add newly embedded chunks to the native indexes without rebuilding them.
+        
+        Does nothing until an index has been built by a first Rust search,
+        since that build picks up every chunk in the knowledge base.
+        """
+        if self._lexical_index is not None and chunks:
+            self._lexical_index.add_many(
+                [c.chunk_id for c in chunks],
+                [c.content for c in chunks],
+                [chunk_attributes(c) for c in chunks],
+            )
+            self._chunks_by_id.update((c.chunk_id, c) for c in chunks)
+        
+        if self._rust_index is None:
+            return
+        
+        embedded = [c for c in chunks if c.embedding is not None]
+        if embedded:
+            self._add_to_rust_index(self._rust_index, embedded)
+
+    def _add_to_rust_index(self, index: Any, chunks: List[KnowledgeChunk]) -> None:
+        """Add embedded chunks to a native index."""
+        self._check_embeddings(chunks, index.dim)
+        
+        # One float32 matrix crosses into Rust as a buffer, not row by row
+        chunk_ids = [c.chunk_id for c in chunks]
+        embeddings = np.array([c.embedding for c in chunks], dtype=np.float32)
+        if self.index_type == "flat":
+            # The flat index filters on attributes during its scan
+            index.add_many(
+                chunk_ids,
+                embeddings,
+                [chunk_attributes(c) for c in chunks],
+                model=self.embedding_model,
+            )
+        else:
+            index.add_many(chunk_ids, embeddings, model=self.embedding_model)
+        self._chunks_by_id.update((c.chunk_id, c) for c in chunks)
+
+    def stored_index(self) -> Any:
+        """The built native index if it is worth saving with the knowledge base, else None."""
+        return self._rust_index if self.index_type in ("hnsw", "ivfpq") else None
+
+    def adopt_index(self, index: Any) -> None:
+        """Use a native index loaded from disk, built over the knowledge base's embedded chunks."""
+        if index.model and self.embedding_model and index.model != self.embedding_model:
+            raise ModelMismatchError(
+                f"an index of {index.model} cannot serve queries embedded by {self.embedding_model}"
+            )
+        self._embeddings_cache = None
+        self._lexical_index = None
+        self._rust_index = index
+        self._chunks_by_id.update(
+            (c.chunk_id, c) for c in self.knowledge_base.chunks if self.has_embedding(c)
+        )
+
+    def has_embedding(self, chunk: KnowledgeChunk) -> bool:
+        """Whether chunk is embedded, even if only the native index holds its embedding."""
+        return chunk.embedding is not None or chunk.chunk_id in self._released
+
+    def release_embeddings(self, chunks: Optional[List[KnowledgeChunk]] = None) -> int:
+        """
+        Drop the float embeddings of chunks held by a compressed native index.
+        
+        An "ivfpq", "int8" or "binary" index only saves memory once the
+        chunks no longer keep a List[float] of their own; afterwards only the
+        index's codes stay in memory. Call it once the embeddings have been
+        saved where vector_source reads them from. Chunks defaults to the
+        whole knowledge base. Returns the number of embeddings dropped.
+        """
+        if self.index_type not in COMPRESSED_INDEX_TYPES or self._rust_index is None:
+            return 0
+        if self.vector_source is None:
+            return 0
+        released = 0
+        for chunk in self.knowledge_base.chunks if chunks is None else chunks:
+            if chunk.chunk_id in self._rust_index and chunk.chunk_id not in self._released:
+                chunk.embedding = None
+                self._released.add(chunk.chunk_id)
+                self._chunks_by_id[chunk.chunk_id] = chunk
+                released += 1
+        return released
+
+    def remove_chunks(self, chunk_ids: List[str]) -> None:
+        """Drop chunks that left the knowledge base from the native indexes."""
+        self._embeddings_cache = None
+        for chunk_id in chunk_ids:
+            self._released.discard(chunk_id)
+            if self._chunks_by_id.pop(chunk_id, None) is None:
+                continue
+            for index in (self._rust_index, self._lexical_index):
+                if index is None:
+                    continue
+                try:
+                    index.remove(chunk_id)
+                except KeyError:
+                    pass  # Chunks without an embedding are only in the lexical index
+
+    def _rerank(
+        self,
+        query: np.ndarray,
+        matches: List[Tuple[str, float]],
+        top_k: int,
+        score_threshold: Optional[float],
+    ) -> List[Tuple[str, float]]:
+        """Re-score native index candidates by exact cosine on float embeddings."""
+        if not matches:
+            return matches
+        chunk_ids = [chunk_id for chunk_id, _ in matches]
+        # Released embeddings are read back from where they were saved
+        released = [chunk_id for chunk_id in chunk_ids if chunk_id in self._released]
+        vectors = dict(zip(released, self.vector_source(released))) if released else {}
+        embeddings = np.array(
+            [
+                vectors[chunk_id] if chunk_id in vectors else self._chunks_by_id[chunk_id].embedding
+                for chunk_id in chunk_ids
+            ],
+            dtype=np.float32,
+        )
+        if score_threshold is None:
+            score_threshold = -math.inf
+        return rust_lib.rerank(query, chunk_ids, embeddings, top_k, score_threshold)
+
+    def _rust_search(
+        self,
+        queries: np.ndarray,
+        top_k: int,
+        score_threshold: Optional[float],
+        metadata_filter: Optional[Dict[str, Any]] = None,
+    ) -> List[List[Tuple[str, float]]]:
+        """Search the native index for each row of queries, re-ranking if enabled."""
+        self._ensure_rust_index()
+        model = self.embedding_model
+        if self.index_type == "flat":
+            return self._rust_index.batch_search(
+                queries, top_k, score_threshold, metadata_filter, model=model
+            )
+        if metadata_filter is not None:
+            raise ValueError(f"The {self.index_type} index does not support metadata filters")
+        if self.rerank_factor <= 0:
+            # The approximate indexes are cosine-only and take a float threshold
+            if score_threshold is None:
+                score_threshold = -math.inf
+            return self._rust_index.batch_search(queries, top_k, score_threshold, model=model)
+        
+        # Quantized scores are approximate, so the threshold is applied after re-ranking
+        candidates = self._rust_index.batch_search(queries, top_k * self.rerank_factor, model=model)
+        return [
+            self._rerank(query, matches, top_k, score_threshold)
+            for query, matches in zip(queries, candidates)
+        ]
+
+    @staticmethod
+    def _rust_backend_name() -> str:
+        """Name the Rust backend after its distance kernel, e.g. "rust-avx2"."""
+        return f"rust-{rust_lib.simd_kernel()}"
+
+    @staticmethod
+    def _numpy_scores(query: np.ndarray, docs: np.ndarray, metric: str) -> np.ndarray:
+        """Score docs against query with NumPy under any supported metric."""
+        if metric == "cosine":
+            return SemanticRetriever._numpy_cosine_sim(query, docs)
+        if metric == "dot":
+            return np.dot(docs, query)
+        if metric == "ip_normalized":
+            unit_docs = docs / np.maximum(np.linalg.norm(docs, axis=1, keepdims=True), 1e-8)
+            return np.dot(unit_docs, query / max(np.linalg.norm(query), 1e-8))
+        return np.linalg.norm(docs - query, axis=1)
+
     @staticmethod
     def _numpy_cosine_sim(query: np.ndarray, docs: np.ndarray) -> np.ndarray:
         """Compute cosine similarity with NumPy."""
@@ -164,8 +596,9 @@ class SemanticRetriever:
         self, 
         query_embedding: List[float],
         top_k: int = 3,
-        score_threshold: float = 0.6,
-        backend: Optional[str] = None
+        score_threshold: Optional[float] = 0.6,
+        backend: Optional[str] = None,
+        metadata_filter: Optional[Dict[str, Any]] = None,
     ) -> Tuple[List[Dict[str, Any]], str, float]:
         """
         Perform semantic search against the knowledge base.
@@ -173,17 +606,18 @@ class SemanticRetriever:
         Args:
             query_embedding: The embedding vector of the query
             top_k: Number of top results to return
-            score_threshold: Minimum similarity score for results
-            backend: Preferred backend (mlx, jax, or numpy)
+            score_threshold: Minimum similarity score for results, or maximum
+                distance with the "l2" metric; None keeps every result
+            backend: Preferred backend (mlx, jax, rust, or numpy)
+            metadata_filter: Only consider chunks whose attributes match, e.g.
+                {"source_type": "markdown", "created_at": {"$gte": since}};
+                see matches_filter for the supported operators
             
         Returns:
             A tuple of (search_results, backend_used, execution_time_ms)
         """
         start_time = time.time()
         
-        # Ensure embeddings are cached
-        self._ensure_embeddings_cache()
-        
         # Convert query to numpy array for initial processing
         query_np = np.array(query_embedding, dtype=np.float32)
         
@@ -191,12 +625,43 @@ class SemanticRetriever:
         selected_backend = self._select_backend(query_np, prefer_backend=backend)
         logger.debug(f"Selected backend for search: {selected_backend}")
         
+        # The Rust index scores, thresholds and ranks in a single native pass
+        if selected_backend == "rust":
+            try:
+                top_matches = self._rust_search(
+                    query_np[None, :], top_k, score_threshold, metadata_filter
+                )[0]
+                results = [
+                    self._result_for_chunk(self._chunks_by_id[chunk_id], score)
+                    for chunk_id, score in top_matches
+                ]
+                execution_time_ms = (time.time() - start_time) * 1000
+                return results, self._rust_backend_name(), execution_time_ms
+            except EmbeddingMismatchError:
+                # Another backend would score the same mismatched vectors
+                raise
+            except Exception as e:
+                logger.warning(f"Error with rust backend: {e}, falling back to numpy")
+                selected_backend = "numpy"
+        
+        # Ensure embeddings are cached
+        self._ensure_embeddings_cache()
+        
         # Get embeddings for the selected backend
         docs_embeddings = self._embeddings_cache[selected_backend if selected_backend in self._embeddings_cache else "numpy"]
+        dim = self._embeddings_cache["numpy"].shape[1]
+        if query_np.shape != (dim,):
+            raise DimensionMismatchError(
+                f"embedding has dimension {query_np.size}, expected {dim}"
+            )
         
         # Compute similarity scores with the selected backend
         try:
-            if selected_backend == "mlx":
+            if self.metric != "cosine":
+                # The MLX and JAX kernels are cosine-only
+                scores = self._numpy_scores(query_np, self._embeddings_cache["numpy"], self.metric)
+                selected_backend = "numpy"
+            elif selected_backend == "mlx":
                 scores = self._mlx_cosine_sim(query_np, docs_embeddings)
             elif selected_backend == "jax":
                 scores = self._jax_cosine_sim(query_np, docs_embeddings)
@@ -204,39 +669,193 @@ class SemanticRetriever:
                 scores = self._numpy_cosine_sim(query_np, docs_embeddings)
         except Exception as e:
             logger.warning(f"Error with {selected_backend} backend: {e}, falling back to numpy")
-            scores = self._numpy_cosine_sim(query_np, self._embeddings_cache["numpy"])
+            scores = self._numpy_scores(query_np, self._embeddings_cache["numpy"], self.metric)
             selected_backend = "numpy"
 
         # Convert scores to list
         scores_list = scores.tolist()
         
-        # Get top-k indices and filter by threshold
+        # Get top-k indices and filter by threshold; l2 distances rank lowest first
+        lower_is_better = self.metric == "l2"
         results = []
         for i, score in enumerate(scores_list):
-            if score >= score_threshold:
-                chunk = self.knowledge_base.chunks[i]
-                results.append({
-                    "chunk_id": chunk.chunk_id,
-                    "content": chunk.content,
-                    "source": chunk.source,
-                    "score": float(score),
-                    "metadata": chunk.metadata
-                })
-        
-        # Sort by score descending and limit to top_k
-        results = sorted(results, key=lambda x: x["score"], reverse=True)[:top_k]
+            chunk = self.knowledge_base.chunks[i]
+            if metadata_filter is not None and not matches_filter(
+                chunk_attributes(chunk), metadata_filter
+            ):
+                continue
+            if score_threshold is None or (
+                score <= score_threshold if lower_is_better else score >= score_threshold
+            ):
+                results.append(self._result_for_chunk(chunk, score))
+        
+        # Sort best first and limit to top_k
+        results = sorted(results, key=lambda x: x["score"], reverse=not lower_is_better)[:top_k]
         
         # Calculate execution time
         execution_time_ms = (time.time() - start_time) * 1000
         
         return results, selected_backend, execution_time_ms
 
+    def batch_semantic_search(
+        self,
+        query_embeddings: List[List[float]],
+        top_k: int = 3,
+        score_threshold: Optional[float] = 0.6,
+        backend: Optional[str] = None,
+        metadata_filter: Optional[Dict[str, Any]] = None,
+    ) -> Tuple[List[List[Dict[str, Any]]], str, float]:
+        """
+        Perform semantic search for many queries at once.
+        
+        With the Rust backend all queries are scored in parallel across CPU
+        cores without holding the GIL; other backends search one at a time.
+        
+        Args:
+            query_embeddings: The embedding vectors of the queries
+            top_k: Number of top results to return per query
+            score_threshold: Minimum similarity score for results, or maximum
+                distance with the "l2" metric; None keeps every result
+            backend: Preferred backend (mlx, jax, rust, or numpy)
+            metadata_filter: Only consider chunks whose attributes match, e.g.
+                {"source_type": "markdown", "created_at": {"$gte": since}};
+                see matches_filter for the supported operators
+            
+        Returns:
+            A tuple of (per_query_results, backend_used, execution_time_ms)
+        """
+        start_time = time.time()
+        
+        queries_np = np.array(query_embeddings, dtype=np.float32)
+        selected_backend = self._select_backend(queries_np, prefer_backend=backend)
+        
+        if selected_backend == "rust":
+            try:
+                batch_matches = self._rust_search(
+                    queries_np, top_k, score_threshold, metadata_filter
+                )
+                results = [
+                    [
+                        self._result_for_chunk(self._chunks_by_id[chunk_id], score)
+                        for chunk_id, score in matches
+                    ]
+                    for matches in batch_matches
+                ]
+                execution_time_ms = (time.time() - start_time) * 1000
+                return results, self._rust_backend_name(), execution_time_ms
+            except EmbeddingMismatchError:
+                # Another backend would score the same mismatched vectors
+                raise
+            except Exception as e:
+                logger.warning(f"Error with rust backend: {e}, falling back to numpy")
+                selected_backend = "numpy"
+        
+        results = []
+        for query_embedding in query_embeddings:
+            query_results, selected_backend, _ = self.semantic_search(
+                query_embedding=query_embedding,
+                top_k=top_k,
+                score_threshold=score_threshold,
+                backend=selected_backend,
+                metadata_filter=metadata_filter,
+            )
+            results.append(query_results)
+        
+        execution_time_ms = (time.time() - start_time) * 1000
+        return results, selected_backend, execution_time_ms
+
+    def hybrid_search(
+        self,
+        query_text: str,
+        query_embedding: List[float],
+        top_k: int = 3,
+        score_threshold: Optional[float] = 0.6,
+        backend: Optional[str] = None,
+        metadata_filter: Optional[Dict[str, Any]] = None,
+        fusion: str = "rrf",
+        weights: Optional[Tuple[float, float]] = None,
+        candidates: int = 50,
+    ) -> Tuple[List[Dict[str, Any]], str, float]:
+        """
+        Combine BM25 keyword search with semantic search.
+        
+        Lexical matching finds exact identifiers, error codes and function
+        names that embeddings blur. Both result lists are fused in Rust and
+        each result's score is its fused score.
+        
+        Args:
+            query_text: The raw query text, matched lexically
+            query_embedding: The embedding vector of the query
+            top_k: Number of top results to return
+            score_threshold: Threshold for the semantic results only, as in
+                semantic_search; keyword matches are never thresholded
+            backend: Preferred backend for the semantic half
+            metadata_filter: Only consider chunks whose attributes match
+            fusion: "rrf" for reciprocal rank fusion, or "weighted" to blend
+                min-max normalized scores
+            weights: (semantic, lexical) weights for fusion, 1.0 each by default
+            candidates: Number of results fetched from each side before fusion
+            
+        Returns:
+            A tuple of (search_results, backend_used, execution_time_ms)
+        """
+        start_time = time.time()
+        
+        semantic_results, backend_used, _ = self.semantic_search(
+            query_embedding=query_embedding,
+            top_k=max(candidates, top_k),
+            score_threshold=score_threshold,
+            backend=backend,
+            metadata_filter=metadata_filter,
+        )
+        if not HAS_RUST:
+            logger.warning("Hybrid search needs the Rust accelerator, returning semantic results")
+            execution_time_ms = (time.time() - start_time) * 1000
+            return semantic_results[:top_k], backend_used, execution_time_ms
+        
+        self._ensure_lexical_index()
+        lexical_matches = self._lexical_index.search(
+            query_text, max(candidates, top_k), metadata_filter
+        )
+        
+        # Fusion expects higher-is-better scores, so l2 distances are negated
+        sign = -1.0 if self.metric == "l2" else 1.0
+        semantic_matches = [(r["chunk_id"], sign * r["score"]) for r in semantic_results]
+        fused = rust_lib.fuse(
+            [semantic_matches, lexical_matches],
+            top_k,
+            method=fusion,
+            weights=list(weights) if weights is not None else None,
+        )
+        
+        # Reuse the semantic result entries; keyword-only hits are built here
+        by_id = {r["chunk_id"]: r for r in semantic_results}
+        results = [
+            {**by_id[chunk_id], "score": float(score)} if chunk_id in by_id
+            else self._result_for_chunk(self._chunks_by_id[chunk_id], score)
+            for chunk_id, score in fused
+        ]
+        execution_time_ms = (time.time() - start_time) * 1000
+        return results, f"{backend_used}+bm25", execution_time_ms
+
+    @staticmethod
+    def _result_for_chunk(chunk: KnowledgeChunk, score: float) -> Dict[str, Any]:
+        """Build a search result entry for a chunk."""
+        return {
+            "chunk_id": chunk.chunk_id,
+            "content": chunk.content,
+            "source": chunk.source,
+            "score": float(score),
+            "metadata": chunk.metadata
+        }
+
     def query(
         self,
         query_embedding: List[float],
         top_k: int = 3,
-        score_threshold: float = 0.6,
-        backend: Optional[str] = None
+        score_threshold: Optional[float] = 0.6,
+        backend: Optional[str] = None,
+        metadata_filter: Optional[Dict[str, Any]] = None,
     ) -> SearchResults:
         """
         Execute a query and return a structured SearchResults object.
@@ -244,8 +863,12 @@ class SemanticRetriever:
         Args:
             query_embedding: The embedding vector of the query
             top_k: Number of top results to return
-            score_threshold: Minimum similarity score for results
-            backend: Preferred backend (mlx, jax, or numpy)
+            score_threshold: Minimum similarity score for results, or maximum
+                distance with the "l2" metric; None keeps every result
+            backend: Preferred backend (mlx, jax, rust, or numpy)
+            metadata_filter: Only consider chunks whose attributes match, e.g.
+                {"source_type": "markdown", "created_at": {"$gte": since}};
+                see matches_filter for the supported operators
             
         Returns:
             A SearchResults object with the results and metadata
@@ -254,7 +877,8 @@ class SemanticRetriever:
             query_embedding=query_embedding,
             top_k=top_k,
             score_threshold=score_threshold,
-            backend=backend
+            backend=backend,
+            metadata_filter=metadata_filter,
         )
         
         return SearchResults(
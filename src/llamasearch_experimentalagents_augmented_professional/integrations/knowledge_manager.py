@@ -1,161 +1,920 @@
 """
 Manages the loading, chunking, embedding, and retrieval of knowledge.
 """
+import hashlib
+import json
 import logging
 import os
+import re
+import sys
+import uuid
+from dataclasses import replace
+from datetime import datetime
 from pathlib import Path
-from typing import List, Optional, Dict, Any
+from typing import List, Optional, Dict, Any, Tuple
 import time
 
+import numpy as np
 from openai import OpenAI
 
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
 from ..models.models_knowledge import KnowledgeBase, KnowledgeChunk
-from ..agents.agents_retriever import SemanticRetriever  # May consolidate later
+from ..agents.agents_retriever import (  # May consolidate later
+    COMPRESSED_INDEX_TYPES,
+    EmbeddingMismatchError,
+    SemanticRetriever,
+)
 
 logger = logging.getLogger(__name__)
 
 # Define default chunking parameters (can be made configurable)
 DEFAULT_CHUNK_MIN_LENGTH = 20
+DEFAULT_CHUNK_STRATEGY = "markdown"
+DEFAULT_CHUNK_SIZE = 1000
+DEFAULT_CHUNK_OVERLAP = 200
+
+# "paragraph" is the pure-Python fallback and was the default before "markdown";
+# the others run in the Rust chunker. "markdown" splits .md files on headings and
+# other files like "recursive". "tokens" counts approximate word-piece tokens,
+# not the embedding model's.
+CHUNK_STRATEGIES = ("paragraph", "fixed", "sentence", "recursive", "tokens", "markdown")
+MARKDOWN_SUFFIXES = (".md", ".markdown")
+
+# Source files are split at function and class boundaries by the Rust chunker
+CODE_LANGUAGES = {
+    ".py": "python",
+    ".rs": "rust",
+    ".js": "javascript",
+    ".mjs": "javascript",
+    ".jsx": "javascript",
+    ".go": "go",
+}
+TEXT_SUFFIXES = (".md", ".txt") + tuple(CODE_LANGUAGES)
+# Documents only readable through the Rust accelerator; HTML, DOCX and EPUB
+# are converted to markdown and chunked along their headings
+NATIVE_SUFFIXES = (".pdf", ".html", ".htm", ".xhtml", ".docx", ".epub")
+DOCUMENT_SUFFIXES = TEXT_SUFFIXES + NATIVE_SUFFIXES
+
+# Dependency and build directories are never indexed
+SKIPPED_DIRECTORIES = {".git", "node_modules", "target", "__pycache__", ".venv", "venv"}
+
+# Incremental ingestion state of each loaded directory, kept outside it: the Rust
+# manifest of ingested files and a knowledge base file holding the embeddings
+# already paid for and the index built over them
+MANIFEST_FILE = "manifest.json"
+KNOWLEDGE_BASE_FILE = "knowledge.llkb"
+
+# Namespace for chunk ids derived from source, byte range and content
+CHUNK_ID_NAMESPACE = uuid.uuid5(uuid.NAMESPACE_URL, "llamasearch/chunks")
+
+
+def paragraph_spans(content: str) -> List[Tuple[int, int, str]]:
+    """Split text on blank lines, returning (start_byte, end_byte, paragraph) triples."""
+    spans = []
+    byte_offset = 0
+    char_offset = 0
+    for match in re.finditer(r"\S(?:.|\n(?!\s*\n))*", content):
+        # Advance the byte offset incrementally instead of re-encoding the prefix
+        byte_offset += len(content[char_offset:match.start()].encode("utf-8"))
+        paragraph = match.group().rstrip()
+        end = byte_offset + len(paragraph.encode("utf-8"))
+        spans.append((byte_offset, end, paragraph))
+        byte_offset, char_offset = end, match.start() + len(paragraph)
+    return spans
+
+
+def chunk_id_for(source: str, start: int, end: int, content: str) -> str:
+    """Derive a stable chunk id so re-ingesting a file reproduces the same ids."""
+    return str(uuid.uuid5(CHUNK_ID_NAMESPACE, f"{source}:{start}:{end}:{content}"))
+
+
+def user_cache_dir() -> Path:
+    """The platform's per-user cache directory."""
+    if sys.platform == "win32":
+        return Path(os.environ.get("LOCALAPPDATA") or Path.home() / "AppData" / "Local")
+    if sys.platform == "darwin":
+        return Path.home() / "Library" / "Caches"
+    return Path(os.environ.get("XDG_CACHE_HOME") or Path.home() / ".cache")
+
+
+def state_path_for(state_dir: Path, knowledge_path: Path) -> Path:
+    """Directory under state_dir keeping the ingestion state of one knowledge directory."""
+    resolved = knowledge_path.resolve()
+    # Keyed by the resolved path, so directories with the same name do not collide
+    digest = hashlib.sha256(str(resolved).encode("utf-8")).hexdigest()[:16]
+    return state_dir / f"{resolved.name}-{digest}"
 
 class KnowledgeManager:
     """Handles knowledge base operations: loading, embedding, searching."""
 
     def __init__(
         self,
-        openai_client: OpenAI,
+        openai_client: Optional[OpenAI],
         embedding_model: str = "text-embedding-3-small",
         knowledge_base: Optional[KnowledgeBase] = None,
+        index_type: str = "flat",
+        index_options: Optional[Dict[str, Any]] = None,
+        rerank_factor: int = 0,
+        metric: str = "cosine",
+        chunk_strategy: str = DEFAULT_CHUNK_STRATEGY,
+        chunk_size: int = DEFAULT_CHUNK_SIZE,
+        chunk_overlap: int = DEFAULT_CHUNK_OVERLAP,
+        store_path: Optional[str] = None,
+        local_model_path: Optional[str] = None,
+        reranker_path: Optional[str] = None,
+        rerank_candidates: int = 20,
+        state_dir: Optional[str] = None,
     ):
+        if chunk_strategy not in CHUNK_STRATEGIES:
+            raise ValueError(f"Unknown chunk strategy: {chunk_strategy}")
+        if chunk_strategy != "paragraph" and not HAS_RUST:
+            logger.warning(f"Chunk strategy {chunk_strategy!r} needs the Rust accelerator, splitting paragraphs instead")
+            chunk_strategy = "paragraph"
+        self.chunk_strategy = chunk_strategy
+        self.chunk_size = chunk_size
+        self.chunk_overlap = chunk_overlap
+        # Incremental loads keep each directory's state here, never inside the directory
+        self.state_dir = Path(state_dir) if state_dir else user_cache_dir() / "llamasearch"
         self.client = openai_client
+        # Local sentence-transformer models embed chunks and queries on the CPU,
+        # without the API; their embeddings are recorded under the model's name
+        self.local_models: Dict[str, Any] = {}
+        if local_model_path:
+            embedding_model = self._load_local_model(local_model_path)
+        elif openai_client is None:
+            raise ValueError("Embedding needs an OpenAI client or a local model")
         self.embedding_model = embedding_model
+        # A local cross-encoder reorders the top rerank_candidates search results
+        self.reranker = None
+        self.rerank_candidates = rerank_candidates
+        if reranker_path:
+            if not HAS_RUST:
+                raise RuntimeError("Reranking needs the Rust accelerator")
+            self.reranker = rust_lib.CrossEncoder(reranker_path)
+            logger.info(f"Loaded cross-encoder {self.reranker.name} for reranking")
         self.kb = knowledge_base or KnowledgeBase(name="Managed KB")
-        self.retriever = SemanticRetriever(self.kb) # Retriever uses the KB
+        # Rows of the knowledge base files the embeddings were last saved to,
+        # memory-mapped, by chunk id
+        self._saved_vectors: Dict[str, Tuple[np.ndarray, int]] = {}
+        # Quantized index types ("int8", "binary") and IVF-PQ cut memory for large
+        # directories: chunks drop their float embeddings once saved and indexed
+        self.retriever = SemanticRetriever(
+            self.kb,
+            index_type=index_type,
+            index_options=index_options,
+            rerank_factor=rerank_factor,
+            metric=metric,
+            embedding_model=embedding_model,
+            vector_source=self._saved_embeddings,
+        )
+        # Directories kept live by watch_directory, with their native watchers
+        self._watchers: Dict[Path, Any] = {}
+        # One SQLite file per knowledge base that chunks and embeddings are written through to
+        self.store = None
+        if store_path:
+            if HAS_RUST:
+                self.store = rust_lib.KnowledgeStore(store_path)
+                self._load_store()
+            else:
+                logger.warning("A knowledge store needs the Rust accelerator, keeping chunks in memory only")
         logger.info(f"KnowledgeManager initialized with embedding model: {embedding_model}")
 
-    def load_documents_from_directory(self, dir_path: str, embed_immediately: bool = True):
-        """Loads documents from a directory, chunks them, and optionally embeds."""
+    def split_text(self, content: str) -> List[Tuple[int, int, str]]:
+        """
+        Chunk a document with the configured strategy.
+        
+        Returns (start_byte, end_byte, text) triples, where the byte range
+        locates the chunk in the UTF-8 encoded document.
+        """
+        if self.chunk_strategy == "paragraph":
+            return paragraph_spans(content)
+        return rust_lib.chunk_text(
+            content,
+            strategy="recursive" if self.chunk_strategy == "markdown" else self.chunk_strategy,
+            size=self.chunk_size,
+            overlap=self.chunk_overlap,
+        )
+
+    def split_markdown(self, content: str) -> List[Tuple[int, int, str, List[str]]]:
+        """
+        Chunk a markdown document along its headings.
+        
+        Returns (start_byte, end_byte, text, heading_path) tuples, where
+        heading_path lists the titles of the headings enclosing the chunk,
+        outermost first. Code blocks and tables are never split.
+        """
+        return rust_lib.chunk_markdown(content, size=self.chunk_size)
+
+    def split_code(self, content: str, language: str) -> List[Tuple[int, int, str, Optional[str], int, int]]:
+        """
+        Chunk a source file at function and class boundaries.
+        
+        Returns (start_byte, end_byte, text, symbol, start_line, end_line)
+        tuples, where symbol is the qualified name of the enclosing definition
+        (e.g. "SemanticRetriever.query", None for module-level code) and the
+        line range is 1-based and inclusive.
+        """
+        return rust_lib.chunk_code(content, language, size=self.chunk_size)
+
+    def split_document(self, file_path: Path, content: str) -> Tuple[str, List[Tuple[int, int, str, Dict[str, Any]]]]:
+        """
+        Chunk one file with the chunker suited to its type.
+        
+        Returns the strategy used and (start_byte, end_byte, text, metadata)
+        tuples, where metadata holds the chunker's extra fields such as the
+        heading path or the symbol and line range.
+        """
+        suffix = file_path.suffix.lower()
+        if suffix in CODE_LANGUAGES and HAS_RUST:
+            language = CODE_LANGUAGES[suffix]
+            pieces = []
+            for start, end, text, symbol, start_line, end_line in self.split_code(content, language):
+                metadata = {"language": language, "start_line": start_line, "end_line": end_line}
+                if symbol:
+                    metadata["symbol"] = symbol
+                pieces.append((start, end, text, metadata))
+            return "code", pieces
+        if self.chunk_strategy == "markdown" and suffix in MARKDOWN_SUFFIXES:
+            pieces = []
+            for start, end, text, heading_path in self.split_markdown(content):
+                # Breadcrumb such as "Neural Networks > Training > Backprop"
+                metadata = {"heading_path": " > ".join(heading_path)} if heading_path else {}
+                pieces.append((start, end, text, metadata))
+            return "markdown", pieces
+        strategy = "recursive" if self.chunk_strategy == "markdown" else self.chunk_strategy
+        return strategy, [(start, end, text, {}) for start, end, text in self.split_text(content)]
+
+    def load_documents_from_directory(self, dir_path: str, embed_immediately: bool = True, incremental: bool = True):
+        """
+        Loads documents from a directory, chunks them, and optionally embeds.
+        
+        With incremental=True and the Rust accelerator, only chunks added or
+        changed since the last load are embedded; see _sync_directory.
+        """
         knowledge_path = Path(dir_path)
         if not knowledge_path.exists() or not knowledge_path.is_dir():
             logger.error(f"Knowledge directory not found or not a directory: {dir_path}")
             return
 
-        text_files = list(knowledge_path.glob("**/*.md")) + list(knowledge_path.glob("**/*.txt"))
-        if not text_files:
-            logger.warning(f"No .md or .txt files found in {dir_path}")
+        if HAS_RUST and self.chunk_strategy != "paragraph" and incremental:
+            self._sync_directory(knowledge_path, embed_immediately)
             return
+        if HAS_RUST and self.chunk_strategy != "paragraph":
+            new_chunks = self._ingest_directory_natively(knowledge_path)
+        else:
+            new_chunks = self._load_files_serially(knowledge_path)
+
+        # Chunk ids derive from the content, so chunks loaded before are not added twice
+        present = {chunk.chunk_id for chunk in self.kb.chunks}
+        new_chunks = [chunk for chunk in new_chunks if chunk.chunk_id not in present]
+        if new_chunks:
+            self.kb.add_chunks(new_chunks)
+            self._persist(new_chunks)
+            logger.info(f"Added {len(new_chunks)} new chunks to the knowledge base.")
+            if embed_immediately:
+                self.generate_embeddings_for_new_chunks()
+        else:
+             logger.info("No new chunks were added from the directory.")
+
+    def _sync_directory(self, knowledge_path: Path, embed_immediately: bool) -> None:
+        """
+        Bring the knowledge base in line with the directory, re-embedding only what changed.
+        
+        The Rust manifest reports which chunks were added, changed or deleted
+        since the last load. The manifest and a knowledge base file are kept
+        under state_dir, in a directory keyed by the resolved path of the
+        knowledge directory. A knowledge base that is still empty first loads
+        the chunks, embeddings and index saved by the previous sync. Stale
+        chunks leave the knowledge base and indexes, and new chunks reuse any
+        embedding already computed for the same content hash before the rest
+        are sent to the embedding API.
+        """
+        state_path = state_path_for(self.state_dir, knowledge_path)
+        saved = state_path / KNOWLEDGE_BASE_FILE
+        loaded = set()
+        if not self.kb.chunks and saved.exists():
+            try:
+                loaded = {chunk.chunk_id for chunk in self.load_knowledge_base(str(saved))}
+            except ValueError as e:
+                logger.warning(f"Ignoring unreadable knowledge base file: {e}")
+
+        delta = rust_lib.ingest_changes(
+            str(knowledge_path),
+            str(state_path / MANIFEST_FILE),
+            globs=[f"*{suffix}" for suffix in DOCUMENT_SUFFIXES],
+            ignore=[f"{name}/" for name in SKIPPED_DIRECTORIES],
+            strategy=self.chunk_strategy,
+            size=self.chunk_size,
+            overlap=self.chunk_overlap,
+        )
+        for error in delta.errors:
+            logger.error(f"Error loading file {error}")
+        logger.info(
+            f"{knowledge_path}: {len(delta.added)} chunks added, {len(delta.changed)} changed, "
+            f"{len(delta.deleted)} deleted, {len(delta.unchanged)} unchanged"
+        )
+
+        reusable = {
+            chunk.metadata["content_hash"]: chunk
+            for chunk in self.kb.chunks
+            if self.retriever.has_embedding(chunk) and "content_hash" in chunk.metadata
+            and chunk.metadata.get("embedding_model", self.embedding_model) == self.embedding_model
+        }
+        chunk_id = lambda c: chunk_id_for(c.source, c.start_byte, c.end_byte, c.text)
+        current = list(delta.added) + [new for _, new in delta.changed] + list(delta.unchanged)
+        stale = {chunk_id(c) for c in [old for old, _ in delta.changed] + list(delta.deleted)}
+        # Saved chunks the manifest no longer knows about, e.g. after an interrupted sync
+        stale |= loaded - {chunk_id(c) for c in current}
+
+        # Embeddings dropped by a compressed index are read back before their chunks leave
+        kept = {chunk.chunk_id for chunk in self.kb.chunks} - stale
+        wanted = {item.content_hash for item in current if chunk_id(item) not in kept}
+        released = [c for h, c in reusable.items() if h in wanted and c.embedding is None]
+        embeddings = {h: c.embedding for h, c in reusable.items() if c.embedding is not None}
+        if released:
+            vectors = self._saved_embeddings([c.chunk_id for c in released])
+            embeddings.update(
+                (c.metadata["content_hash"], vector.tolist()) for c, vector in zip(released, vectors)
+            )
+        self._remove_chunks(stale)
 
-        logger.info(f"Loading {len(text_files)} files from {dir_path}...")
+        # Unchanged chunks are only new to a knowledge base that has not loaded them yet
+        present = {chunk.chunk_id for chunk in self.kb.chunks}
+        new_chunks = []
+        for item in current:
+            chunk = self._chunk_from_ingested(item)
+            if chunk is None or chunk.chunk_id in present:
+                continue
+            if item.content_hash in embeddings:
+                chunk.embedding = embeddings[item.content_hash]
+                chunk.metadata["embedding_model"] = self.embedding_model
+            new_chunks.append(chunk)
+
+        if new_chunks:
+            self.kb.add_chunks(new_chunks)
+            self._persist(new_chunks)
+            logger.info(f"Added {len(new_chunks)} new chunks to the knowledge base.")
+            # Chunks with a reused embedding are searchable without an API call
+            self.retriever._embeddings_cache = None
+            self.retriever.index_chunks([c for c in new_chunks if c.embedding is not None])
+            if embed_immediately:
+                self.generate_embeddings_for_new_chunks()
+        try:
+            if new_chunks or stale:
+                self.save_knowledge_base(str(saved))
+            delta.save()
+        except OSError as e:
+            logger.warning(f"Could not save the ingestion state: {e}")
+        else:
+            self._release_embeddings()
+
+    def watch_directory(self, dir_path: str) -> None:
+        """
+        Watch a loaded directory so edits made during a long-running session reach the index.
+        
+        Change notifications are collected in the background by the Rust
+        watcher; call refresh() to apply them, e.g. before each query.
+        """
+        if not HAS_RUST or self.chunk_strategy == "paragraph":
+            logger.warning("Watching a directory needs the Rust accelerator and a native chunk strategy")
+            return
+        knowledge_path = Path(dir_path)
+        self._watchers[knowledge_path] = rust_lib.DirectoryWatcher(
+            str(knowledge_path),
+            globs=[f"*{suffix}" for suffix in DOCUMENT_SUFFIXES],
+            ignore=[f"{name}/" for name in SKIPPED_DIRECTORIES],
+        )
+        logger.info(f"Watching {knowledge_path} for changes")
+
+    def refresh(self) -> List[str]:
+        """
+        Re-sync watched directories in which files changed since the last refresh.
+        
+        Only the changed chunks are re-embedded. Returns the changed paths,
+        relative to their directory ("." when the whole directory was rescanned).
+        """
+        changed = []
+        for knowledge_path, watcher in self._watchers.items():
+            paths = watcher.pending()
+            if paths:
+                logger.info(f"{len(paths)} paths changed in {knowledge_path}, re-syncing")
+                self._sync_directory(knowledge_path, embed_immediately=True)
+                changed.extend(paths)
+        return changed
+
+    def stop_watching(self) -> None:
+        """Stop all directory watchers."""
+        for watcher in self._watchers.values():
+            watcher.close()
+        self._watchers.clear()
+
+    def save_knowledge_base(self, path: str) -> None:
+        """
+        Write the embedded chunks, their embeddings and the native index to a knowledge base file.
+        
+        The file is in the Rust crate's versioned, memory-mappable format.
+        An HNSW or IVF-PQ index is stored as built, so loading the file does
+        not rebuild it. Chunks without an embedding are left out; embeddings
+        a compressed index released are read back from where they were saved.
+        Creation times are kept, so created_at filters match after reloading.
+        """
+        if not HAS_RUST:
+            raise RuntimeError("Saving a knowledge base file needs the Rust accelerator")
+        chunks = [chunk for chunk in self.kb.chunks if self.retriever.has_embedding(chunk)]
+        released = [chunk.chunk_id for chunk in chunks if chunk.embedding is None]
+        vectors = dict(zip(released, self._saved_embeddings(released))) if released else {}
+        Path(path).parent.mkdir(parents=True, exist_ok=True)
+        rust_lib.save_kb(
+            path,
+            [chunk.chunk_id for chunk in chunks],
+            [chunk.source for chunk in chunks],
+            [chunk.content for chunk in chunks],
+            [json.dumps(chunk.metadata, default=str) for chunk in chunks],
+            embeddings=np.array(
+                [vectors.get(chunk.chunk_id, chunk.embedding) for chunk in chunks], dtype=np.float32
+            ) if chunks else None,
+            index=self.retriever.stored_index() if chunks else None,
+            info=json.dumps({
+                "name": self.kb.name,
+                "description": self.kb.description,
+                "embedding_model": self.embedding_model,
+            }),
+            created_at=[chunk.created_at.timestamp() for chunk in chunks],
+        )
+        self._remember_saved([chunk.chunk_id for chunk in chunks], rust_lib.load_kb(path).embeddings())
+        logger.info(f"Saved {len(chunks)} chunks to {path}")
+
+    def load_knowledge_base(self, path: str) -> List[KnowledgeChunk]:
+        """
+        Add the chunks saved by save_knowledge_base to the knowledge base.
+        
+        Chunks already present are skipped. The stored index is adopted when
+        the retriever uses the same index type and has none yet. A file
+        embedded with a different model is not loaded, since its vectors are
+        not comparable with new queries. Returns the chunks added.
+        
+        Each added chunk's embedding is a read-only row of the memory-mapped
+        file rather than a copy, so loading a large file stays cheap.
+        """
+        if not HAS_RUST:
+            raise RuntimeError("Loading a knowledge base file needs the Rust accelerator")
+        kb_file = rust_lib.load_kb(path)
+        info = json.loads(kb_file.info or "{}")
+        if info.get("embedding_model", self.embedding_model) != self.embedding_model:
+            logger.warning(
+                f"{path} was embedded with {info['embedding_model']}, not {self.embedding_model}; not loading it"
+            )
+            return []
+
+        present = {chunk.chunk_id for chunk in self.kb.chunks}
+        vectors = kb_file.embeddings()
+        saved = kb_file.chunks()
+        created_at = kb_file.created_at()
+        chunks = []
+        for row, (chunk_id, source, text, metadata) in enumerate(saved):
+            if chunk_id in present:
+                continue
+            chunks.append(KnowledgeChunk(
+                content=text,
+                source=source,
+                chunk_id=chunk_id,
+                embedding=vectors[row] if vectors is not None else None,
+                metadata=json.loads(metadata),
+                # Files saved before creation times were stored fall back to now
+                created_at=datetime.fromtimestamp(created_at[row]) if created_at is not None else datetime.now(),
+            ))
+        was_empty = not self.kb.chunks
+        self.kb.add_chunks(chunks)
+        self._persist(chunks)
+        self._remember_saved([chunk_id for chunk_id, _, _, _ in saved], vectors)
+
+        # The stored index only covers the whole knowledge base if it was empty
+        index = kb_file.index()
+        index_class = {"hnsw": rust_lib.HnswIndex, "ivfpq": rust_lib.IvfPqIndex}.get(self.retriever.index_type, ())
+        if was_empty and isinstance(index, index_class) and self.retriever.stored_index() is None:
+            self.retriever.adopt_index(index)
+        else:
+            self.retriever._embeddings_cache = None
+            self.retriever.index_chunks(chunks)
+        self._release_embeddings(chunks)
+        logger.info(f"Loaded {len(chunks)} chunks from {path}")
+        return chunks
+
+    def _remember_saved(self, chunk_ids: List[str], vectors: Optional[np.ndarray]) -> None:
+        """Record the rows of a knowledge base file's embedding matrix that chunk_ids were saved in."""
+        if vectors is None:
+            return
+        for row, chunk_id in enumerate(chunk_ids):
+            self._saved_vectors[chunk_id] = (vectors, row)
+
+    def _saved_embeddings(self, chunk_ids: List[str]) -> np.ndarray:
+        """
+        Read the float embeddings of chunk_ids back from a saved knowledge base file or the store.
+        
+        Raises KeyError for a chunk whose embedding was saved in neither.
+        """
+        missing = [chunk_id for chunk_id in chunk_ids if chunk_id not in self._saved_vectors]
+        stored = {}
+        if missing:
+            if self.store is None:
+                raise KeyError(missing[0])
+            stored = dict(zip(missing, self.store.embeddings(self.embedding_model, missing)[1]))
+        rows = []
+        for chunk_id in chunk_ids:
+            if chunk_id in stored:
+                rows.append(stored[chunk_id])
+            else:
+                vectors, row = self._saved_vectors[chunk_id]
+                rows.append(vectors[row])
+        return np.array(rows, dtype=np.float32).reshape(len(chunk_ids), -1)
+
+    def _release_embeddings(self, chunks: Optional[List[KnowledgeChunk]] = None) -> None:
+        """
+        Let a compressed index hold the only in-memory copy of saved embeddings.
+        
+        Builds the "ivfpq", "int8" or "binary" index if no search has yet,
+        then drops the float embeddings of the chunks (by default all) that
+        were saved to a knowledge base file or the store, so re-ranking can
+        read them back from there.
+        """
+        if not HAS_RUST or self.retriever.index_type not in COMPRESSED_INDEX_TYPES:
+            return
+        chunks = self.kb.chunks if chunks is None else chunks
+        saved = [
+            chunk for chunk in chunks
+            if chunk.embedding is not None and (self.store is not None or chunk.chunk_id in self._saved_vectors)
+        ]
+        if not saved:
+            return
+        try:
+            self.retriever._ensure_rust_index()
+        except (ValueError, EmbeddingMismatchError) as e:
+            logger.warning(f"Keeping float embeddings in memory: {e}")
+            return
+        released = self.retriever.release_embeddings(saved)
+        logger.debug(f"Released {released} float embeddings held by the {self.retriever.index_type} index")
+
+    def _remove_chunks(self, chunk_ids: set) -> None:
+        """Remove chunks from the knowledge base and the retriever's indexes."""
+        if not chunk_ids:
+            return
+        self.kb.chunks = [chunk for chunk in self.kb.chunks if chunk.chunk_id not in chunk_ids]
+        self.retriever.remove_chunks(list(chunk_ids))
+        for chunk_id in chunk_ids:
+            self._saved_vectors.pop(chunk_id, None)
+        if self.store is not None:
+            try:
+                self.store.remove_chunks(list(chunk_ids))
+            except OSError as e:
+                logger.error(f"Could not remove chunks from the knowledge store: {e}")
+
+    def _load_store(self) -> None:
+        """
+        Add the store's chunks to the knowledge base, with their embeddings from the current model.
+        
+        The native index is filled straight from the store when the
+        knowledge base was empty, so the embeddings are not copied through
+        Python to build it.
+        """
+        vectors = {}
+        if any(name == self.embedding_model for name, _, _ in self.store.models()):
+            ids, matrix = self.store.embeddings(self.embedding_model)
+            vectors = dict(zip(ids, matrix))
+        present = {chunk.chunk_id for chunk in self.kb.chunks}
+        chunks = [
+            KnowledgeChunk(
+                content=text,
+                source=source,
+                chunk_id=chunk_id,
+                embedding=vectors[chunk_id].tolist() if chunk_id in vectors else None,
+                metadata=json.loads(metadata),
+                created_at=datetime.fromtimestamp(created_at),
+            )
+            for chunk_id, source, text, metadata, created_at in self.store.chunks()
+            if chunk_id not in present
+        ]
+        # The store may hold several models' embeddings; only the current one's were loaded
+        for chunk in chunks:
+            if chunk.embedding is not None:
+                chunk.metadata["embedding_model"] = self.embedding_model
+            else:
+                chunk.metadata.pop("embedding_model", None)
+        was_empty = not self.kb.chunks
+        self.kb.add_chunks(chunks)
+        if was_empty and vectors:
+            self.retriever.load_index(self.store, self.embedding_model)
+            self._release_embeddings(chunks)
+        logger.info(f"Loaded {len(chunks)} chunks ({len(vectors)} embedded) from {self.store}")
+
+    def _persist(self, chunks: List[KnowledgeChunk]) -> None:
+        """Write chunks, and the embeddings they have, through to the store."""
+        if self.store is None or not chunks:
+            return
+        try:
+            self.store.add_chunks(
+                [chunk.chunk_id for chunk in chunks],
+                [chunk.source for chunk in chunks],
+                [chunk.content for chunk in chunks],
+                [json.dumps(chunk.metadata, default=str) for chunk in chunks],
+                created_at=[chunk.created_at.timestamp() for chunk in chunks],
+            )
+            embedded = [chunk for chunk in chunks if chunk.embedding is not None]
+            if embedded:
+                self.store.add_embeddings(
+                    self.embedding_model,
+                    [chunk.chunk_id for chunk in embedded],
+                    np.array([chunk.embedding for chunk in embedded], dtype=np.float32),
+                )
+        except OSError as e:
+            logger.error(f"Could not write to the knowledge store: {e}")
+
+    def _chunk_from_ingested(self, item: Any) -> Optional[KnowledgeChunk]:
+        """Wrap a chunk from the Rust ingestion in a KnowledgeChunk, skipping tiny ones."""
+        if len(item.text) < DEFAULT_CHUNK_MIN_LENGTH:
+            return None
+
+        metadata = {
+            "chunk_index": item.index,
+            "filename": item.source.rsplit("/", 1)[-1],
+            "start_byte": item.start_byte,
+            "end_byte": item.end_byte,
+            "chunk_strategy": item.strategy,
+            "content_hash": item.content_hash,
+            "encoding": item.encoding,
+        }
+        if item.page is not None:
+            metadata["page"] = item.page
+        if item.heading_path:
+            metadata["heading_path"] = " > ".join(item.heading_path)
+        if item.language:
+            metadata.update(language=item.language, start_line=item.start_line, end_line=item.end_line)
+            if item.symbol:
+                metadata["symbol"] = item.symbol
+        return KnowledgeChunk(
+            content=item.text,
+            source=item.source,
+            chunk_id=chunk_id_for(item.source, item.start_byte, item.end_byte, item.text),
+            metadata=metadata,
+        )
+
+    def _ingest_directory_natively(self, knowledge_path: Path) -> List[KnowledgeChunk]:
+        """
+        Walk, decode and chunk the directory in parallel in the Rust crate.
+        
+        Honours .gitignore files, detects non-UTF-8 encodings, extracts PDF
+        text page by page, converts HTML, DOCX and EPUB documents to markdown
+        and records a content hash for every chunk.
+        """
+        logger.info(f"Ingesting {knowledge_path} with the Rust accelerator...")
+        ingested, errors = rust_lib.ingest_directory(
+            str(knowledge_path),
+            globs=[f"*{suffix}" for suffix in DOCUMENT_SUFFIXES],
+            ignore=[f"{name}/" for name in SKIPPED_DIRECTORIES],
+            strategy=self.chunk_strategy,
+            size=self.chunk_size,
+            overlap=self.chunk_overlap,
+        )
+        for error in errors:
+            logger.error(f"Error loading file {error}")
+
+        return [chunk for chunk in map(self._chunk_from_ingested, ingested) if chunk is not None]
+
+    def _load_files_serially(self, knowledge_path: Path) -> List[KnowledgeChunk]:
+        """Read and chunk UTF-8 files one by one, without the Rust accelerator."""
+        text_files = sorted(
+            path for path in knowledge_path.rglob("*")
+            if path.suffix.lower() in TEXT_SUFFIXES
+            and path.is_file()
+            and not SKIPPED_DIRECTORIES.intersection(path.relative_to(knowledge_path).parts)
+        )
+        if not text_files:
+            logger.warning(f"No documents or source files found in {knowledge_path}")
+            return []
+
+        logger.info(f"Loading {len(text_files)} files from {knowledge_path}...")
         new_chunks = []
         for file_path in text_files:
             try:
                 content = file_path.read_text(encoding="utf-8")
-                # Simple chunking by paragraphs
-                paragraphs = [p.strip() for p in content.split("\n\n") if p.strip()]
+                source = str(file_path.relative_to(knowledge_path))
 
-                for i, paragraph in enumerate(paragraphs):
-                    if len(paragraph) < DEFAULT_CHUNK_MIN_LENGTH:
+                strategy, pieces = self.split_document(file_path, content)
+                for i, (start, end, text, extra_metadata) in enumerate(pieces):
+                    if len(text) < DEFAULT_CHUNK_MIN_LENGTH:
                         continue
 
+                    metadata = {
+                        "chunk_index": i,
+                        "filename": file_path.name,
+                        "start_byte": start,
+                        "end_byte": end,
+                        "chunk_strategy": strategy,
+                        **extra_metadata,
+                    }
                     chunk = KnowledgeChunk(
-                        content=paragraph,
-                        source=str(file_path.relative_to(knowledge_path)),
-                        metadata={
-                            "chunk_index": i,
-                            "filename": file_path.name,
-                        }
+                        content=text,
+                        source=source,
+                        chunk_id=chunk_id_for(source, start, end, text),
+                        metadata=metadata,
                         # embedding is added later
                     )
-                    # Add chunk to KB directly or collect first?
-                    # self.kb.add_chunk(chunk) # Add directly
                     new_chunks.append(chunk)
 
             except Exception as e:
                 logger.error(f"Error loading or chunking file {file_path}: {e}")
-
-        if new_chunks:
-            self.kb.add_chunks(new_chunks)
-            logger.info(f"Added {len(new_chunks)} new chunks to the knowledge base.")
-            if embed_immediately:
-                self.generate_embeddings_for_new_chunks()
-        else:
-             logger.info("No new chunks were added from the directory.")
-
+        return new_chunks
 
     def generate_embeddings_for_new_chunks(self, batch_size: int = 20):
         """Generates embeddings for chunks in the KB that don't have them."""
-        chunks_to_embed = [chunk for chunk in self.kb.chunks if chunk.embedding is None]
+        chunks_to_embed = [chunk for chunk in self.kb.chunks if not self.retriever.has_embedding(chunk)]
         if not chunks_to_embed:
             logger.info("No new chunks require embedding.")
             return
 
         logger.info(f"Generating embeddings for {len(chunks_to_embed)} chunks...")
-        total_embedded = 0
         try:
-            for i in range(0, len(chunks_to_embed), batch_size):
-                batch = chunks_to_embed[i:i+batch_size]
-                batch_texts = [chunk.content for chunk in batch]
+            self._embed(chunks_to_embed, self.embedding_model, batch_size)
+            logger.info(f"Successfully generated embeddings for {len(chunks_to_embed)} chunks.")
 
-                response = self.client.embeddings.create(
-                    model=self.embedding_model,
-                    input=batch_texts
-                )
+        except Exception as e:
+            logger.error(f"Error generating embeddings: {e}")
+            # Optionally, decide how to handle partial failure
+        finally:
+            # Batches embedded before a failure are kept, searchable and persisted.
+            # Important: Clear the retriever's dense caches as embeddings have changed;
+            # the native index is extended in place instead of being rebuilt
+            embedded = [chunk for chunk in chunks_to_embed if chunk.embedding is not None]
+            self.retriever._embeddings_cache = None
+            self._persist(embedded)
+            self.retriever.index_chunks(embedded)
+            if self.store is not None:
+                self._release_embeddings(embedded)
 
-                # Assign embeddings back to the original chunks in the KB
-                for j, embedding_data in enumerate(response.data):
-                    # Find the original chunk object and assign the embedding
-                    # This assumes chunk objects are stable in self.kb.chunks
-                    # (might need optimization if KB is very large or changes often)
-                    batch[j].embedding = embedding_data.embedding
-                    total_embedded += 1
+    def _load_local_model(self, path: str) -> str:
+        """Load the sentence-transformer model in directory path and return its name."""
+        if not HAS_RUST:
+            raise RuntimeError("Local embedding needs the Rust accelerator")
+        embedder = rust_lib.TextEmbedder(path)
+        self.local_models[embedder.name] = embedder
+        logger.info(f"Loaded local embedding model {embedder.name} ({embedder.dim} dimensions)")
+        return embedder.name
 
-                logger.debug(f"Embedded batch {i//batch_size + 1}/{len(chunks_to_embed)//batch_size + 1}")
-                if i + batch_size < len(chunks_to_embed):
-                    time.sleep(0.5) # Avoid rate limits
+    def _embed_texts(self, texts: List[str], model: str) -> List[List[float]]:
+        """Embed texts with model, locally if it is a loaded local model."""
+        if model in self.local_models:
+            return self.local_models[model].embed(texts).tolist()
+        response = self.client.embeddings.create(
+            model=model,
+            input=texts
+        )
+        return [embedding_data.embedding for embedding_data in response.data]
 
-            logger.info(f"Successfully generated embeddings for {total_embedded} chunks.")
-            # Important: Clear the retriever's cache as embeddings have changed
-            self.retriever._embeddings_cache = None
+    def _embed(self, chunks: List[KnowledgeChunk], model: str, batch_size: int) -> None:
+        """Embed chunks in batches with model, recording the model in each chunk's metadata."""
+        for i in range(0, len(chunks), batch_size):
+            batch = chunks[i:i+batch_size]
+            batch_texts = [chunk.content for chunk in batch]
 
-        except Exception as e:
-            logger.error(f"Error generating embeddings: {e}")
-            # Optionally, decide how to handle partial failure
+            # Assign embeddings back to the original chunks in the KB
+            for chunk, embedding in zip(batch, self._embed_texts(batch_texts, model)):
+                chunk.embedding = embedding
+                chunk.metadata["embedding_model"] = model
 
-    def search(self, query: str, top_k: int = 3, score_threshold: float = 0.6) -> List[Dict[str, Any]]:
-        """Search the knowledge base for relevant chunks."""
+            logger.debug(f"Embedded batch {i//batch_size + 1}/{len(chunks)//batch_size + 1}")
+            if i + batch_size < len(chunks) and model not in self.local_models:
+                time.sleep(0.5) # Avoid rate limits
+
+    def migrate_embedding_model(
+        self,
+        embedding_model: str,
+        batch_size: int = 20,
+        local_model_path: Optional[str] = None,
+    ) -> None:
+        """
+        Re-embed the knowledge base with another model and switch searches over to it.
+        
+        The new embeddings and their native index are built side by side
+        with the current ones, which keep serving searches until the switch.
+        A store keeps both models' embeddings. If embedding fails the
+        knowledge base stays on the current model. With local_model_path the
+        new model is that local model and embedding_model is ignored.
+        """
+        if local_model_path:
+            embedding_model = self._load_local_model(local_model_path)
+        if embedding_model == self.embedding_model:
+            return
+        chunks = [
+            replace(chunk, embedding=None, metadata=dict(chunk.metadata))
+            for chunk in self.kb.chunks
+        ]
+        logger.info(f"Re-embedding {len(chunks)} chunks with {embedding_model}...")
+        self._embed(chunks, embedding_model, batch_size)
+
+        shadow = KnowledgeBase(name=self.kb.name, description=self.kb.description, chunks=chunks)
+        retriever = SemanticRetriever(
+            shadow,
+            index_type=self.retriever.index_type,
+            index_options=self.retriever.index_options,
+            rerank_factor=self.retriever.rerank_factor,
+            metric=self.retriever.metric,
+            embedding_model=embedding_model,
+            vector_source=self._saved_embeddings,
+        )
+        if HAS_RUST and chunks:
+            retriever._ensure_rust_index()
+
+        # Switch over in one step, so searches never mix the two models
+        self.kb.chunks = chunks
+        retriever.knowledge_base = self.kb
+        self.retriever = retriever
+        self.embedding_model = embedding_model
+        # Saved files hold the previous model's embeddings
+        self._saved_vectors = {}
+        self._persist(chunks)
+        if self.store is not None:
+            self._release_embeddings()
+        logger.info(f"Knowledge base now searches with {embedding_model}")
+
+    def search(
+        self,
+        query: str,
+        top_k: int = 3,
+        score_threshold: float = 0.6,
+        metadata_filter: Optional[Dict[str, Any]] = None,
+        hybrid: bool = False,
+    ) -> List[Dict[str, Any]]:
+        """
+        Search the knowledge base for relevant chunks.
+        
+        metadata_filter restricts the search to matching chunks before ranking,
+        e.g. {"source_type": "markdown"} or {"filename": {"$prefix": "api_"}}.
+        With hybrid=True, BM25 keyword matches are fused with the semantic
+        results so exact identifiers and error codes are found too.
+
+        With a reranker, the top rerank_candidates results are fetched without
+        score_threshold and reordered by the cross-encoder, so passages that
+        answer the query in other words are not cut off by their similarity.
+        Their "score" is then the cross-encoder's relevance, between 0 and 1,
+        and "retrieval_score" the score they were retrieved with.
+        """
         if not self.kb or not self.kb.chunks:
              logger.warning("Search attempted on empty or non-existent knowledge base.")
              return []
-        if all(c.embedding is None for c in self.kb.chunks):
+        if not any(self.retriever.has_embedding(c) for c in self.kb.chunks):
             logger.warning("Search attempted, but no chunks have embeddings.")
             return []
 
         try:
-            query_embedding = self.client.embeddings.create(
-                model=self.embedding_model,
-                input=query,
-            ).data[0].embedding
-
-            results, backend_used, execution_time_ms = self.retriever.semantic_search(
-                query_embedding=query_embedding,
-                top_k=top_k,
-                score_threshold=score_threshold,
-                # backend preference can be added here if needed
-            )
+            query_embedding = self._embed_texts([query], self.embedding_model)[0]
+            candidates = top_k
+            if self.reranker is not None:
+                candidates, score_threshold = max(top_k, self.rerank_candidates), None
+
+            if hybrid:
+                results, backend_used, execution_time_ms = self.retriever.hybrid_search(
+                    query_text=query,
+                    query_embedding=query_embedding,
+                    top_k=candidates,
+                    score_threshold=score_threshold,
+                    metadata_filter=metadata_filter,
+                )
+            else:
+                results, backend_used, execution_time_ms = self.retriever.semantic_search(
+                    query_embedding=query_embedding,
+                    top_k=candidates,
+                    score_threshold=score_threshold,
+                    metadata_filter=metadata_filter,
+                    # backend preference can be added here if needed
+                )
+            if self.reranker is not None:
+                results = self._rerank(query, results, top_k)
             logger.info(f"Search for '{query[:50]}...' found {len(results)} results in {execution_time_ms:.2f}ms using {backend_used}")
             return results
 
+        except EmbeddingMismatchError:
+            # The query and knowledge base embeddings are not comparable
+            raise
         except Exception as e:
             logger.error(f"Error during search: {e}")
             return []
 
+    def _rerank(self, query: str, results: List[Dict[str, Any]], top_k: int) -> List[Dict[str, Any]]:
+        """Reorder results by the cross-encoder's relevance to query and keep the top_k."""
+        ranked = self.reranker.rerank(query, [result["content"] for result in results], top_k)
+        return [
+            {**results[i], "score": float(score), "retrieval_score": results[i]["score"]}
+            for i, score in ranked
+        ]
+
     @property
     def knowledge_base_size(self) -> int:
         """Return the number of chunks in the knowledge base."""
         return len(self.kb) if self.kb else 0
 
 # TODO:
-# - Add method to save/load knowledge base with embeddings (e.g., to JSON or SQLite)
-# - Implement more sophisticated chunking strategies
-# - Add support for other document types (PDF, etc.)
-# - Integrate SQLite for persistent storage of chunks and embeddings
 # - Refine error handling 
\ No newline at end of file
@@ -26,6 +26,7 @@ from .agents.agents_assistant import LlamaAssistant
 from .models.models_knowledge import KnowledgeBase, KnowledgeChunk
 from .models.models_responses import ProfessionalResponse
 from .integrations.knowledge_manager import KnowledgeManager
+from .utils.context_utils import DEFAULT_CONTEXT_BUDGET_TOKENS
 from .llama_animations.thinking import LlamaThinking
 from .llama_animations.typing_effect import LlamaResponseTypingEffect
 
@@ -86,7 +87,7 @@ def format_response(response: ProfessionalResponse, detailed: bool = False) -> s
     if response.sources and detailed:
         markdown += "\n## Sources\n\n"
         for i, source in enumerate(response.sources, 1):
-            markdown += f"{i}. **{source.source}** (relevance: {source.relevance:.0%})\n"
+            markdown += f"{i}. **{source.citation}** (relevance: {source.relevance:.0%})\n"
             if source.excerpt:
                 markdown += f"   > {source.excerpt}\n"
             markdown += "\n"
@@ -144,6 +145,41 @@ def ask_query(
         "--db-path",
         help="Path to the SQLite database for logging (defaults to local_agent_logs.db)"
     ),
+    store: Optional[str] = typer.Option(
+        None,
+        "--store",
+        help="SQLite file keeping this knowledge base's chunks and embeddings across runs"
+    ),
+    local_model: Optional[str] = typer.Option(
+        None,
+        "--local-model",
+        help="Sentence-transformer model directory to embed with on the CPU instead of the OpenAI API"
+    ),
+    reranker: Optional[str] = typer.Option(
+        None,
+        "--reranker",
+        help="Cross-encoder model directory to rerank search results with on the CPU"
+    ),
+    tokenizer: Optional[str] = typer.Option(
+        None,
+        "--tokenizer",
+        help="The assistant model's tiktoken or tokenizer.json file, to fit sources to the context budget"
+    ),
+    context_budget: int = typer.Option(
+        DEFAULT_CONTEXT_BUDGET_TOKENS,
+        "--context-budget",
+        help="Most tokens of knowledge base sources to put in each prompt"
+    ),
+    hybrid: bool = typer.Option(
+        False,
+        "--hybrid",
+        help="Also match exact keywords such as identifiers and error codes with BM25"
+    ),
+    state_dir: Optional[str] = typer.Option(
+        None,
+        "--state-dir",
+        help="Directory keeping what was already embedded, so unchanged files are not re-embedded (defaults to the user cache directory)"
+    ),
 ):
     """Ask a question and get an answer from the knowledge base."""
     # Get API key
@@ -172,7 +208,13 @@ def ask_query(
     
     try:
         # Initialize Knowledge Manager
-        knowledge_manager = KnowledgeManager(openai_client=client)
+        knowledge_manager = KnowledgeManager(
+            openai_client=client,
+            store_path=store,
+            local_model_path=local_model,
+            reranker_path=reranker,
+            state_dir=state_dir,
+        )
         
         # Load knowledge base using Knowledge Manager
         with console.status(f"Loading knowledge from [bold blue]{knowledge_dir}[/]..."):
@@ -190,11 +232,16 @@ def ask_query(
         assistant = LlamaAssistant(
             knowledge_manager=knowledge_manager,
             openai_client=client,
-            assistant_model="gpt-4-turbo-preview" # Or make this configurable
+            assistant_model="gpt-4-turbo-preview", # Or make this configurable
+            context_budget_tokens=context_budget,
+            tokenizer_path=tokenizer,
+            hybrid=hybrid,
         )
         
         # Interactive mode
         if interactive:
+            # Pick up documents edited while the session is running
+            knowledge_manager.watch_directory(knowledge_dir)
             console.print(Panel(
                 Markdown("# 🦙 LlamaSearch Professional\n\nAsk questions about your knowledge base!"),
                 border_style="blue"
@@ -209,6 +256,10 @@ def ask_query(
                 if query.lower() in ("exit", "quit", "q"):
                     break
                 
+                changed = knowledge_manager.refresh()
+                if changed:
+                    console.print(f"[dim]Re-indexed {len(changed)} changed paths from {knowledge_dir}[/]")
+                
                 # Process the query
                 if visual == "animated":
                     thinking_animation.start()
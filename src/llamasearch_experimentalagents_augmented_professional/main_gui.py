@@ -51,6 +51,8 @@ def get_agent_instance():
             knowledge_manager = KnowledgeManager(openai_client=client)
             # Load documents - consider doing this async or on demand
             knowledge_manager.load_documents_from_directory(knowledge_dir, embed_immediately=True)
+            # The GUI session is long-running, so documents edited meanwhile must reach the index
+            knowledge_manager.watch_directory(knowledge_dir)
 
             AGENT_INSTANCE = LlamaAssistant(
                 knowledge_manager=knowledge_manager,
@@ -87,6 +89,11 @@ def handle_frontend_request(query: str) -> dict:
         return {"status": "error", "message": "Agent initialization failed. Check backend logs."}
 
     try:
+        # Re-sync documents that changed since the last query
+        changed = agent.knowledge_manager.refresh()
+        if changed:
+            logger.info(f"Re-indexed {len(changed)} changed paths before answering")
+
         # Call the agent's generate_response method
         # Note: Callbacks like on_search_start won't work directly here
         # Need a different mechanism (e.g., websocket) for streaming updates to frontend
@@ -36,8 +36,12 @@ class KnowledgeChunk:
                     self.metadata["source_type"] = "markdown"
                 elif ext in ["txt", "text"]:
                     self.metadata["source_type"] = "plaintext"
-                elif ext in ["py", "python"]:
+                elif ext in ["py", "python", "rs", "js", "mjs", "jsx", "go"]:
                     self.metadata["source_type"] = "code"
+                elif ext in ["pdf", "docx", "epub"]:
+                    self.metadata["source_type"] = ext
+                elif ext in ["html", "htm", "xhtml"]:
+                    self.metadata["source_type"] = "html"
                 else:
                     self.metadata["source_type"] = "unknown"
             else:
@@ -15,6 +15,12 @@ class SourceReference(BaseModel):
     source: str = Field(..., description="Source document name or path")
     relevance: float = Field(..., description="Relevance score from semantic search", ge=0, le=1)
     excerpt: Optional[str] = Field(None, description="Short excerpt from the source")
+    page: Optional[int] = Field(None, description="Page number within the source, for paginated documents such as PDFs", ge=1)
+    
+    @property
+    def citation(self) -> str:
+        """The source with its page, e.g. "paper.pdf p.12"."""
+        return f"{self.source} p.{self.page}" if self.page is not None else self.source
     
     class Config:
         frozen = True
@@ -64,7 +70,7 @@ class ProfessionalResponse(BaseModel):
         
         result = "Sources:\n"
         for i, source in enumerate(self.sources, 1):
-            result += f"{i}. {source.source} (relevance: {source.relevance:.2f})\n"
+            result += f"{i}. {source.citation} (relevance: {source.relevance:.2f})\n"
             if source.excerpt:
                 result += f"   Excerpt: \"{source.excerpt}\"\n"
         return result
@@ -99,7 +105,7 @@ class SearchResults(BaseModel):
         description="Search results with scores"
     )
     execution_time_ms: float = Field(..., description="Search execution time in milliseconds")
-    backend_used: str = Field(..., description="Backend used for search (MLX, JAX, etc.)")
+    backend_used: str = Field(..., description="Backend used for search (MLX, JAX, NumPy, or rust-<kernel> such as rust-avx2)")
     
     class Config:
         frozen = True
@@ -0,0 +1,85 @@
+"""
+Utilities for fitting knowledge base search results into the model's context window.
+"""
+
+import logging
+from typing import Any, Dict, List, Optional
+
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
+logger = logging.getLogger(__name__)
+
+DEFAULT_CONTEXT_BUDGET_TOKENS = 6000
+# A source that would be cut shorter than this is dropped instead
+MIN_SOURCE_TOKENS = 50
+# Characters per token of typical English text
+CHARS_PER_TOKEN = 4
+
+
+class ApproximateTokenizer:
+    """Estimates tokens from characters, for when no tokenizer file is loaded."""
+
+    name = "approximate"
+
+    def count(self, text: str) -> int:
+        return -(-len(text) // CHARS_PER_TOKEN)
+
+    def truncate(self, text: str, max_tokens: int) -> str:
+        return text[:max_tokens * CHARS_PER_TOKEN]
+
+
+def load_tokenizer(path: Optional[str]) -> Any:
+    """Load the tiktoken or Hugging Face tokenizer at path, or estimate tokens without one."""
+    if not path:
+        return ApproximateTokenizer()
+    if not HAS_RUST:
+        raise RuntimeError("Loading a tokenizer file needs the Rust accelerator")
+    tokenizer = rust_lib.BpeTokenizer(path)
+    logger.info(f"Loaded tokenizer {tokenizer.name} for context budgeting")
+    return tokenizer
+
+
+def format_source(number: int, result: Dict[str, Any]) -> str:
+    """Format a search result as a numbered, cited source for the prompt."""
+    page = result.get("metadata", {}).get("page")
+    citation = f"{result['source']} p.{page}" if page is not None else result['source']
+    return f"[Source {number}: {citation}]\n{result['content']}\n\n"
+
+
+def pack_context(
+    results: List[Dict[str, Any]],
+    budget_tokens: int,
+    tokenizer: Optional[Any] = None,
+    min_source_tokens: int = MIN_SOURCE_TOKENS,
+) -> List[Dict[str, Any]]:
+    """
+    Fit search results, best first, into budget_tokens of formatted sources.
+
+    Results that fit whole are kept whole. The first one that does not is cut
+    to the budget left if at least min_source_tokens of its content fit, and
+    dropped otherwise, so that shorter results after it may still fit. A cut
+    result is returned as a copy with the shortened content and "truncated"
+    set. Tokens are counted with tokenizer, estimated from characters if None.
+    """
+    tokenizer = tokenizer or ApproximateTokenizer()
+    packed = []
+    remaining = budget_tokens
+    for result in results:
+        number = len(packed) + 1
+        cost = tokenizer.count(format_source(number, result))
+        if cost <= remaining:
+            packed.append(result)
+            remaining -= cost
+            continue
+        room = remaining - tokenizer.count(format_source(number, {**result, "content": ""}))
+        if room >= min_source_tokens:
+            content = tokenizer.truncate(result["content"], room)
+            packed.append({**result, "content": content, "truncated": True})
+            break
+    if len(packed) < len(results) or any(r.get("truncated") for r in packed):
+        logger.info(f"Packed {len(packed)} of {len(results)} sources into {budget_tokens} tokens")
+    return packed
@@ -23,8 +23,14 @@ try:
 except ImportError:
     HAS_JAX = False
 
-from llamasearch_experimentalagents_augmented_professional.models.knowledge import KnowledgeBase, KnowledgeChunk
-from llamasearch_experimentalagents_augmented_professional.agents.retriever import SemanticRetriever
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
+from llamasearch_experimentalagents_augmented_professional.models.models_knowledge import KnowledgeBase, KnowledgeChunk
+from llamasearch_experimentalagents_augmented_professional.agents.agents_retriever import SemanticRetriever
 
 
 def create_test_kb(
@@ -74,7 +80,9 @@ def benchmark_search(
     query_embedding: np.ndarray,
     backend: Optional[str] = None,
     warmup: int = 2,
-    repeat: int = 5
+    repeat: int = 5,
+    top_k: int = 3,
+    score_threshold: Optional[float] = 0.6
 ) -> float:
     """Benchmark search performance for a given backend."""
     retriever = SemanticRetriever(kb)
@@ -83,6 +91,8 @@ def benchmark_search(
     for _ in range(warmup):
         retriever.semantic_search(
             query_embedding=query_embedding.tolist(),
+            top_k=top_k,
+            score_threshold=score_threshold,
             backend=backend
         )
     
@@ -92,6 +102,8 @@ def benchmark_search(
         start_time = time.time()
         retriever.semantic_search(
             query_embedding=query_embedding.tolist(),
+            top_k=top_k,
+            score_threshold=score_threshold,
             backend=backend
         )
         times.append((time.time() - start_time) * 1000)  # ms
@@ -129,6 +141,21 @@ def test_mlx_search_1k(benchmark, kb_1k):
     assert result > 0
 
 
+@pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+def test_rust_search_1k(benchmark, kb_1k):
+    """Benchmark the Rust SIMD search with 1000 chunks."""
+    kb, query_embedding = kb_1k
+    
+    result = benchmark(
+        benchmark_search,
+        kb,
+        query_embedding,
+        backend="rust"
+    )
+    
+    assert result > 0
+
+
 @pytest.mark.skipif(not HAS_JAX, reason="JAX not installed")
 def test_jax_search_1k(benchmark, kb_1k):
     """Benchmark JAX search with 1000 chunks."""
@@ -174,6 +201,48 @@ def test_mlx_search_10k(benchmark, kb_10k):
     assert result > 0
 
 
+@pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+def test_rust_search_10k(benchmark, kb_10k):
+    """Benchmark the Rust SIMD search with 10000 chunks."""
+    kb, query_embedding = kb_10k
+    
+    result = benchmark(
+        benchmark_search,
+        kb,
+        query_embedding,
+        backend="rust"
+    )
+    
+    assert result > 0
+
+
+@pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+@pytest.mark.parametrize("top_k", [1, 3, 10])
+def test_rust_beats_numpy_for_small_k(kb_10k, top_k):
+    """The Rust SIMD search must be faster than NumPy with 10000 chunks for k <= 10."""
+    kb, query_embedding = kb_10k
+    
+    # No threshold, so both backends rank and return top_k chunks
+    numpy_time = benchmark_search(
+        kb,
+        query_embedding,
+        backend="numpy",
+        top_k=top_k,
+        score_threshold=None
+    )
+    rust_time = benchmark_search(
+        kb,
+        query_embedding,
+        backend="rust",
+        top_k=top_k,
+        score_threshold=None
+    )
+    
+    assert rust_time < numpy_time, (
+        f"rust-{rust_lib.simd_kernel()} took {rust_time:.2f} ms, NumPy {numpy_time:.2f} ms"
+    )
+
+
 @pytest.mark.skipif(not HAS_JAX, reason="JAX not installed")
 def test_jax_search_10k(benchmark, kb_10k):
     """Benchmark JAX search with 10000 chunks."""
@@ -225,6 +294,17 @@ def test_backend_comparison():
         )
         results["mlx"] = mlx_time
     
+    # Rust, labelled with the SIMD kernel picked for this CPU
+    if HAS_RUST:
+        rust_time = benchmark_search(
+            kb,
+            query_embedding,
+            backend="rust",
+            warmup=2,
+            repeat=3
+        )
+        results[f"rust-{rust_lib.simd_kernel()}"] = rust_time
+    
     # Print results
     print("\nBackend Performance Comparison (lower is better):")
     for backend, time_ms in results.items():
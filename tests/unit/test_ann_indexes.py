@@ -0,0 +1,204 @@
+"""
+Tests for the approximate nearest-neighbour indexes of the Rust accelerator.
+"""
+
+import json
+
+import numpy as np
+import pytest
+
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
+from llamasearch_experimentalagents_augmented_professional.agents.agents_retriever import SemanticRetriever
+from llamasearch_experimentalagents_augmented_professional.models.models_knowledge import KnowledgeBase, KnowledgeChunk
+
+pytestmark = pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+
+
+def sample(count=2000, dim=32, seed=0):
+    rng = np.random.default_rng(seed)
+    embeddings = rng.standard_normal((count, dim)).astype(np.float32)
+    return [f"chunk-{i}" for i in range(count)], embeddings
+
+
+def recall(index, exact, queries, k):
+    found = 0
+    for query in queries:
+        expected = {chunk_id for chunk_id, _ in exact.search(query, k)}
+        found += len(expected & {chunk_id for chunk_id, _ in index.search(query, k)})
+    return found / (len(queries) * k)
+
+
+def test_hnsw_recall_against_flat_search():
+    ids, embeddings = sample()
+    index = rust_lib.HnswIndex(32)
+    exact = rust_lib.EmbeddingIndex(32)
+    index.add_many(ids, embeddings)
+    exact.add_many(ids, embeddings)
+    assert recall(index, exact, embeddings[:100] + 0.1, 10) >= 0.9
+
+
+def test_hnsw_returns_k_live_results_after_deletes():
+    ids, embeddings = sample()
+    index = rust_lib.HnswIndex(32, ef_search=10)
+    index.add_many(ids, embeddings)
+    for chunk_id in ids[:900]:
+        index.remove(chunk_id)
+    assert len(index) == 1100 and index.nodes == 2000
+
+    for query in embeddings[:50]:
+        matches = index.search(query, 30)
+        assert len(matches) == 30
+        assert all(int(chunk_id.split("-")[1]) >= 900 for chunk_id, _ in matches)
+    assert all(score >= 0.3 for _, score in index.search(embeddings[1500], 30, 0.3))
+
+    # Deleted nodes outnumbering live ones rebuild the graph without them
+    for chunk_id in ids[900:1001]:
+        index.remove(chunk_id)
+    assert len(index) == index.nodes == 999
+    exact = rust_lib.EmbeddingIndex(32)
+    exact.add_many(ids[1001:], embeddings[1001:])
+    assert recall(index, exact, embeddings[1001:1101] + 0.1, 10) >= 0.9
+
+    index.add(ids[0], embeddings[0])
+    index.add(ids[0], embeddings[1])
+    assert (len(index), index.nodes) == (1000, 1001)
+    index.compact()
+    assert len(index) == index.nodes == 1000
+    assert index.search(embeddings[1], 1)[0][0] == ids[0]
+
+
+def test_hnsw_save_load_round_trip_keeps_deletes(tmp_path):
+    ids, embeddings = sample(count=300)
+    index = rust_lib.HnswIndex(32, m=8)
+    index.add_many(ids, embeddings)
+    for chunk_id in ids[:100]:
+        index.remove(chunk_id)
+
+    path = tmp_path / "kb.llkb"
+    rust_lib.save_kb(
+        str(path),
+        ids[100:],
+        ["a.md"] * 200,
+        ["text"] * 200,
+        [json.dumps({})] * 200,
+        embeddings=embeddings[100:],
+        index=index,
+    )
+    loaded = rust_lib.load_kb(str(path)).index()
+    assert (len(loaded), loaded.nodes) == (200, 300)
+    assert loaded.batch_search(embeddings[:20], 5) == index.batch_search(embeddings[:20], 5)
+    loaded.remove(ids[100])
+    assert ids[100] not in {chunk_id for chunk_id, _ in loaded.search(embeddings[100], 5)}
+
+
+def trained_ivfpq(ids, embeddings, **options):
+    index = rust_lib.IvfPqIndex(embeddings.shape[1], **options)
+    index.train(embeddings)
+    index.add_many(ids, embeddings)
+    return index
+
+
+def test_ivfpq_recall_against_flat_search():
+    ids, embeddings = sample()
+    index = trained_ivfpq(ids, embeddings, nlist=16, nprobe=16)
+    exact = rust_lib.EmbeddingIndex(32)
+    exact.add_many(ids, embeddings)
+    queries = embeddings[:100] + 0.1
+    # Codes of 8 bytes for 128 bytes of float32 keep most neighbours
+    assert recall(index, exact, queries, 10) >= 0.5
+    for chunk_id, query in zip(ids, queries):
+        assert chunk_id in {found for found, _ in index.search(query, 3)}
+
+
+def test_ivfpq_remove_and_replace():
+    ids, embeddings = sample(count=500)
+    index = trained_ivfpq(ids, embeddings, nlist=8, nprobe=8)
+    for chunk_id in ids[:100]:
+        index.remove(chunk_id)
+    assert len(index) == 400 and ids[0] not in index
+    for query in embeddings[:20]:
+        assert all(int(chunk_id.split("-")[1]) >= 100 for chunk_id, _ in index.search(query, 10))
+    with pytest.raises(KeyError):
+        index.remove(ids[0])
+
+    index.add(ids[200], embeddings[0])
+    assert len(index) == 400
+    assert index.search(embeddings[0], 1)[0][0] == ids[200]
+
+
+def test_ivfpq_save_load_round_trip(tmp_path):
+    ids, embeddings = sample(count=300)
+    index = trained_ivfpq(ids, embeddings, nlist=4, m=4, nbits=6)
+    path = tmp_path / "kb.llkb"
+    rust_lib.save_kb(str(path), ids, ["a.md"] * 300, ["text"] * 300, ["{}"] * 300, index=index)
+    loaded = rust_lib.load_kb(str(path)).index()
+    assert (len(loaded), loaded.nlist, loaded.m, loaded.trained) == (300, 4, 4, True)
+    assert loaded.batch_search(embeddings[:20], 5) == index.batch_search(embeddings[:20], 5)
+
+
+def test_ivfpq_files_storing_a_chunk_twice_are_rejected(tmp_path):
+    ids, embeddings = sample(count=300)
+    index = trained_ivfpq(ids, embeddings, nlist=4, m=4, nbits=6)
+    path = tmp_path / "kb.llkb"
+    rust_lib.save_kb(str(path), ids, ["a.md"] * 300, ["text"] * 300, ["{}"] * 300, index=index)
+    # Renaming one stored chunk after another gives two entries for one id
+    path.write_bytes(path.read_bytes().replace(b"chunk-299", b"chunk-298"))
+    with pytest.raises(ValueError, match="twice"):
+        rust_lib.load_kb(str(path)).index()
+
+
+def test_retriever_sizes_ivfpq_to_small_knowledge_bases():
+    rng = np.random.default_rng(0)
+    for count in (1, 2, 40):
+        embeddings = rng.standard_normal((count, 16)).astype(np.float32)
+        kb = KnowledgeBase()
+        kb.add_chunks([
+            KnowledgeChunk(content=f"Chunk {i}", source="a.md", embedding=e.tolist())
+            for i, e in enumerate(embeddings)
+        ])
+        retriever = SemanticRetriever(kb, index_type="ivfpq", index_options={"m": 4})
+        
+        results, backend, _ = retriever.semantic_search(
+            embeddings[0].tolist(), top_k=1, score_threshold=None, backend="rust"
+        )
+        
+        assert backend.startswith("rust")
+        assert results[0]["chunk_id"] == kb.chunks[0].chunk_id
+        assert retriever.stored_index().nlist == count
+
+
+def test_compressed_indexes_release_float_embeddings():
+    ids, embeddings = sample(count=300)
+    saved = dict(zip(ids, embeddings))
+    for index_type in ("int8", "ivfpq"):
+        kb = KnowledgeBase()
+        kb.add_chunks([
+            KnowledgeChunk(content=f"Chunk {i}", source="a.md", chunk_id=ids[i], embedding=e.tolist())
+            for i, e in enumerate(embeddings)
+        ])
+        retriever = SemanticRetriever(
+            kb,
+            index_type=index_type,
+            index_options={"nlist": 4} if index_type == "ivfpq" else None,
+            rerank_factor=4,
+            vector_source=lambda chunk_ids: np.array([saved[chunk_id] for chunk_id in chunk_ids]),
+        )
+        query = embeddings[5].tolist()
+        before, _, _ = retriever.semantic_search(query, top_k=5, score_threshold=None, backend="rust")
+        
+        assert retriever.release_embeddings() == 300
+        assert all(chunk.embedding is None and retriever.has_embedding(chunk) for chunk in kb.chunks)
+        
+        # Re-ranking and the NumPy fallback read the released embeddings back
+        after, _, _ = retriever.semantic_search(query, top_k=5, score_threshold=None, backend="rust")
+        assert after == before and after[0]["chunk_id"] == ids[5]
+        exact, backend, _ = retriever.semantic_search(query, top_k=5, score_threshold=None, backend="numpy")
+        assert backend == "numpy" and exact[0]["chunk_id"] == ids[5]
+        
+        retriever.remove_chunks([ids[5]])
+        assert not retriever.has_embedding(kb.chunks[5])
@@ -0,0 +1,151 @@
+"""
+Property-based tests for the native text chunker.
+"""
+
+from hypothesis import given, strategies as st
+import pytest
+
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
+pytestmark = pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+
+STRATEGIES = ["fixed", "sentence", "recursive", "tokens"]
+
+documents = st.text(
+    alphabet=st.sampled_from(list("abcdé wörld.!?\n") + ["\n\n", ". ", "ünï"]),
+    max_size=2000,
+)
+
+
+@given(text=documents, strategy=st.sampled_from(STRATEGIES))
+def test_chunk_offsets_point_into_utf8_bytes(text, strategy):
+    """Each chunk's byte range should slice exactly its text out of the document."""
+    encoded = text.encode("utf-8")
+    for start, end, chunk in rust_lib.chunk_text(text, strategy=strategy, size=80, overlap=20):
+        assert encoded[start:end].decode("utf-8") == chunk
+        assert chunk == chunk.strip() and chunk
+
+
+@given(text=documents, strategy=st.sampled_from(STRATEGIES))
+def test_chunking_is_deterministic(text, strategy):
+    """Re-chunking the same text must give identical chunks, for stable ids."""
+    first = rust_lib.chunk_text(text, strategy=strategy, size=60, overlap=10)
+    assert first == rust_lib.chunk_text(text, strategy=strategy, size=60, overlap=10)
+
+
+@given(text=documents)
+def test_character_strategies_respect_size(text):
+    """Character-measured strategies never emit chunks longer than size."""
+    for strategy in ["fixed", "sentence", "recursive"]:
+        for _, _, chunk in rust_lib.chunk_text(text, strategy=strategy, size=50, overlap=10):
+            assert len(chunk) <= 50
+
+
+def test_chunks_cover_the_document():
+    """Without overlap, fixed windows tile the document end to end."""
+    text = "word " * 100
+    chunks = rust_lib.chunk_text(text, strategy="fixed", size=64, overlap=0)
+    assert "".join(chunk for _, _, chunk in chunks).replace(" ", "") == text.replace(" ", "")
+
+
+def test_invalid_arguments_raise():
+    with pytest.raises(ValueError):
+        rust_lib.chunk_text("text", strategy="semantic")
+    with pytest.raises(ValueError):
+        rust_lib.chunk_text("text", size=10, overlap=10)
+
+
+markdown_documents = st.lists(
+    st.sampled_from([
+        "# Title\n\n", "## Section\n\n", "### Sub ü\n\n",
+        "Some paragraph text. Another sentence.\n\n",
+        "- item one\n- item two\n\n",
+        "```python\ndef f():\n\n    return 1\n```\n\n",
+        "| a | b |\n|---|---|\n| 1 | 2 |\n\n",
+    ]),
+    max_size=40,
+).map("".join)
+
+
+@given(text=markdown_documents, size=st.integers(min_value=10, max_value=200))
+def test_markdown_chunks_keep_blocks_and_offsets(text, size):
+    """Markdown chunks slice the document exactly and never split code or tables."""
+    encoded = text.encode("utf-8")
+    for start, end, chunk, heading_path in rust_lib.chunk_markdown(text, size=size):
+        assert encoded[start:end].decode("utf-8") == chunk
+        assert chunk.count("```") % 2 == 0
+        if "| a | b |" in chunk:
+            assert "| 1 | 2 |" in chunk
+        assert len(heading_path) <= 3
+
+
+def test_markdown_chunks_record_heading_path():
+    text = (
+        "# Neural Networks\n\n"
+        "## Training\n\nIntro to training.\n\n"
+        "### Backprop\n\nGradients flow backwards.\n\n"
+        "## Types\n\nMany kinds.\n"
+    )
+    chunks = rust_lib.chunk_markdown(text, size=1000)
+    paths = {chunk: path for _, _, chunk, path in chunks}
+    assert paths["### Backprop\n\nGradients flow backwards."] == ["Neural Networks", "Training", "Backprop"]
+    assert paths["## Types\n\nMany kinds."] == ["Neural Networks", "Types"]
+    # A heading with no body of its own yields no chunk
+    assert all(chunk != "# Neural Networks" for chunk in paths)
+
+
+PYTHON_SOURCE = '''import os
+
+
+@decorator
+class Retriever:
+    """Finds chunks."""
+
+    def search(self, query):
+        return []
+
+    def index(self, chunks):
+        for chunk in chunks:
+            pass
+
+
+def helper():
+    return os.getcwd()
+'''
+
+
+def test_code_chunks_split_at_definitions():
+    """Each function or class becomes a chunk labelled with its symbol and lines."""
+    chunks = rust_lib.chunk_code(PYTHON_SOURCE, "python", size=1000)
+    symbols = [(symbol, start_line, end_line) for _, _, _, symbol, start_line, end_line in chunks]
+    assert symbols == [(None, 1, 1), ("Retriever", 4, 13), ("helper", 16, 17)]
+    encoded = PYTHON_SOURCE.encode("utf-8")
+    for start, end, chunk, _, start_line, end_line in chunks:
+        assert encoded[start:end].decode("utf-8") == chunk
+        assert chunk.splitlines() == PYTHON_SOURCE.splitlines()[start_line - 1:end_line]
+
+
+def test_large_definitions_split_into_members():
+    """A class larger than size is split into its methods, qualified by the class name."""
+    chunks = rust_lib.chunk_code(PYTHON_SOURCE, "python", size=80)
+    symbols = [symbol for _, _, _, symbol, _, _ in chunks]
+    assert "Retriever.search" in symbols and "Retriever.index" in symbols
+    assert chunks[1][2].startswith("@decorator\nclass Retriever:")
+
+
+@pytest.mark.parametrize("language, source, expected", [
+    ("rust", "/// Adds.\nfn add(a: i32) -> i32 { a }\n\nimpl Foo {\n    fn bar(&self) {}\n}\n", ["add", "Foo"]),
+    ("javascript", "export function add(a, b) { return a + b; }\nconst mul = (a, b) => a * b;\n", ["add", "mul"]),
+    ("go", "package main\n\nfunc (t *T) Get() int { return t.x }\n", [None, "T.Get"]),
+])
+def test_code_chunker_languages(language, source, expected):
+    assert [chunk[3] for chunk in rust_lib.chunk_code(source, language)] == expected
+
+
+def test_code_chunker_rejects_unknown_language():
+    with pytest.raises(ValueError):
+        rust_lib.chunk_code("x = 1", "cobol")
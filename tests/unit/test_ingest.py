@@ -0,0 +1,240 @@
+"""
+Tests for the native parallel directory ingestion.
+"""
+
+import io
+import os
+import zipfile
+
+import pytest
+
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
+pytestmark = pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+
+
+def make_pdf(pages):
+    """Build a minimal PDF with one line of Helvetica text per page."""
+    objects = ["<< /Type /Catalog /Pages 2 0 R >>", None, "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>"]
+    kids = []
+    for text in pages:
+        stream = f"BT /F1 12 Tf 72 720 Td ({text}) Tj ET"
+        objects.append(f"<< /Length {len(stream)} >>\nstream\n{stream}\nendstream")
+        objects.append(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] "
+            f"/Resources << /Font << /F1 3 0 R >> >> /Contents {len(objects)} 0 R >>"
+        )
+        kids.append(f"{len(objects)} 0 R")
+    objects[1] = f"<< /Type /Pages /Kids [{' '.join(kids)}] /Count {len(kids)} >>"
+    out = b"%PDF-1.4\n"
+    offsets = []
+    for number, body in enumerate(objects, 1):
+        offsets.append(len(out))
+        out += f"{number} 0 obj\n{body}\nendobj\n".encode("latin-1")
+    xref = len(out)
+    out += f"xref\n0 {len(objects) + 1}\n0000000000 65535 f \n".encode()
+    out += "".join(f"{offset:010d} 00000 n \n" for offset in offsets).encode()
+    out += f"trailer\n<< /Size {len(objects) + 1} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n".encode()
+    return out
+
+
+def make_docx(paragraphs):
+    """Build a minimal Word document from (style, text) paragraphs."""
+    body = ""
+    for style, text in paragraphs:
+        properties = f'<w:pPr><w:pStyle w:val="{style}"/></w:pPr>' if style else ""
+        body += f"<w:p>{properties}<w:r><w:t>{text}</w:t></w:r></w:p>"
+    return docx_with_body(body)
+
+
+def docx_with_body(body):
+    """Build a Word document around the WordprocessingML of its body."""
+    xml = (
+        '<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">'
+        f"<w:body>{body}</w:body></w:document>"
+    )
+    buffer = io.BytesIO()
+    with zipfile.ZipFile(buffer, "w") as archive:
+        archive.writestr("word/document.xml", xml)
+    return buffer.getvalue()
+
+
+def make_epub(chapters):
+    """Build a minimal EPUB whose spine lists the chapters in order."""
+    buffer = io.BytesIO()
+    with zipfile.ZipFile(buffer, "w") as archive:
+        archive.writestr(
+            "META-INF/container.xml",
+            '<container><rootfiles><rootfile full-path="OEBPS/book.opf"/></rootfiles></container>',
+        )
+        items = "".join(f'<item id="c{i}" href="ch{i}.xhtml"/>' for i in range(len(chapters)))
+        refs = "".join(f'<itemref idref="c{i}"/>' for i in range(len(chapters)))
+        archive.writestr("OEBPS/book.opf", f"<package><manifest>{items}</manifest><spine>{refs}</spine></package>")
+        for i, chapter in enumerate(chapters):
+            archive.writestr(f"OEBPS/ch{i}.xhtml", f"<html><body>{chapter}</body></html>")
+    return buffer.getvalue()
+
+
+@pytest.fixture
+def knowledge_dir(tmp_path):
+    (tmp_path / ".gitignore").write_text("build/\ndraft.md\n")
+    (tmp_path / "docs").mkdir()
+    (tmp_path / "docs" / "guide.md").write_text("# Guide\n\n## Setup\n\nInstall the package first.\n")
+    (tmp_path / "notes.txt").write_bytes("Café au lait, déjà vu.\n".encode("latin-1"))
+    (tmp_path / "utf16.txt").write_bytes("Ünïcode in UTF-16.\n".encode("utf-16"))
+    (tmp_path / "tool.py").write_text("def run():\n    return 1\n")
+    (tmp_path / "image.txt").write_bytes(b"\x89PNG\x00\x00binary")
+    (tmp_path / "draft.md").write_text("Ignored by .gitignore\n")
+    (tmp_path / "build").mkdir()
+    (tmp_path / "build" / "out.md").write_text("Ignored build output\n")
+    (tmp_path / "node_modules").mkdir()
+    (tmp_path / "node_modules" / "dep.md").write_text("Ignored dependency\n")
+    return tmp_path
+
+
+def test_ingest_honours_ignores_and_globs(knowledge_dir):
+    chunks, errors = rust_lib.ingest_directory(
+        str(knowledge_dir), globs=["*.md", "*.txt", "*.py"], ignore=["node_modules/"]
+    )
+    assert errors == []
+    assert sorted({chunk.source for chunk in chunks}) == ["docs/guide.md", "notes.txt", "tool.py", "utf16.txt"]
+
+
+def test_ingest_decodes_and_chunks_by_file_type(knowledge_dir):
+    chunks, _ = rust_lib.ingest_directory(str(knowledge_dir), globs=["*.md", "*.txt", "*.py"])
+    by_source = {chunk.source: chunk for chunk in chunks}
+    assert by_source["notes.txt"].text == "Café au lait, déjà vu."
+    assert by_source["notes.txt"].encoding != "UTF-8"
+    assert by_source["utf16.txt"].text == "Ünïcode in UTF-16."
+    assert by_source["docs/guide.md"].strategy == "markdown"
+    assert by_source["docs/guide.md"].heading_path == ["Guide", "Setup"]
+    assert by_source["tool.py"].symbol == "run"
+    assert (by_source["tool.py"].start_line, by_source["tool.py"].end_line) == (1, 2)
+
+
+def test_content_hashes_are_stable(knowledge_dir):
+    first, _ = rust_lib.ingest_directory(str(knowledge_dir))
+    (knowledge_dir / "tool.py").write_text("def run():\n    return 2\n")
+    second, _ = rust_lib.ingest_directory(str(knowledge_dir))
+    hashes = lambda chunks: {chunk.source: chunk.content_hash for chunk in chunks}
+    changed = {source for source, digest in hashes(second).items() if hashes(first)[source] != digest}
+    assert changed == {"tool.py"}
+
+
+def test_ingest_rejects_missing_directory(tmp_path):
+    with pytest.raises(ValueError):
+        rust_lib.ingest_directory(str(tmp_path / "missing"))
+
+
+def test_pdf_pages_land_in_chunks(tmp_path):
+    (tmp_path / "paper.pdf").write_bytes(make_pdf(["Backpropagation computes gradients.", "Attention is all you need."]))
+    assert [page.strip() for page in rust_lib.extract_pdf_pages(str(tmp_path / "paper.pdf"))] == [
+        "Backpropagation computes gradients.",
+        "Attention is all you need.",
+    ]
+    chunks, errors = rust_lib.ingest_directory(str(tmp_path))
+    assert errors == []
+    assert [(chunk.page, chunk.text) for chunk in chunks] == [
+        (1, "Backpropagation computes gradients."),
+        (2, "Attention is all you need."),
+    ]
+
+
+def test_malformed_pdf_is_reported(tmp_path):
+    (tmp_path / "broken.pdf").write_bytes(b"%PDF-1.4 not really")
+    chunks, errors = rust_lib.ingest_directory(str(tmp_path))
+    assert chunks == [] and len(errors) == 1 and errors[0].startswith("broken.pdf:")
+    with pytest.raises(ValueError):
+        rust_lib.extract_pdf_pages(str(tmp_path / "broken.pdf"))
+
+
+def test_structured_documents_keep_their_headings(tmp_path):
+    (tmp_path / "page.html").write_text(
+        "<html><body><nav>Home</nav><div id='main-content'><h1>Deploy</h1><p>Read  this first.</p>"
+        "<h2>Steps</h2><ul><li>Build</li><li>Ship</li></ul>"
+        "<table><tr><th>Env</th></tr><tr><td>prod</td></tr></table></div></body></html>"
+    )
+    (tmp_path / "report.docx").write_bytes(make_docx([
+        ("Heading1", "Revenue"), (None, "Revenue grew this quarter."),
+        ("Heading2", "By region"), (None, "EMEA led growth."),
+    ]))
+    (tmp_path / "manual.epub").write_bytes(make_epub([
+        "<h1>Install</h1><p>Run the installer.</p>", "<h1>Usage</h1><p>Start the app.</p>",
+    ]))
+
+    assert rust_lib.convert_document(str(tmp_path / "page.html")) == (
+        "# Deploy\n\nRead this first.\n\n## Steps\n\n- Build\n- Ship\n\n| Env |\n|---|\n| prod |"
+    )
+    chunks, errors = rust_lib.ingest_directory(str(tmp_path))
+    assert errors == []
+    paths = [(chunk.source, chunk.heading_path) for chunk in chunks]
+    assert paths == [
+        ("manual.epub", ["Install"]),
+        ("manual.epub", ["Usage"]),
+        ("page.html", ["Deploy"]),
+        ("page.html", ["Deploy", "Steps"]),
+        ("report.docx", ["Revenue"]),
+        ("report.docx", ["Revenue", "By region"]),
+    ]
+    assert all(chunk.strategy == "markdown" for chunk in chunks)
+
+
+def test_docx_outline_levels_and_empty_runs(tmp_path):
+    (tmp_path / "notes.docx").write_bytes(docx_with_body(
+        '<w:p><w:pPr><w:outlineLvl w:val="0"/></w:pPr><w:r><w:t>Summary</w:t></w:r></w:p>'
+        '<w:p><w:pPr><w:outlineLvl w:val="9"/></w:pPr><w:r><w:t>Body text.</w:t></w:r></w:p>'
+        '<w:p><w:r><w:t/></w:r><w:r><w:instrText>PAGE</w:instrText></w:r><w:r><w:t>Seen.</w:t></w:r></w:p>'
+    ))
+    # Level 9 is body text, and the field code after an empty run is not text
+    assert rust_lib.convert_document(str(tmp_path / "notes.docx")) == "# Summary\n\nBody text.\n\nSeen."
+
+
+def test_manifest_reports_changed_chunks(knowledge_dir):
+    manifest = str(knowledge_dir / ".llamasearch" / "manifest.json")
+    first = rust_lib.ingest_changes(str(knowledge_dir), manifest)
+    assert first.unchanged == [] and first.changed == [] and first.deleted == []
+    first.save()
+
+    os.utime(knowledge_dir / "notes.txt")
+    assert len(rust_lib.ingest_changes(str(knowledge_dir), manifest).unchanged) == len(first.added)
+
+    (knowledge_dir / "tool.py").write_text("def run():\n    return 2\n")
+    (knowledge_dir / "notes.txt").unlink()
+    (knowledge_dir / "new.md").write_text("Fresh notes.\n")
+    delta = rust_lib.ingest_changes(str(knowledge_dir), manifest)
+    assert [chunk.source for chunk in delta.added] == ["new.md"]
+    assert [(old.text, new.text) for old, new in delta.changed] == [
+        ("def run():\n    return 1", "def run():\n    return 2"),
+    ]
+    assert [chunk.source for chunk in delta.deleted] == ["notes.txt"]
+    assert len(delta.unchanged) == len(first.added) - 2
+
+    # Nothing is recorded until the delta is saved
+    assert len(rust_lib.ingest_changes(str(knowledge_dir), manifest).changed) == 1
+    delta.save()
+    again = rust_lib.ingest_changes(str(knowledge_dir), manifest)
+    assert again.added == again.changed == again.deleted == []
+
+
+def test_watcher_reports_matching_changes(knowledge_dir):
+    watcher = rust_lib.DirectoryWatcher(str(knowledge_dir), globs=["*.md", "*.txt"], ignore=["node_modules/"])
+    assert watcher.wait(timeout=0.1) == []
+    for timeout, debounce in [(-1, 0.2), (float("nan"), 0.2), (0.1, float("inf")), (0.1, float("nan"))]:
+        with pytest.raises(ValueError):
+            watcher.wait(timeout=timeout, debounce=debounce)
+
+    (knowledge_dir / "docs" / "guide.md").write_text("# Guide\n\nRewritten.\n")
+    (knowledge_dir / "draft.md").write_text("Still ignored\n")
+    (knowledge_dir / "node_modules" / "dep.md").write_text("Still ignored\n")
+    (knowledge_dir / ".llamasearch").mkdir()
+    (knowledge_dir / ".llamasearch" / "manifest.json").write_text("{}")
+    (knowledge_dir / "notes.txt").unlink()
+    assert watcher.wait(timeout=5) == ["docs/guide.md", "notes.txt"]
+
+    watcher.close()
+    (knowledge_dir / "utf16.txt").write_text("After closing\n")
+    assert watcher.wait(timeout=0.1) == []
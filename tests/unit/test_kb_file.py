@@ -0,0 +1,84 @@
+"""
+Tests for the versioned, memory-mapped knowledge base file.
+"""
+
+import json
+
+import numpy as np
+import pytest
+
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
+pytestmark = pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+
+
+def save_sample(path, count=50, dim=16, index=None):
+    rng = np.random.default_rng(0)
+    embeddings = rng.standard_normal((count, dim)).astype(np.float32)
+    ids = [f"chunk-{i}" for i in range(count)]
+    if index is not None:
+        index.add_many(ids, embeddings)
+    rust_lib.save_kb(
+        str(path),
+        ids,
+        [f"doc-{i % 5}.md" for i in range(count)],
+        [f"Text of chunk {i}, café." for i in range(count)],
+        [json.dumps({"chunk_index": i}) for i in range(count)],
+        embeddings=embeddings,
+        index=index,
+        info=json.dumps({"embedding_model": "test-model"}),
+        created_at=[1700000000.5 + i for i in range(count)],
+    )
+    return ids, embeddings
+
+
+def test_round_trip_keeps_chunks_embeddings_and_index(tmp_path):
+    path = tmp_path / "kb.llkb"
+    index = rust_lib.HnswIndex(16, m=8)
+    ids, embeddings = save_sample(path, index=index)
+
+    kb_file = rust_lib.load_kb(str(path))
+    assert (len(kb_file), kb_file.dim, kb_file.version) == (50, 16, 1)
+    assert json.loads(kb_file.info) == {"embedding_model": "test-model"}
+    assert kb_file.chunks()[3] == ("chunk-3", "doc-3.md", "Text of chunk 3, café.", '{"chunk_index": 3}')
+    assert kb_file.created_at()[3] == 1700000003.5
+
+    vectors = kb_file.embeddings()
+    np.testing.assert_array_equal(vectors, embeddings)
+    assert not vectors.flags.writeable
+
+    loaded = kb_file.index()
+    assert isinstance(loaded, rust_lib.HnswIndex) and len(loaded) == len(ids)
+    assert loaded.batch_search(embeddings[:5], 3) == index.batch_search(embeddings[:5], 3)
+
+
+def test_file_without_embeddings_or_index(tmp_path):
+    path = tmp_path / "kb.llkb"
+    rust_lib.save_kb(str(path), ["a"], ["a.md"], ["Only text"], ["{}"])
+    kb_file = rust_lib.load_kb(str(path))
+    assert kb_file.embeddings() is None and kb_file.index() is None and kb_file.info is None
+    assert kb_file.created_at() is None
+    assert kb_file.chunks() == [("a", "a.md", "Only text", "{}")]
+
+
+def test_corrupt_files_are_rejected(tmp_path):
+    path = tmp_path / "kb.llkb"
+    save_sample(path)
+    data = path.read_bytes()
+
+    path.write_bytes(data[: len(data) // 2])
+    with pytest.raises(ValueError):
+        rust_lib.load_kb(str(path))
+    path.write_bytes(b"not a knowledge base" + data[20:])
+    with pytest.raises(ValueError):
+        rust_lib.load_kb(str(path))
+    with pytest.raises(FileNotFoundError):
+        rust_lib.load_kb(str(tmp_path / "missing.llkb"))
+    with pytest.raises(ValueError):
+        rust_lib.save_kb(str(path), ["a", "b"], ["a.md"], ["text"], ["{}"])
+    with pytest.raises(ValueError):
+        rust_lib.save_kb(str(path), ["a"], ["a.md"], ["text"], ["{}"], created_at=[1.0, 2.0])
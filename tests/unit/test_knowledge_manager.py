@@ -0,0 +1,86 @@
+"""
+Tests for KnowledgeManager's incremental loading and directory watching.
+"""
+
+import hashlib
+import time
+from types import SimpleNamespace
+
+import pytest
+
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
+from llamasearch_experimentalagents_augmented_professional.integrations.knowledge_manager import KnowledgeManager, state_path_for
+
+pytestmark = pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+
+
+class FakeEmbeddings:
+    """Stands in for client.embeddings, embedding each text by its hash and counting the texts."""
+
+    def __init__(self):
+        self.embedded = []
+
+    def create(self, model, input):
+        self.embedded.extend(input)
+        return SimpleNamespace(data=[
+            SimpleNamespace(embedding=[float(b) - 127.5 for b in hashlib.sha256(text.encode()).digest()[:8]])
+            for text in input
+        ])
+
+
+@pytest.fixture
+def manager(tmp_path):
+    client = SimpleNamespace(embeddings=FakeEmbeddings())
+    manager = KnowledgeManager(openai_client=client, state_dir=str(tmp_path / "state"))
+    yield manager
+    manager.stop_watching()
+
+
+def contents(manager):
+    return sorted(chunk.content for chunk in manager.kb.chunks)
+
+
+def test_watched_edits_reach_the_index_on_refresh(manager, tmp_path):
+    knowledge_dir = tmp_path / "docs"
+    knowledge_dir.mkdir()
+    (knowledge_dir / "guide.md").write_text("The guide explains deployment.\n")
+    (knowledge_dir / "node_modules").mkdir()
+    manager.load_documents_from_directory(str(knowledge_dir))
+    manager.watch_directory(str(knowledge_dir))
+
+    (knowledge_dir / "guide.md").write_text("The guide now explains rollbacks.\n")
+    (knowledge_dir / "faq.md").write_text("Frequently asked questions live here.\n")
+    (knowledge_dir / "node_modules" / "dep.md").write_text("A dependency's readme is never indexed.\n")
+    changed = []
+    deadline = time.monotonic() + 5
+    while "faq.md" not in changed and time.monotonic() < deadline:
+        changed += manager.refresh()
+        time.sleep(0.1)
+
+    assert "node_modules/dep.md" not in changed
+    assert contents(manager) == ["Frequently asked questions live here.", "The guide now explains rollbacks."]
+    assert all(chunk.embedding is not None for chunk in manager.kb.chunks)
+
+
+def test_incremental_state_is_kept_outside_the_directory(manager, tmp_path):
+    knowledge_dir = tmp_path / "docs"
+    knowledge_dir.mkdir()
+    (knowledge_dir / "guide.md").write_text("The guide explains deployment.\n")
+    manager.load_documents_from_directory(str(knowledge_dir))
+
+    assert [path.name for path in knowledge_dir.iterdir()] == ["guide.md"]
+    state = state_path_for(tmp_path / "state", knowledge_dir)
+    assert (state / "manifest.json").is_file() and (state / "knowledge.llkb").is_file()
+
+    # A new session reloads the saved chunks instead of embedding them again
+    client = SimpleNamespace(embeddings=FakeEmbeddings())
+    reloaded = KnowledgeManager(openai_client=client, state_dir=str(tmp_path / "state"))
+    reloaded.load_documents_from_directory(str(knowledge_dir))
+    assert client.embeddings.embedded == []
+    assert contents(reloaded) == contents(manager)
+    assert [c.created_at for c in reloaded.kb.chunks] == [c.created_at for c in manager.kb.chunks]
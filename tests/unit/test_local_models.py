@@ -0,0 +1,185 @@
+"""
+Tests for local embedding and reranking inference, run on tiny randomly
+initialised BERTs.
+"""
+
+import json
+import struct
+
+import numpy as np
+import pytest
+
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
+pytestmark = pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+
+SPECIAL_TOKENS = ["[PAD]", "[UNK]", "[CLS]", "[SEP]"]
+WORDS = ["the", "cat", "sat", "on", "mat", "dog", "ran", "far", "away", "##s", "."]
+HIDDEN, LAYERS, HEADS, INTERMEDIATE, MAX_POSITIONS = 16, 2, 2, 32, 64
+
+
+def write_tokenizer(path):
+    vocab = {token: i for i, token in enumerate(SPECIAL_TOKENS + WORDS)}
+    special = lambda token, type_id=0: {"SpecialToken": {"id": token, "type_id": type_id}}
+    tokenizer = {
+        "version": "1.0",
+        "truncation": None,
+        "padding": None,
+        "added_tokens": [
+            {"id": i, "content": token, "single_word": False, "lstrip": False,
+             "rstrip": False, "normalized": False, "special": True}
+            for i, token in enumerate(SPECIAL_TOKENS)
+        ],
+        "normalizer": {"type": "BertNormalizer", "clean_text": True, "handle_chinese_chars": True,
+                       "strip_accents": None, "lowercase": True},
+        "pre_tokenizer": {"type": "BertPreTokenizer"},
+        "post_processor": {
+            "type": "TemplateProcessing",
+            "single": [special("[CLS]"), {"Sequence": {"id": "A", "type_id": 0}}, special("[SEP]")],
+            "pair": [special("[CLS]"), {"Sequence": {"id": "A", "type_id": 0}}, special("[SEP]"),
+                     {"Sequence": {"id": "B", "type_id": 1}}, special("[SEP]", 1)],
+            "special_tokens": {
+                token: {"id": token, "ids": [vocab[token]], "tokens": [token]}
+                for token in ("[CLS]", "[SEP]")
+            },
+        },
+        "decoder": None,
+        "model": {"type": "WordPiece", "unk_token": "[UNK]", "continuing_subword_prefix": "##",
+                  "max_input_chars_per_word": 100, "vocab": vocab},
+    }
+    path.write_text(json.dumps(tokenizer))
+    return len(vocab)
+
+
+def write_safetensors(path, tensors):
+    header, offset = {}, 0
+    for name, tensor in tensors.items():
+        header[name] = {"dtype": "F32", "shape": list(tensor.shape),
+                        "data_offsets": [offset, offset + tensor.nbytes]}
+        offset += tensor.nbytes
+    encoded = json.dumps(header).encode()
+    with open(path, "wb") as f:
+        f.write(struct.pack("<Q", len(encoded)) + encoded)
+        for tensor in tensors.values():
+            f.write(tensor.astype("<f4").tobytes())
+
+
+def write_bert(path, prefix="", head=False):
+    """
+    Write a 2-layer BERT with random weights to directory path.
+    
+    Weight names start with prefix; with head the pooler and a single
+    output classifier of a cross-encoder are added.
+    """
+    vocab_size = write_tokenizer(path / "tokenizer.json")
+    (path / "config.json").write_text(json.dumps({
+        "vocab_size": vocab_size, "hidden_size": HIDDEN, "num_hidden_layers": LAYERS,
+        "num_attention_heads": HEADS, "intermediate_size": INTERMEDIATE, "hidden_act": "gelu",
+        "hidden_dropout_prob": 0.1, "max_position_embeddings": MAX_POSITIONS,
+        "type_vocab_size": 2, "initializer_range": 0.02, "layer_norm_eps": 1e-12,
+        "pad_token_id": 0, "model_type": "bert",
+    }))
+    rng = np.random.default_rng(0)
+    weight = lambda *shape: rng.normal(0, 0.5, shape).astype(np.float32)
+    tensors = {
+        prefix + "embeddings.word_embeddings.weight": weight(vocab_size, HIDDEN),
+        prefix + "embeddings.position_embeddings.weight": weight(MAX_POSITIONS, HIDDEN),
+        prefix + "embeddings.token_type_embeddings.weight": weight(2, HIDDEN),
+        prefix + "embeddings.LayerNorm.weight": np.ones(HIDDEN, np.float32),
+        prefix + "embeddings.LayerNorm.bias": np.zeros(HIDDEN, np.float32),
+    }
+    for layer in range(LAYERS):
+        layer_prefix = f"{prefix}encoder.layer.{layer}."
+        dense = {
+            "attention.self.query": (HIDDEN, HIDDEN),
+            "attention.self.key": (HIDDEN, HIDDEN),
+            "attention.self.value": (HIDDEN, HIDDEN),
+            "attention.output.dense": (HIDDEN, HIDDEN),
+            "intermediate.dense": (INTERMEDIATE, HIDDEN),
+            "output.dense": (HIDDEN, INTERMEDIATE),
+        }
+        for name, shape in dense.items():
+            tensors[layer_prefix + name + ".weight"] = weight(*shape)
+            tensors[layer_prefix + name + ".bias"] = weight(shape[0])
+        for name in ("attention.output.LayerNorm", "output.LayerNorm"):
+            tensors[layer_prefix + name + ".weight"] = np.ones(HIDDEN, np.float32)
+            tensors[layer_prefix + name + ".bias"] = np.zeros(HIDDEN, np.float32)
+    if head:
+        tensors[prefix + "pooler.dense.weight"] = weight(HIDDEN, HIDDEN)
+        tensors[prefix + "pooler.dense.bias"] = weight(HIDDEN)
+        tensors["classifier.weight"] = weight(1, HIDDEN)
+        tensors["classifier.bias"] = weight(1)
+    write_safetensors(path / "model.safetensors", tensors)
+    return path
+
+
+@pytest.fixture
+def model_dir(tmp_path):
+    """A sentence-transformer directory."""
+    return write_bert(tmp_path)
+
+
+@pytest.fixture
+def cross_encoder_dir(tmp_path):
+    """A cross-encoder directory laid out like BertForSequenceClassification."""
+    (tmp_path / "cross-encoder").mkdir()
+    return write_bert(tmp_path / "cross-encoder", prefix="bert.", head=True)
+
+
+def test_embeddings_are_unit_rows_in_input_order(model_dir):
+    embedder = rust_lib.TextEmbedder(str(model_dir), name="tiny")
+    assert (embedder.name, embedder.dim, embedder.pooling) == ("tiny", HIDDEN, "mean")
+    texts = ["The cat sat on the mat.", "Dogs ran far away", "cat"]
+    embeddings = embedder.embed(texts)
+    assert embeddings.shape == (3, HIDDEN) and embeddings.dtype == np.float32
+    np.testing.assert_allclose(np.linalg.norm(embeddings, axis=1), 1.0, rtol=1e-5)
+
+    # Padding in a shared batch does not change a text's embedding
+    for i, text in enumerate(texts):
+        np.testing.assert_allclose(embedder.embed([text])[0], embeddings[i], atol=1e-5)
+    assert not np.allclose(embeddings[0], embeddings[1])
+
+
+def test_pooling_follows_the_sentence_transformers_config(model_dir):
+    mean = rust_lib.TextEmbedder(str(model_dir)).embed(["the dog sat"])
+    (model_dir / "1_Pooling").mkdir()
+    (model_dir / "1_Pooling" / "config.json").write_text(
+        json.dumps({"word_embedding_dimension": HIDDEN, "pooling_mode_cls_token": True})
+    )
+    embedder = rust_lib.TextEmbedder(str(model_dir))
+    assert embedder.pooling == "cls" and embedder.name == model_dir.name
+    assert not np.allclose(embedder.embed(["the dog sat"]), mean)
+
+
+def test_missing_model_files_are_reported(model_dir, tmp_path):
+    with pytest.raises(FileNotFoundError):
+        rust_lib.TextEmbedder(str(tmp_path / "missing"))
+    (model_dir / "model.safetensors").unlink()
+    with pytest.raises(FileNotFoundError):
+        rust_lib.TextEmbedder(str(model_dir))
+
+
+def test_cross_encoder_scores_and_reranks_pairs(cross_encoder_dir):
+    reranker = rust_lib.CrossEncoder(str(cross_encoder_dir), batch_size=2)
+    assert reranker.name == "cross-encoder"
+    query = "where did the cat sit"
+    passages = ["The cat sat on the mat.", "Dogs ran far away", "cat", "the dog sat on the cat"]
+    scores = reranker.score(query, passages)
+    assert len(scores) == 4 and all(0.0 < score < 1.0 for score in scores)
+    # Padding in a shared batch does not change a pair's score
+    for passage, score in zip(passages, scores):
+        assert reranker.score(query, [passage])[0] == pytest.approx(score, abs=1e-5)
+
+    ranked = reranker.rerank(query, passages)
+    assert [position for position, _ in ranked] == sorted(range(4), key=lambda i: -scores[i])
+    assert reranker.rerank(query, passages, top_k=2) == ranked[:2]
+    assert reranker.rerank(query, []) == []
+
+
+def test_an_embedding_model_is_not_a_cross_encoder(model_dir):
+    with pytest.raises(ValueError):
+        rust_lib.CrossEncoder(str(model_dir))
@@ -22,8 +22,14 @@ try:
 except ImportError:
     HAS_JAX = False
 
-from llamasearch_experimentalagents_augmented_professional.agents.retriever import SemanticRetriever
-from llamasearch_experimentalagents_augmented_professional.models.knowledge import KnowledgeBase, KnowledgeChunk
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
+from llamasearch_experimentalagents_augmented_professional.agents.agents_retriever import SemanticRetriever
+from llamasearch_experimentalagents_augmented_professional.models.models_knowledge import KnowledgeBase, KnowledgeChunk
 
 
 # Strategy for valid embedding vectors
@@ -131,10 +137,10 @@ def test_backend_selection():
     backend4 = retriever._select_backend(query_embedding)  # Auto
     
     # Check that we got valid backends
-    assert backend1 in ["mlx", "jax", "numpy"]
-    assert backend2 in ["mlx", "jax", "numpy"]
+    assert backend1 in ["mlx", "jax", "rust", "numpy"]
+    assert backend2 in ["mlx", "jax", "rust", "numpy"]
     assert backend3 == "numpy"
-    assert backend4 in ["mlx", "jax", "numpy"]
+    assert backend4 in ["mlx", "jax", "rust", "numpy"]
 
 
 # Test MLX cosine similarity if available
@@ -193,6 +199,283 @@ def test_jax_cosine_similarity_bounds(query, docs):
         pytest.skip(f"JAX test failed with {str(e)}")
 
 
+# Test that the Rust top-k agrees with the NumPy scores
+@pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+@given(
+    query=valid_embedding_vector(min_dim=10, max_dim=10),
+    docs=st.lists(valid_embedding_vector(min_dim=10, max_dim=10), min_size=1, max_size=20),
+    top_k=st.integers(min_value=1, max_value=5)
+)
+def test_rust_cosine_top_k_matches_numpy(query, docs, top_k):
+    """Test that the Rust top-k returns the best NumPy-scored rows in order."""
+    query_np = np.array(query, dtype=np.float32)
+    docs_np = np.array(docs, dtype=np.float32)
+
+    expected = SemanticRetriever._numpy_cosine_sim(query_np, docs_np)
+    matches = rust_lib.cosine_top_k(query, docs, top_k)
+
+    assert len(matches) == min(top_k, len(docs))
+    scores = [score for _, score in matches]
+    assert scores == sorted(scores, reverse=True), "Results should be sorted best first"
+    best = np.sort(expected)[::-1][:top_k]
+    for (index, score), best_score in zip(matches, best):
+        assert abs(score - expected[index]) < 1e-4
+        assert abs(score - best_score) < 1e-4
+
+
+# Test that a mismatched batch leaves the Rust index untouched
+@pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+def test_rust_add_many_rejects_mismatched_batches_whole():
+    """A batch whose ids, rows or dimension disagree is refused before any row is stored."""
+    index = rust_lib.EmbeddingIndex(4)
+    index.add("kept", np.ones(4, dtype=np.float32))
+    
+    with pytest.raises(ValueError):
+        index.add_many(["a", "b", "c"], np.ones((2, 4), dtype=np.float32))
+    with pytest.raises(ValueError):
+        index.add_many(["a", "b"], np.ones((2, 3), dtype=np.float32))
+    with pytest.raises(ValueError):
+        index.add_many(["a", "b"], np.ones((2, 4), dtype=np.float32), [{"page": 1}])
+    
+    assert len(index) == 1
+    assert "a" not in index and "b" not in index
+
+
+# Test that batch search answers every query like a single search
+@pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+def test_rust_batch_search_matches_single_searches():
+    """Each row of a parallel batch search equals the search for that query alone."""
+    rng = np.random.default_rng(3)
+    docs = rng.normal(size=(300, 16)).astype(np.float32)
+    queries = rng.normal(size=(25, 16)).astype(np.float32)
+    index = rust_lib.EmbeddingIndex(16)
+    index.add_many(
+        [str(i) for i in range(len(docs))],
+        docs,
+        [{"page": i % 3} for i in range(len(docs))],
+    )
+    
+    for threshold, metadata_filter in [(None, None), (0.2, None), (None, {"page": 1})]:
+        batch = index.batch_search(queries, 7, threshold, metadata_filter)
+        assert len(batch) == len(queries)
+        for query, matches in zip(queries, batch):
+            assert matches == index.search(query, 7, threshold, metadata_filter)
+
+
+# Recall of a quantized index, optionally re-ranked, on clustered embeddings
+def _quantized_recall(kind, rerank_factor, k=10, n_docs=2000, n_queries=50, dim=128):
+    """Mean recall@k of a quantized index against the exact NumPy cosine ranking."""
+    rng = np.random.default_rng(0)
+    centers = rng.normal(size=(32, dim))
+    docs = (centers[rng.integers(32, size=n_docs)] + 0.5 * rng.normal(size=(n_docs, dim))).astype(np.float32)
+    queries = (centers[rng.integers(32, size=n_queries)] + 0.5 * rng.normal(size=(n_queries, dim))).astype(np.float32)
+    ids = [str(i) for i in range(n_docs)]
+    
+    index = rust_lib.QuantizedIndex(dim, kind=kind)
+    index.add_many(ids, docs)
+    
+    hits = 0
+    for query in queries:
+        exact = np.argsort(-SemanticRetriever._numpy_cosine_sim(query, docs), kind="stable")[:k]
+        matches = index.search(query, k * max(rerank_factor, 1))
+        if rerank_factor:
+            candidates = [int(chunk_id) for chunk_id, _ in matches]
+            matches = rust_lib.rerank(query, [ids[i] for i in candidates], docs[candidates], k)
+        hits += len({int(chunk_id) for chunk_id, _ in matches[:k]} & set(exact.tolist()))
+    return hits / (k * n_queries)
+
+
+# Test that quantized search stays close to exact search
+@pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+@pytest.mark.parametrize("kind, rerank_factor, min_recall", [
+    ("int8", 0, 0.9),
+    ("int8", 4, 0.99),
+    ("binary", 0, 0.25),
+    ("binary", 10, 0.8),
+])
+def test_quantized_index_recall(kind, rerank_factor, min_recall):
+    """Quantized search should stay close to the exact NumPy cosine ranking."""
+    recall = _quantized_recall(kind, rerank_factor)
+    assert recall >= min_recall, f"{kind} recall@10 was {recall:.3f}"
+
+
+# Test that int8 and binary codes round-trip
+@pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+def test_quantize_round_trip():
+    """Dequantized int8 codes stay within half a quantization step of the input."""
+    rng = np.random.default_rng(1)
+    matrix = rng.normal(size=(16, 64)).astype(np.float32)
+    
+    codes, scales = rust_lib.quantize_int8(matrix)
+    assert codes.dtype == np.int8 and codes.shape == matrix.shape
+    restored = rust_lib.dequantize_int8(codes, scales)
+    assert np.all(np.abs(restored - matrix) <= scales[:, None] / 2 + 1e-6)
+    
+    packed = rust_lib.quantize_binary(matrix)
+    assert np.array_equal(packed, np.packbits(matrix > 0, axis=-1))
+    assert np.array_equal(rust_lib.dequantize_binary(packed, 64), np.where(matrix > 0, 1.0, -1.0))
+
+
+# Test that the native metrics match the NumPy reference
+@pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+@pytest.mark.parametrize("metric", ["cosine", "dot", "ip_normalized", "l2"])
+def test_rust_metrics_match_numpy(metric):
+    """Each native metric should rank and score like the NumPy reference."""
+    rng = np.random.default_rng(2)
+    docs = rng.normal(size=(200, 32)).astype(np.float32)
+    query = rng.normal(size=32).astype(np.float32)
+    
+    index = rust_lib.EmbeddingIndex(32, metric=metric)
+    index.add_many([str(i) for i in range(len(docs))], docs)
+    matches = index.search(query, 10)
+    
+    expected = SemanticRetriever._numpy_scores(query, docs, metric)
+    order = np.argsort(expected if metric == "l2" else -expected)[:10]
+    assert [int(chunk_id) for chunk_id, _ in matches] == order.tolist()
+    for chunk_id, score in matches:
+        assert abs(score - expected[int(chunk_id)]) < 1e-3 * max(1.0, abs(expected[int(chunk_id)]))
+    
+    # l2 thresholds are a maximum distance, every other metric's a minimum score
+    threshold = float(expected[order[4]] + expected[order[5]]) / 2
+    assert len(index.search(query, 10, threshold)) == 5
+
+
+# Test that other metrics are refused with an approximate or quantized index
+@pytest.mark.parametrize("index_type", ["hnsw", "ivfpq", "int8", "binary"])
+def test_non_cosine_metrics_need_the_flat_index(index_type):
+    """The retriever should fail up front rather than score by cosine anyway."""
+    with pytest.raises(ValueError, match='only supports the cosine metric, not dot; use index_type="flat"'):
+        SemanticRetriever(KnowledgeBase(), index_type=index_type, metric="dot")
+
+
+# Test that the native approximate and quantized indexes refuse other metrics
+@pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+def test_rust_ann_indexes_refuse_other_metrics():
+    """Only the flat index scores by dot products or distances."""
+    for make in (
+        lambda metric: rust_lib.HnswIndex(8, metric=metric),
+        lambda metric: rust_lib.IvfPqIndex(8, m=2, metric=metric),
+        lambda metric: rust_lib.QuantizedIndex(8, kind="binary", metric=metric),
+    ):
+        make("cosine")
+        with pytest.raises(ValueError, match="only supports the cosine metric, not l2"):
+            make("l2")
+    assert rust_lib.EmbeddingIndex(8, metric="l2").metric == "l2"
+
+
+# Test that metadata filters apply before top-k selection
+@pytest.mark.parametrize("backend", [
+    "numpy",
+    pytest.param("rust", marks=pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")),
+])
+def test_metadata_filter_applies_before_top_k(backend):
+    """Filtered-out chunks must not take top-k slots from matching ones."""
+    kb = KnowledgeBase()
+    kb.add_chunks([
+        KnowledgeChunk(content="best", source="notes/best.txt", embedding=[1.0, 0.0]),
+        KnowledgeChunk(content="close", source="notes/close.md", embedding=[0.9, 0.1]),
+        KnowledgeChunk(content="far", source="api/far.md", embedding=[0.5, 0.5]),
+    ])
+    retriever = SemanticRetriever(kb)
+    
+    results, _, _ = retriever.semantic_search(
+        query_embedding=[1.0, 0.0],
+        top_k=1,
+        score_threshold=None,
+        backend=backend,
+        metadata_filter={"source_type": "markdown"},
+    )
+    assert [r["content"] for r in results] == ["close"]
+    
+    results, _, _ = retriever.semantic_search(
+        query_embedding=[1.0, 0.0],
+        top_k=3,
+        score_threshold=None,
+        backend=backend,
+        metadata_filter={"source": {"$prefix": "api/"}, "filename": {"$in": ["far.md"]}},
+    )
+    assert [r["content"] for r in results] == ["far"]
+
+
+# Test that every operator of a filter condition applies on each backend
+@pytest.mark.parametrize("backend", [
+    "numpy",
+    pytest.param("rust", marks=pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")),
+])
+def test_mixed_operator_filters_apply_every_operator(backend):
+    """A condition mixing $in with a range, or $prefix with $eq, must match all of them."""
+    kb = KnowledgeBase()
+    kb.add_chunks([
+        KnowledgeChunk(
+            content=f"page {page}",
+            source=f"doc{page % 2}.md",
+            embedding=[1.0, page / 10],
+            metadata={"page": page},
+        )
+        for page in range(8)
+    ])
+    retriever = SemanticRetriever(kb)
+    
+    results, backend_used, _ = retriever.semantic_search(
+        query_embedding=[1.0, 0.0],
+        top_k=8,
+        score_threshold=None,
+        backend=backend,
+        metadata_filter={
+            "page": {"$in": [1, 2, 3, 5, 6], "$gte": 3, "$lt": 6},
+            "filename": {"$prefix": "doc", "$eq": "doc1.md"},
+        },
+    )
+    assert backend_used.startswith(backend)
+    assert sorted(r["metadata"]["page"] for r in results) == [3, 5]
+
+
+# Test that hybrid search finds exact identifiers
+@pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+@pytest.mark.parametrize("fusion", ["rrf", "weighted"])
+def test_hybrid_search_finds_exact_identifiers(fusion):
+    """A keyword-only match on an error code should surface through fusion."""
+    kb = KnowledgeBase()
+    kb.add_chunks([
+        KnowledgeChunk(content="Networking overview and retries", source="a.md", embedding=[1.0, 0.0]),
+        KnowledgeChunk(content="General connection troubleshooting", source="b.md", embedding=[0.9, 0.1]),
+        KnowledgeChunk(content="ERR_CONN_RESET is raised when the peer drops", source="c.md", embedding=[0.0, 1.0]),
+    ])
+    retriever = SemanticRetriever(kb)
+    
+    semantic, _, _ = retriever.semantic_search([1.0, 0.0], top_k=2, score_threshold=0.5)
+    assert "c.md" not in [r["source"] for r in semantic]
+    
+    results, backend_used, _ = retriever.hybrid_search(
+        "what does ERR_CONN_RESET mean", [1.0, 0.0], top_k=2, score_threshold=0.5, fusion=fusion
+    )
+    assert "c.md" in [r["source"] for r in results]
+    assert backend_used.endswith("+bm25")
+
+
+# Test that removing chunks from the BM25 index leaves it as if they were never added
+@pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+def test_bm25_remove_matches_a_fresh_index():
+    """Removed chunks stop matching and no longer count towards term statistics."""
+    texts = {
+        "a": "ERR_CONN_RESET raised on retry",
+        "b": "retry the connection after ERR_TIMEOUT",
+        "c": "connection pooling and retry limits",
+        "d": "unrelated text",
+    }
+    index = rust_lib.Bm25Index()
+    index.add_many(list(texts), list(texts.values()))
+    index.remove("b")
+    index.add("d", "retry once more")
+    index.remove("a")
+    
+    fresh = rust_lib.Bm25Index()
+    fresh.add_many(["c", "d"], [texts["c"], "retry once more"])
+    assert len(index) == 2 and "a" not in index
+    for query in ("retry", "ERR_CONN_RESET connection", "timeout"):
+        assert index.search(query, 5) == fresh.search(query, 5)
+
+
 # Test that semantic search returns the expected number of results
 @given(
     chunks=knowledge_chunks_with_embeddings(min_chunks=10, max_chunks=20, embedding_dim=10),
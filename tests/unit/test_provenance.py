@@ -0,0 +1,90 @@
+"""
+Tests for the embedding model and dimension recorded by the native indexes.
+"""
+
+import json
+
+import numpy as np
+import pytest
+
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
+pytestmark = pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+
+
+def make_indexes(dim, model):
+    return [
+        rust_lib.EmbeddingIndex(dim, model=model),
+        rust_lib.QuantizedIndex(dim, model=model),
+        rust_lib.HnswIndex(dim, model=model),
+    ]
+
+
+def test_error_types_are_value_errors():
+    assert issubclass(rust_lib.EmbeddingMismatchError, ValueError)
+    assert issubclass(rust_lib.DimensionMismatchError, rust_lib.EmbeddingMismatchError)
+    assert issubclass(rust_lib.ModelMismatchError, rust_lib.EmbeddingMismatchError)
+
+
+@pytest.mark.parametrize("index", make_indexes(8, "model-a"), ids=type)
+def test_indexes_reject_other_models_and_dimensions(index):
+    assert index.model == "model-a" and index.dim == 8
+    vector = np.ones(8, dtype=np.float32)
+    index.add("chunk-0", vector, model="model-a")
+    # Calls that do not name a model are not checked against it
+    index.add("chunk-1", vector)
+
+    with pytest.raises(rust_lib.ModelMismatchError):
+        index.add("chunk-2", vector, model="model-b")
+    with pytest.raises(rust_lib.ModelMismatchError):
+        index.search(vector, 1, model="model-b")
+    with pytest.raises(rust_lib.DimensionMismatchError):
+        index.add("chunk-2", np.ones(4, dtype=np.float32))
+    with pytest.raises(rust_lib.DimensionMismatchError):
+        index.search(np.ones(4, dtype=np.float32), 1)
+    with pytest.raises(rust_lib.DimensionMismatchError):
+        index.add_many(["chunk-2"], np.ones((1, 4), dtype=np.float32))
+    assert len(index) == 2
+
+
+def test_indexes_for_two_models_live_side_by_side(tmp_path):
+    rng = np.random.default_rng(0)
+    ids = [f"chunk-{i}" for i in range(20)]
+    old = rust_lib.HnswIndex(8, model="model-a")
+    new = rust_lib.HnswIndex(16, model="model-b")
+    old.add_many(ids, rng.standard_normal((20, 8)).astype(np.float32), model="model-a")
+    embeddings = rng.standard_normal((20, 16)).astype(np.float32)
+    new.add_many(ids, embeddings, model="model-b")
+    assert new.search(embeddings[3], 1, model="model-b")[0][0] == "chunk-3"
+    assert old.model == "model-a" and len(old) == 20
+
+    # A saved index keeps its model
+    path = tmp_path / "kb.llkb"
+    rust_lib.save_kb(
+        str(path),
+        ids,
+        ["doc.md"] * 20,
+        ["text"] * 20,
+        [json.dumps({})] * 20,
+        embeddings=embeddings,
+        index=new,
+    )
+    assert rust_lib.load_kb(str(path)).index().model == "model-b"
+
+
+def test_store_refuses_an_index_of_another_model(tmp_path):
+    store = rust_lib.KnowledgeStore(str(tmp_path / "kb.db"))
+    store.add_chunks(["chunk-0"], ["doc.md"], ["text"], ["{}"])
+    store.add_embeddings("model-a", ["chunk-0"], np.ones((1, 8), dtype=np.float32))
+
+    with pytest.raises(rust_lib.ModelMismatchError):
+        store.load_index(rust_lib.EmbeddingIndex(8, model="model-b"), "model-a")
+    with pytest.raises(rust_lib.DimensionMismatchError):
+        store.load_index(rust_lib.EmbeddingIndex(4, model="model-a"), "model-a")
+    with pytest.raises(rust_lib.DimensionMismatchError):
+        store.add_embeddings("model-a", ["chunk-0"], np.ones((1, 4), dtype=np.float32))
+    assert store.load_index(rust_lib.EmbeddingIndex(8, model="model-a"), "model-a") == 1
@@ -0,0 +1,99 @@
+"""
+Tests for the SQLite-backed knowledge store.
+"""
+
+import json
+import shutil
+
+import numpy as np
+import pytest
+
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
+from llamasearch_experimentalagents_augmented_professional.agents.agents_retriever import SemanticRetriever
+from llamasearch_experimentalagents_augmented_professional.models.models_knowledge import KnowledgeBase
+
+pytestmark = pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+
+
+@pytest.fixture
+def store(tmp_path):
+    store = rust_lib.KnowledgeStore(str(tmp_path / "kb.db"))
+    ids = [f"chunk-{i}" for i in range(40)]
+    store.add_chunks(
+        ids,
+        [f"docs/doc-{i % 4}.md" for i in range(40)],
+        [f"Text of chunk {i}" for i in range(40)],
+        [json.dumps({"source_type": "markdown", "chunk_index": i}) for i in range(40)],
+    )
+    embeddings = np.random.default_rng(0).standard_normal((40, 8)).astype(np.float32)
+    store.add_embeddings("model-a", ids, embeddings)
+    return store
+
+
+def test_chunks_and_embeddings_round_trip(store, tmp_path):
+    assert len(store) == 40
+    assert store.models() == [("model-a", 8, 40)]
+    ids, embeddings = store.embeddings("model-a")
+    assert embeddings.shape == (40, 8) and embeddings.dtype == np.float32
+
+    # The database is one file that can be copied elsewhere and reopened
+    shutil.copy(tmp_path / "kb.db", tmp_path / "copy.db")
+    copy = rust_lib.KnowledgeStore(str(tmp_path / "copy.db"))
+    assert copy.chunks() == store.chunks()
+    copied_ids, copied = copy.embeddings("model-a")
+    assert copied_ids == ids
+    np.testing.assert_array_equal(copied, embeddings)
+
+
+def test_embeddings_of_some_chunks(store):
+    ids, embeddings = store.embeddings("model-a")
+    picked, rows = store.embeddings("model-a", ["chunk-7", "chunk-2"])
+    assert picked == ["chunk-7", "chunk-2"]
+    np.testing.assert_array_equal(rows, embeddings[[ids.index("chunk-7"), ids.index("chunk-2")]])
+    with pytest.raises(KeyError):
+        store.embeddings("model-a", ["chunk-7", "missing"])
+
+
+def test_models_live_side_by_side(store):
+    store.add_embeddings("model-b", ["chunk-0"], np.ones((1, 4), dtype=np.float32))
+    assert store.models() == [("model-a", 8, 40), ("model-b", 4, 1)]
+    with pytest.raises(ValueError):
+        store.add_embeddings("model-a", ["chunk-0"], np.ones((1, 4), dtype=np.float32))
+    with pytest.raises(KeyError):
+        store.add_embeddings("model-a", ["missing"], np.ones((1, 8), dtype=np.float32))
+
+    # Changed text leaves its embeddings stale, so they are dropped
+    store.add_chunks(["chunk-0"], ["docs/doc-0.md"], ["Rewritten"], ["{}"])
+    assert store.models() == [("model-a", 8, 39), ("model-b", 4, 0)]
+    assert store.remove_chunks(["chunk-1", "missing"]) == 1
+    assert len(store) == 39 and store.models()[0] == ("model-a", 8, 38)
+
+
+def test_indexes_load_from_the_store(store):
+    ids, embeddings = store.embeddings("model-a")
+    flat = rust_lib.EmbeddingIndex(8)
+    assert store.load_index(flat, "model-a") == 40
+    # Rows come grouped by document, so chunk-5 is not the sixth
+    query = embeddings[ids.index("chunk-5")]
+    hits = flat.search(query, 3, filter={"filename": "doc-1.md", "source_type": "markdown"})
+    assert hits[0][0] == "chunk-5" and len(hits) == 3
+
+    hnsw = rust_lib.HnswIndex(8)
+    store.load_index(hnsw, "model-a")
+    assert hnsw.search(embeddings[7], 1)[0][0] == ids[7]
+
+    ivfpq = rust_lib.IvfPqIndex(8, nlist=2, m=2, nbits=4)
+    store.load_index(ivfpq, "model-a")
+    assert ivfpq.trained and len(ivfpq) == 40
+
+    with pytest.raises(ValueError):
+        store.load_index(rust_lib.HnswIndex(4), "model-a")
+    with pytest.raises(KeyError):
+        store.load_index(hnsw, "unknown-model")
+    with pytest.raises(KeyError, match="unknown-model"):
+        SemanticRetriever(KnowledgeBase(), index_type="hnsw").load_index(store, "unknown-model")
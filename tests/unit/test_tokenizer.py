@@ -0,0 +1,127 @@
+"""
+Tests for the native BPE tokenizer and token-budgeted context packing.
+"""
+
+import base64
+import json
+
+import pytest
+
+try:
+    import llamasearch_experimentalagents_rust_lib as rust_lib
+    HAS_RUST = True
+except ImportError:
+    HAS_RUST = False
+
+from llamasearch_experimentalagents_augmented_professional.utils.context_utils import (
+    ApproximateTokenizer,
+    format_source,
+    pack_context,
+)
+
+requires_rust = pytest.mark.skipif(not HAS_RUST, reason="Rust accelerator not built")
+
+# Merged tokens after the 256 single bytes, each made of two earlier ones
+MERGES = [b"he", b"ll", b"hell", b"hello", b" w", b"or", b" wor", b"ld"]
+
+
+@pytest.fixture
+def tiktoken_file(tmp_path):
+    tokens = [bytes([b]) for b in range(256)] + MERGES
+    path = tmp_path / "tiny.tiktoken"
+    path.write_text(
+        "".join(f"{base64.b64encode(token).decode()} {rank}\n" for rank, token in enumerate(tokens))
+    )
+    return path
+
+
+@pytest.fixture
+def huggingface_dir(tmp_path):
+    letters = "thecasonm"
+    vocab = {"[UNK]": 0, **{c: i + 1 for i, c in enumerate(letters)}, "th": 10, "the": 11, "at": 12}
+    tokenizer = {
+        "version": "1.0",
+        "truncation": None,
+        "padding": None,
+        "added_tokens": [],
+        "normalizer": None,
+        "pre_tokenizer": {"type": "Whitespace"},
+        "post_processor": None,
+        "decoder": None,
+        "model": {"type": "BPE", "dropout": None, "unk_token": "[UNK]",
+                  "continuing_subword_prefix": None, "end_of_word_suffix": None,
+                  "fuse_unk": False, "byte_fallback": False,
+                  "vocab": vocab, "merges": ["t h", "th e", "a t"]},
+    }
+    directory = tmp_path / "tiny-model"
+    directory.mkdir()
+    (directory / "tokenizer.json").write_text(json.dumps(tokenizer))
+    return directory
+
+
+@requires_rust
+def test_tiktoken_files_merge_by_rank(tiktoken_file):
+    tokenizer = rust_lib.BpeTokenizer(str(tiktoken_file))
+    assert tokenizer.name == "tiny"
+    assert tokenizer.encode("hello world") == [259, 262, 263]
+    assert tokenizer.decode([259, 262]) == "hello wor"
+    assert tokenizer.count("hello world") == 3
+    assert tokenizer.truncate("hello world", 2) == "hello wor"
+    assert tokenizer.truncate("hello world", 3) == "hello world"
+    # A character split across tokens is not cut in half
+    assert tokenizer.count("é") == 2
+    assert tokenizer.truncate("é", 1) == ""
+
+
+@requires_rust
+def test_huggingface_tokenizer_files_load_from_their_directory(huggingface_dir):
+    tokenizer = rust_lib.BpeTokenizer(str(huggingface_dir))
+    assert tokenizer.name == "tiny-model"
+    assert tokenizer.count("the cat sat") == 5
+    assert tokenizer.truncate("the cat sat", 3) == "the cat"
+    with pytest.raises(ValueError):
+        rust_lib.BpeTokenizer(str(huggingface_dir / "tokenizer.json"), pattern=r"\w+")
+
+
+@requires_rust
+def test_missing_tokenizer_files_are_reported(tmp_path):
+    with pytest.raises(FileNotFoundError):
+        rust_lib.BpeTokenizer(str(tmp_path / "cl100k_base.tiktoken"))
+    (tmp_path / "broken.tiktoken").write_text("not base64\n")
+    with pytest.raises(ValueError):
+        rust_lib.BpeTokenizer(str(tmp_path / "broken.tiktoken"))
+
+
+def sources():
+    return [
+        {"source": "a.md", "content": "x" * 400},
+        {"source": "b.pdf", "content": "y" * 40000, "metadata": {"page": 3}},
+        {"source": "c.md", "content": "z" * 200},
+    ]
+
+
+def test_an_oversized_source_is_cut_to_the_budget():
+    tokenizer = ApproximateTokenizer()
+    results = sources()
+    packed = pack_context(results, 1000)
+    assert [r["source"] for r in packed] == ["a.md", "b.pdf"]
+    assert packed[0] is results[0] and "truncated" not in packed[0]
+    assert packed[1]["truncated"] and results[1]["content"] == "y" * 40000
+    used = sum(tokenizer.count(format_source(i, r)) for i, r in enumerate(packed, 1))
+    assert 990 <= used <= 1000
+
+
+def test_a_source_too_big_to_cut_is_dropped_for_smaller_ones():
+    packed = pack_context(sources(), 160)
+    assert [r["source"] for r in packed] == ["a.md", "c.md"]
+    assert not any(r.get("truncated") for r in packed)
+    assert pack_context(sources(), 10) == []
+
+
+@requires_rust
+def test_packing_counts_with_a_tokenizer_file(tiktoken_file):
+    tokenizer = rust_lib.BpeTokenizer(str(tiktoken_file))
+    results = [{"source": "a.md", "content": "hello world " * 200}]
+    packed = pack_context(results, 120, tokenizer, min_source_tokens=10)
+    assert packed[0]["truncated"]
+    assert tokenizer.count(format_source(1, packed[0])) <= 120